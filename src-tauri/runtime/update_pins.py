@@ -0,0 +1,57 @@
+"""ランタイムの固定情報を更新する（ネットワークが必要）
+
+- manifest.jsonの各URLをダウンロードしてsha256を書き込む
+- requirements.inからWindows x86_64・Python 3.13向けのrequirements.lockを
+  推移的な依存関係とハッシュ付きで生成する（uvが必要）
+
+使い方: python src-tauri/runtime/update_pins.py
+"""
+
+import hashlib
+import json
+import subprocess
+import urllib.request
+from pathlib import Path
+
+RUNTIME_DIR = Path(__file__).resolve().parent
+
+
+def update_manifest() -> None:
+    path = RUNTIME_DIR / "manifest.json"
+    manifest = json.loads(path.read_text(encoding="utf-8"))
+    for name, artifact in manifest.items():
+        digest = hashlib.sha256()
+        with urllib.request.urlopen(artifact["url"]) as response:
+            for chunk in iter(lambda: response.read(1 << 20), b""):
+                digest.update(chunk)
+        artifact["sha256"] = digest.hexdigest()
+        print(f"{name}: {artifact['sha256']}")
+    path.write_text(json.dumps(manifest, indent=2) + "\n", encoding="utf-8")
+
+
+def update_lock() -> None:
+    lock = RUNTIME_DIR / "requirements.lock"
+    header = [
+        "# Demucsランタイムの固定バージョン（Windows x86_64、Python 3.13）",
+        "# requirements.inからupdate_pins.pyで生成する（推移的な依存関係を含め、--hashを付ける）",
+        "# 変更した場合はcheck_demucsが次回起動時に再インストールする",
+    ]
+    compiled = subprocess.run(
+        [
+            "uv", "pip", "compile", str(RUNTIME_DIR / "requirements.in"),
+            "--python-platform", "x86_64-pc-windows-msvc",
+            "--python-version", "3.13",
+            "--generate-hashes",
+            "--no-header",
+            "--no-annotate",
+        ],
+        check=True,
+        capture_output=True,
+        text=True,
+    ).stdout
+    lock.write_text("\n".join(header) + "\n" + compiled, encoding="utf-8", newline="\n")
+
+
+if __name__ == "__main__":
+    update_manifest()
+    update_lock()
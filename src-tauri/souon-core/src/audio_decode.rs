@@ -0,0 +1,157 @@
+use base64::{engine::general_purpose, Engine};
+use std::io::Cursor;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// デコード済みの音声（チャンネルごとのf32サンプル）
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl DecodedAudio {
+    /// 1チャンネルあたりのサンプル数
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, |c| c.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 全チャンネルを平均してモノラル化する
+    pub fn to_mono(&self) -> Vec<f32> {
+        match self.channels.len() {
+            0 => Vec::new(),
+            1 => self.channels[0].clone(),
+            n => (0..self.len())
+                .map(|i| self.channels.iter().map(|c| c[i]).sum::<f32>() / n as f32)
+                .collect(),
+        }
+    }
+
+    /// 32bit floatのWAVファイルとして書き出す
+    pub fn write_wav(&self, path: &std::path::Path) -> Result<(), String> {
+        let spec = hound::WavSpec {
+            channels: self.channels.len().max(1) as u16,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        for i in 0..self.len() {
+            for channel in &self.channels {
+                writer
+                    .write_sample(channel[i])
+                    .map_err(|e| format!("Failed to write WAV sample: {}", e))?;
+            }
+        }
+        writer
+            .finalize()
+            .map_err(|e| format!("Failed to finalize WAV: {}", e))
+    }
+}
+
+/// Data URL（"data:audio/ogg;base64,..."）または素のbase64をデコードする
+///
+/// プレフィックスがない場合はOGGとみなす。
+pub fn decode_data_url(input: &str) -> Result<DecodedAudio, String> {
+    // Data URLのプレフィックスを除去
+    let (mime_type, base64_data) = match input
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+    {
+        Some((mime_type, data)) => (Some(mime_type), data),
+        None => (None, input),
+    };
+
+    let data = general_purpose::STANDARD
+        .decode(base64_data)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+
+    decode_bytes(data, mime_type.or(Some("audio/ogg")))
+}
+
+/// 音声ファイルのバイト列をSymphoniaでデコードする
+pub fn decode_bytes(data: Vec<u8>, mime_type: Option<&str>) -> Result<DecodedAudio, String> {
+    log::info!(
+        "Decoding {} bytes of audio ({})",
+        data.len(),
+        mime_type.unwrap_or("unknown")
+    );
+
+    let cursor = Cursor::new(data);
+    let media_source = MediaSourceStream::new(Box::new(cursor), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(mime_type) = mime_type {
+        hint.mime_type(mime_type);
+    }
+
+    let meta_opts: MetadataOptions = Default::default();
+    let fmt_opts: FormatOptions = Default::default();
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, media_source, &fmt_opts, &meta_opts)
+        .map_err(|e| format!("Failed to probe format: {}", e))?;
+
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("No audio track found")?;
+
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions { verify: false })
+        .map_err(|e| format!("Failed to create decoder: {}", e))?;
+
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+
+    // すべてのオーディオデータをデコード
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let audio_buf = decoder
+            .decode(&packet)
+            .map_err(|e| format!("Decode error: {}", e))?;
+
+        let frames = audio_buf.frames();
+        let channel_count = audio_buf.spec().channels.count();
+        if channels.is_empty() {
+            channels = vec![Vec::new(); channel_count];
+        }
+
+        // f32に変換してチャンネルごとに追加
+        let mut sample_buf =
+            SampleBuffer::<f32>::new(audio_buf.capacity() as u64, *audio_buf.spec());
+        sample_buf.copy_planar_ref(audio_buf);
+        let samples = sample_buf.samples();
+        for (ch, channel) in channels.iter_mut().enumerate().take(channel_count) {
+            channel.extend_from_slice(&samples[ch * frames..(ch + 1) * frames]);
+        }
+    }
+
+    log::info!(
+        "Decoded {} channel(s), {} samples per channel at {} Hz",
+        channels.len(),
+        channels.first().map_or(0, |c| c.len()),
+        sample_rate
+    );
+
+    Ok(DecodedAudio {
+        sample_rate,
+        channels,
+    })
+}
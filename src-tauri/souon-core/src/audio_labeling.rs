@@ -0,0 +1,460 @@
+use crate::audio_decode::DecodedAudio;
+use aubio_rs::{Notes, Onset, OnsetMode, Tempo};
+use serde::{Deserialize, Serialize};
+
+// 改善された定数の定義
+const BUF_SIZE: usize = 1024; // より大きなバッファサイズで精度向上
+const HOP_SIZE: usize = 512; // バッファサイズの半分に設定
+const SILENCE_THRESHOLD: f32 = -50.0; // dBでの無音閾値
+const MIN_INTER_ONSET_INTERVAL: f64 = 0.05; // 最小オンセット間隔（50ms）
+
+/// ドラムの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrumKind {
+    Kick,
+    Snare,
+    HiHat,
+    Tom,
+    Cymbal,
+}
+
+/// 種類付きのドラムのオンセット（stemNotes.drumsの1要素）
+#[derive(Debug, Clone, Serialize)]
+pub struct DrumHit {
+    pub pitch: f64,
+    pub velocity: f64,
+    pub time: f64,
+    pub kind: DrumKind,
+}
+
+/// Data URLの音声からドラムのオンセットを検出し、種類を分類する
+pub fn drum_hits(input_base64_audio: &str) -> Result<Vec<DrumHit>, String> {
+    log::info!("Running drum hit classification");
+
+    let decoded = crate::audio_decode::decode_data_url(input_base64_audio)?;
+    let hits = classify_drum_hits(&decoded)?;
+    log::info!("Classified {} drum hits", hits.len());
+    Ok(hits)
+}
+
+/// ドラムのオンセットを検出して種類を分類する
+///
+/// オンセットも特徴量と同じモノラルのミックスで検出する（片側に振った音も拾う）。
+fn classify_drum_hits(decoded: &DecodedAudio) -> Result<Vec<DrumHit>, String> {
+    let sample_rate = decoded.sample_rate;
+    let mono = decoded.to_mono();
+    let notes = detect_notes(mono.clone(), sample_rate)?;
+
+    let mut classifier = DrumClassifier::new(sample_rate);
+    let hits: Vec<DrumHit> = notes
+        .into_iter()
+        .map(|[pitch, velocity, time]| DrumHit {
+            pitch,
+            velocity,
+            time,
+            kind: classifier.classify(&mono, time),
+        })
+        .collect();
+    Ok(hits)
+}
+
+/// Data URLの音声からノートとオンセットを検出し、[pitch, velocity, time]の列を返す
+pub fn onset(input_base64_audio: &str) -> Result<Vec<[f64; 3]>, String> {
+    log::info!("Running improved onset detection with input base64 audio data");
+
+    // 音声をデコード（全チャンネルをモノラルにまとめる）
+    let decoded = crate::audio_decode::decode_data_url(input_base64_audio)?;
+    detect_notes(decoded.to_mono(), decoded.sample_rate)
+}
+
+/// aubioでノートとオンセットを検出し、[pitch, velocity, time]の列を返す
+fn detect_notes(mut audio_samples: Vec<f32>, sample_rate: u32) -> Result<Vec<[f64; 3]>, String> {
+    log::info!("Extracted audio samples: {}", audio_samples.len());
+
+    log::info!("Creating improved Notes analyzer...");
+    let mut notes = match Notes::new(BUF_SIZE, HOP_SIZE, sample_rate) {
+        Ok(notes) => {
+            log::info!("Notes analyzer created successfully");
+            notes
+        }
+        Err(e) => {
+            log::error!("Failed to create Notes: {:?}", e);
+            return Err(format!("Failed to create Notes: {:?}", e));
+        }
+    };
+
+    // オンセット検出器も追加で使用
+    log::info!("Creating Onset detector...");
+    let mut onset_detector = match Onset::new(OnsetMode::Complex, BUF_SIZE, HOP_SIZE, sample_rate) {
+        Ok(detector) => {
+            log::info!("Onset detector created successfully");
+            Some(detector)
+        }
+        Err(e) => {
+            log::warn!("Failed to create Onset detector: {:?}", e);
+            None
+        }
+    };
+
+    // テンポ検出器も追加
+    log::info!("Creating Tempo detector...");
+    let _tempo_detector = match Tempo::new(OnsetMode::Complex, BUF_SIZE, HOP_SIZE, sample_rate) {
+        Ok(detector) => {
+            log::info!("Tempo detector created successfully");
+            Some(detector)
+        }
+        Err(e) => {
+            log::warn!("Failed to create Tempo detector: {:?}", e);
+            None
+        }
+    };
+
+    // 音声の前処理：正規化
+    normalize_audio(&mut audio_samples);
+
+    // ローパスフィルタを適用してノイズを除去
+    apply_lowpass_filter(&mut audio_samples, sample_rate as f32);
+
+    let mut results_f64: Vec<[f64; 3]> = Vec::new();
+    let mut onset_times: Vec<f64> = Vec::new();
+
+    // HOP_SIZEずつ処理
+    let mut sample_index = 0;
+    while sample_index + BUF_SIZE <= audio_samples.len() {
+        let block = &audio_samples[sample_index..sample_index + BUF_SIZE];
+
+        // 無音部分をスキップ
+        if is_silence(block) {
+            sample_index += HOP_SIZE;
+            continue;
+        }
+
+        let time = sample_index as f64 / sample_rate as f64;
+
+        // Notes検出
+        let note_results = notes
+            .do_result(block)
+            .map_err(|e| format!("Notes processing error: {:?}", e))?;
+
+        // オンセット検出（利用可能な場合のみ）
+        let onset_detected = if let Some(ref mut detector) = onset_detector {
+            match detector.do_result(block) {
+                Ok(result) => result > 0.0,
+                Err(_) => false,
+            }
+        } else {
+            false
+        };
+
+        // オンセットが検出された場合
+        if onset_detected {
+            // 最小間隔チェック
+            if onset_times.is_empty()
+                || time - onset_times.last().unwrap() > MIN_INTER_ONSET_INTERVAL
+            {
+                onset_times.push(time);
+
+                // このタイミングでのノート情報を優先的に記録
+                for note in note_results {
+                    if note.velocity > 0.3 {
+                        // 閾値を設定して弱いノートを除外
+                        results_f64.push([note.pitch as f64, note.velocity as f64, time]);
+                    }
+                }
+            }
+        } else {
+            // オンセットがない場合でも、強いノートは記録
+            for note in note_results {
+                if note.velocity > 0.5 {
+                    // より高い閾値
+                    results_f64.push([note.pitch as f64, note.velocity as f64, time]);
+                }
+            }
+        }
+
+        sample_index += HOP_SIZE;
+    }
+
+    // 結果を時間順にソート
+    results_f64.sort_by(|a, b| a[2].partial_cmp(&b[2]).unwrap());
+
+    // 重複する近いタイミングのノートを統合
+    let results_f64 = merge_close_notes(results_f64);
+
+    log::info!("Detected {} notes/onsets", results_f64.len());
+    Ok(results_f64)
+}
+
+// 音声正規化関数
+fn normalize_audio(samples: &mut [f32]) {
+    let max_amplitude = samples.iter().map(|&s| s.abs()).fold(0.0f32, f32::max);
+    if max_amplitude > 0.0 {
+        let scale = 0.95 / max_amplitude;
+        for sample in samples.iter_mut() {
+            *sample *= scale;
+        }
+    }
+}
+
+// 簡単なローパスフィルタ
+fn apply_lowpass_filter(samples: &mut [f32], sample_rate: f32) {
+    let cutoff = 8000.0; // 8kHzでカットオフ
+    let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+    let dt = 1.0 / sample_rate;
+    let alpha = dt / (rc + dt);
+
+    if samples.len() > 1 {
+        for i in 1..samples.len() {
+            samples[i] = samples[i - 1] + alpha * (samples[i] - samples[i - 1]);
+        }
+    }
+}
+
+// 無音判定関数
+pub(crate) fn is_silence(block: &[f32]) -> bool {
+    let rms = (block.iter().map(|&s| s * s).sum::<f32>() / block.len() as f32).sqrt();
+    let db = 20.0 * rms.log10();
+    db < SILENCE_THRESHOLD
+}
+
+// 近いタイミングのノートを統合
+fn merge_close_notes(notes: Vec<[f64; 3]>) -> Vec<[f64; 3]> {
+    if notes.is_empty() {
+        return notes;
+    }
+
+    let mut merged: Vec<[f64; 3]> = Vec::new();
+    let mut current = notes[0];
+
+    for note in notes.into_iter().skip(1) {
+        // 時間差が50ms以内で、ピッチが近い場合は統合
+        if (note[2] - current[2]).abs() < 0.05 && (note[0] - current[0]).abs() < 2.0 {
+            // より強いベロシティを採用
+            if note[1] > current[1] {
+                current = note;
+            }
+        } else {
+            merged.push(current);
+            current = note;
+        }
+    }
+    merged.push(current);
+
+    merged
+}
+
+// ドラム分類に使う定数
+const DRUM_FFT_SIZE: usize = 2048; // アタック部分のスペクトル（約46ms）
+const DRUM_ENVELOPE_FRAME: f64 = 0.01; // 減衰計測のフレーム長（10ms）
+const DRUM_ENVELOPE_LENGTH: f64 = 0.5; // 減衰を計測する長さ（500ms）
+const DRUM_DECAY_DB: f32 = 20.0; // ピークからこのdB下がるまでを減衰時間とする
+const DRUM_BANDS_HZ: [f32; 4] = [150.0, 400.0, 2000.0, 6000.0]; // 帯域の境界
+
+/// 帯域エネルギーと減衰時間によるドラムの分類器
+struct DrumClassifier {
+    sample_rate: u32,
+    fft: std::sync::Arc<dyn realfft::RealToComplex<f32>>,
+    window: Vec<f32>,
+}
+
+/// ドラム1打分の特徴量
+#[derive(Debug)]
+struct DrumFeatures {
+    low: f32,     // 〜150Hz（キックの胴鳴り）
+    low_mid: f32, // 150〜400Hz（タム・スネアの胴鳴り）
+    // 400〜2kHzは合計にのみ含める
+    high_mid: f32, // 2k〜6kHz（スネアのスナッピー）
+    high: f32,     // 6kHz〜（ハイハット・シンバル）
+    decay: f64,    // 減衰時間（秒）
+}
+
+impl DrumClassifier {
+    fn new(sample_rate: u32) -> Self {
+        let mut planner = realfft::RealFftPlanner::<f32>::new();
+        let window = (0..DRUM_FFT_SIZE)
+            .map(|n| {
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / DRUM_FFT_SIZE as f32).cos()
+            })
+            .collect();
+        Self {
+            sample_rate,
+            fft: planner.plan_fft_forward(DRUM_FFT_SIZE),
+            window,
+        }
+    }
+
+    fn classify(&mut self, samples: &[f32], time: f64) -> DrumKind {
+        let features = self.features(samples, time);
+        let hf = features.high + features.high_mid;
+        let lf = features.low + features.low_mid;
+
+        if hf > 0.6 && lf < 0.15 {
+            // 高域が支配的：長く響けばシンバル、短ければハイハット
+            if features.decay > 0.25 {
+                DrumKind::Cymbal
+            } else {
+                DrumKind::HiHat
+            }
+        } else if features.low > 0.45 && features.low > features.low_mid && hf < 0.2 {
+            DrumKind::Kick
+        } else if lf > 0.5 && hf < 0.2 && features.decay > 0.15 {
+            // 低中域の胴鳴りが長く続くものはタム
+            DrumKind::Tom
+        } else if features.low > 0.35 && features.low > features.low_mid {
+            DrumKind::Kick
+        } else if hf > 0.5 && features.decay > 0.3 {
+            DrumKind::Cymbal
+        } else {
+            DrumKind::Snare
+        }
+    }
+
+    fn features(&mut self, samples: &[f32], time: f64) -> DrumFeatures {
+        let start = ((time * self.sample_rate as f64) as usize).min(samples.len());
+
+        // アタック部分の帯域エネルギー
+        let mut input = self.fft.make_input_vec();
+        for (n, value) in input.iter_mut().enumerate() {
+            *value = samples.get(start + n).copied().unwrap_or(0.0) * self.window[n];
+        }
+        let mut spectrum = self.fft.make_output_vec();
+        let mut bands = [0.0f32; 5];
+        if self.fft.process(&mut input, &mut spectrum).is_ok() {
+            let bin_hz = self.sample_rate as f32 / DRUM_FFT_SIZE as f32;
+            for (k, value) in spectrum.iter().enumerate().skip(1) {
+                let freq = k as f32 * bin_hz;
+                let band = DRUM_BANDS_HZ
+                    .iter()
+                    .position(|&edge| freq < edge)
+                    .unwrap_or(DRUM_BANDS_HZ.len());
+                bands[band] += value.norm_sqr();
+            }
+        }
+        let total = bands.iter().sum::<f32>().max(f32::EPSILON);
+
+        DrumFeatures {
+            low: bands[0] / total,
+            low_mid: bands[1] / total,
+            high_mid: bands[3] / total,
+            high: bands[4] / total,
+            decay: self.decay_time(samples, start),
+        }
+    }
+
+    /// RMSエンベロープがピークからDRUM_DECAY_DB下がるまでの時間
+    fn decay_time(&self, samples: &[f32], start: usize) -> f64 {
+        let frame = ((DRUM_ENVELOPE_FRAME * self.sample_rate as f64) as usize).max(1);
+        let frames = (DRUM_ENVELOPE_LENGTH / DRUM_ENVELOPE_FRAME) as usize;
+
+        let envelope: Vec<f32> = (0..frames)
+            .map_while(|i| {
+                let from = start + i * frame;
+                let block = samples.get(from..(from + frame).min(samples.len()))?;
+                if block.is_empty() {
+                    return None;
+                }
+                Some((block.iter().map(|&s| s * s).sum::<f32>() / block.len() as f32).sqrt())
+            })
+            .collect();
+
+        let Some((peak_index, &peak)) = envelope
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+        else {
+            return 0.0;
+        };
+        let threshold = peak * 10f32.powf(-DRUM_DECAY_DB / 20.0);
+        let end = envelope[peak_index..]
+            .iter()
+            .position(|&v| v < threshold)
+            .map_or(envelope.len(), |i| peak_index + i);
+
+        end as f64 * DRUM_ENVELOPE_FRAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// 指数的に減衰するバースト（timeからdecay秒で1/eになる）
+    fn burst(length: f64, time: f64, decay: f64, source: impl Fn(f64) -> f32) -> Vec<f32> {
+        (0..(length * SAMPLE_RATE as f64) as usize)
+            .map(|i| {
+                let t = i as f64 / SAMPLE_RATE as f64 - time;
+                if t < 0.0 {
+                    0.0
+                } else {
+                    source(t) * (-t / decay).exp() as f32
+                }
+            })
+            .collect()
+    }
+
+    /// 60Hzの減衰するサイン波（キック）
+    fn kick(length: f64, time: f64) -> Vec<f32> {
+        burst(length, time, 0.08, |t| {
+            0.9 * (2.0 * std::f64::consts::PI * 60.0 * t).sin() as f32
+        })
+    }
+
+    /// 短く減衰するホワイトノイズ（線形合同法の疑似乱数）
+    fn noise(length: f64, time: f64) -> Vec<f32> {
+        let seed = std::cell::Cell::new(12345u32);
+        burst(length, time, 0.015, |_| {
+            seed.set(seed.get().wrapping_mul(1_103_515_245).wrapping_add(12345));
+            (seed.get() >> 8) as f32 / (1u32 << 24) as f32 * 1.6 - 0.8
+        })
+    }
+
+    #[test]
+    fn classifies_low_decaying_burst_as_kick() {
+        let samples = kick(0.5, 0.0);
+        let mut classifier = DrumClassifier::new(SAMPLE_RATE);
+
+        let features = classifier.features(&samples, 0.0);
+        assert!(features.low > 0.9, "{:?}", features);
+        assert_eq!(classifier.classify(&samples, 0.0), DrumKind::Kick);
+    }
+
+    #[test]
+    fn classifies_short_noise_burst_as_hi_hat() {
+        let samples = noise(0.5, 0.0);
+        let mut classifier = DrumClassifier::new(SAMPLE_RATE);
+
+        let features = classifier.features(&samples, 0.0);
+        assert!(features.high > 0.6, "{:?}", features);
+        assert!(features.decay < 0.1, "{:?}", features);
+        assert_eq!(classifier.classify(&samples, 0.0), DrumKind::HiHat);
+    }
+
+    #[test]
+    fn detects_hits_panned_to_the_right_channel() {
+        // 左は無音、右だけに440Hzの減衰音が2回
+        let tone = |time| {
+            burst(1.0, time, 0.08, |t| {
+                0.9 * (2.0 * std::f64::consts::PI * 440.0 * t).sin() as f32
+            })
+        };
+        let right: Vec<f32> = tone(0.2)
+            .into_iter()
+            .zip(tone(0.7))
+            .map(|(a, b)| a + b)
+            .collect();
+        let audio = DecodedAudio {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![vec![0.0; right.len()], right],
+        };
+
+        let hits = classify_drum_hits(&audio).unwrap();
+        let times: Vec<f64> = hits.iter().map(|hit| hit.time).collect();
+        assert_eq!(times.len(), 2, "{:?}", times);
+        // aubioのノート検出は数ブロック遅れて報告する
+        assert!((times[0] - 0.2).abs() < 0.1, "{:?}", times);
+        assert!((times[1] - 0.7).abs() < 0.1, "{:?}", times);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::tempo::{self, TempoEvent, TempoMap};
+use realfft::RealFftPlanner;
+use serde::Serialize;
+
+// クロマ計算の設定
+const FFT_SIZE: usize = 8192; // 低音域の分解能を確保する
+const HOP_SIZE: usize = 2048;
+const OTHER_RANGE_HZ: (f32, f32) = (55.0, 5000.0);
+const BASS_RANGE_HZ: (f32, f32) = (30.0, 400.0);
+const BASS_WEIGHT: f32 = 0.5; // コード判定でbassクロマを混ぜる割合
+const BASS_ROOT_BONUS: f32 = 0.1; // bassの最大音がルートと一致した場合の加点
+const SILENT_BAR_RATIO: f32 = 0.05; // 平均の5%未満のエネルギーの小節はコードなし
+
+const PITCH_CLASSES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// Krumhansl-Kesslerの調性プロファイル
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+type Chroma = [f32; 12];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// 曲全体の調
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyEstimate {
+    pub tonic: String,
+    pub mode: Mode,
+    /// 表示用の名前（例: "A minor"）
+    pub name: String,
+    /// プロファイルとの相関係数（-1〜1）
+    pub confidence: f32,
+}
+
+/// 小節ごとのコード
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarChord {
+    /// 小節番号（1始まり）
+    pub bar: usize,
+    pub start_ns: u64,
+    /// ルート音（コードなしの場合はnull）
+    pub root: Option<String>,
+    pub mode: Option<Mode>,
+    /// 表示用の名前（例: "Am"、コードなしは"N"）
+    pub name: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HarmonyAnalysis {
+    pub key: KeyEstimate,
+    pub chords: Vec<BarChord>,
+}
+
+/// otherとbassのステム（Data URL）から調と小節ごとのコードを推定する
+///
+/// bassが空の場合はotherのみで推定する。
+pub fn detect_harmony(
+    music_tempo_list: Vec<TempoEvent>,
+    other: &str,
+    bass: &str,
+) -> Result<HarmonyAnalysis, String> {
+    let tempo_map = TempoMap::new(music_tempo_list);
+    if tempo_map.is_empty() {
+        return Err("musicTempoList is empty".to_string());
+    }
+    if other.is_empty() {
+        return Err("The other stem is empty".to_string());
+    }
+
+    let other = crate::audio_decode::decode_data_url(other)?;
+    let other = TimedChroma::compute(&other.to_mono(), other.sample_rate, OTHER_RANGE_HZ);
+    let bass = if bass.is_empty() {
+        None
+    } else {
+        let bass = crate::audio_decode::decode_data_url(bass)?;
+        Some(TimedChroma::compute(
+            &bass.to_mono(),
+            bass.sample_rate,
+            BASS_RANGE_HZ,
+        ))
+    };
+    Ok(analyze_harmony(&other, bass.as_ref(), &tempo_map))
+}
+
+/// 時刻付きのクロマ列
+pub struct TimedChroma {
+    frames: Vec<Chroma>,
+    frame_seconds: f64,
+    duration_seconds: f64,
+}
+
+impl TimedChroma {
+    /// 指定帯域のスペクトルを12音に畳み込んだクロマ列を求める
+    pub fn compute(samples: &[f32], sample_rate: u32, range_hz: (f32, f32)) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / FFT_SIZE as f32).cos())
+            .collect();
+
+        // 各ビンのピッチクラス（帯域外はNone）
+        let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+        let bin_classes: Vec<Option<usize>> = (0..FFT_SIZE / 2 + 1)
+            .map(|k| {
+                let freq = k as f32 * bin_hz;
+                if freq < range_hz.0 || freq > range_hz.1 {
+                    return None;
+                }
+                let midi = (69.0 + 12.0 * (freq / 440.0).log2()).round() as i32;
+                Some(midi.rem_euclid(12) as usize)
+            })
+            .collect();
+
+        let mut input = fft.make_input_vec();
+        let mut spectrum = fft.make_output_vec();
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while start < samples.len() {
+            for (n, value) in input.iter_mut().enumerate() {
+                *value = samples.get(start + n).copied().unwrap_or(0.0) * window[n];
+            }
+            let mut chroma = [0.0f32; 12];
+            if fft.process(&mut input, &mut spectrum).is_ok() {
+                for (value, class) in spectrum.iter().zip(&bin_classes) {
+                    if let Some(class) = class {
+                        chroma[*class] += value.norm();
+                    }
+                }
+            }
+            frames.push(chroma);
+            start += HOP_SIZE;
+        }
+
+        Self {
+            frames,
+            frame_seconds: HOP_SIZE as f64 / sample_rate as f64,
+            duration_seconds: samples.len() as f64 / sample_rate as f64,
+        }
+    }
+
+    /// 区間内のフレームを合計する（フレームの中心時刻で判定）
+    fn sum_between(&self, start_seconds: f64, end_seconds: f64) -> Chroma {
+        let center_offset = FFT_SIZE as f64 / 2.0 / HOP_SIZE as f64;
+        let mut sum = [0.0f32; 12];
+        for (i, chroma) in self.frames.iter().enumerate() {
+            let center = (i as f64 + center_offset) * self.frame_seconds;
+            if center >= start_seconds && center < end_seconds {
+                for (s, c) in sum.iter_mut().zip(chroma) {
+                    *s += c;
+                }
+            }
+        }
+        sum
+    }
+
+    fn total(&self) -> Chroma {
+        let mut sum = [0.0f32; 12];
+        for chroma in &self.frames {
+            for (s, c) in sum.iter_mut().zip(chroma) {
+                *s += c;
+            }
+        }
+        sum
+    }
+}
+
+/// 調と小節ごとのコードを推定する
+pub fn analyze_harmony(
+    other: &TimedChroma,
+    bass: Option<&TimedChroma>,
+    tempo_map: &TempoMap,
+) -> HarmonyAnalysis {
+    let mut total = other.total();
+    if let Some(bass) = bass {
+        add_scaled(&mut total, &bass.total(), BASS_WEIGHT);
+    }
+    let key = estimate_key(&total);
+
+    let duration_ns = tempo::seconds_to_ns(other.duration_seconds);
+    let bars: Vec<_> = tempo_map
+        .bars_until(duration_ns)
+        .into_iter()
+        .filter(|bar| bar.start_ns < duration_ns)
+        .collect();
+
+    // 小節ごとのクロマ
+    let bar_chromas: Vec<(Chroma, Option<Chroma>)> = bars
+        .iter()
+        .map(|bar| {
+            let start = tempo::ns_to_seconds(bar.start_ns);
+            let end = tempo::ns_to_seconds(bar.end_ns());
+            (
+                other.sum_between(start, end),
+                bass.map(|b| b.sum_between(start, end)),
+            )
+        })
+        .collect();
+
+    let energies: Vec<f32> = bar_chromas
+        .iter()
+        .map(|(o, b)| o.iter().sum::<f32>() + b.map_or(0.0, |b| b.iter().sum::<f32>()))
+        .collect();
+    let mean_energy = energies.iter().sum::<f32>() / energies.len().max(1) as f32;
+
+    let chords = bars
+        .iter()
+        .zip(&bar_chromas)
+        .zip(&energies)
+        .map(|((bar, (other, bass)), &energy)| {
+            if energy < mean_energy * SILENT_BAR_RATIO {
+                return BarChord {
+                    bar: bar.index + 1,
+                    start_ns: bar.start_ns,
+                    root: None,
+                    mode: None,
+                    name: "N".to_string(),
+                    confidence: 0.0,
+                };
+            }
+            let mut chroma = *other;
+            if let Some(bass) = bass {
+                add_scaled(&mut chroma, bass, BASS_WEIGHT);
+            }
+            let bass_root = bass.as_ref().and_then(argmax);
+            let (root, mode, confidence) = estimate_chord(&chroma, bass_root);
+            BarChord {
+                bar: bar.index + 1,
+                start_ns: bar.start_ns,
+                root: Some(PITCH_CLASSES[root].to_string()),
+                mode: Some(mode),
+                name: chord_name(root, mode),
+                confidence,
+            }
+        })
+        .collect();
+
+    HarmonyAnalysis { key, chords }
+}
+
+/// Krumhansl-Schmucklerの方法で調を推定する
+fn estimate_key(chroma: &Chroma) -> KeyEstimate {
+    let mut best = (0, Mode::Major, f32::MIN);
+    for tonic in 0..12 {
+        for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+            let rotated: Chroma = std::array::from_fn(|i| profile[(i + 12 - tonic) % 12]);
+            let score = correlation(chroma, &rotated);
+            if score > best.2 {
+                best = (tonic, mode, score);
+            }
+        }
+    }
+    let (tonic, mode, confidence) = best;
+    KeyEstimate {
+        tonic: PITCH_CLASSES[tonic].to_string(),
+        mode,
+        name: format!(
+            "{} {}",
+            PITCH_CLASSES[tonic],
+            match mode {
+                Mode::Major => "major",
+                Mode::Minor => "minor",
+            }
+        ),
+        confidence,
+    }
+}
+
+/// 長三和音・短三和音のテンプレートとのコサイン類似度でコードを推定する
+fn estimate_chord(chroma: &Chroma, bass_root: Option<usize>) -> (usize, Mode, f32) {
+    // (ルート, 長短, 加点込みのスコア, 類似度)
+    let mut best = (0, Mode::Major, f32::MIN, 0.0);
+    for root in 0..12 {
+        for (mode, third) in [(Mode::Major, 4), (Mode::Minor, 3)] {
+            let mut template = [0.0f32; 12];
+            for interval in [0, third, 7] {
+                template[(root + interval) % 12] = 1.0;
+            }
+            let similarity = cosine(chroma, &template);
+            let score = if bass_root == Some(root) {
+                similarity + BASS_ROOT_BONUS
+            } else {
+                similarity
+            };
+            if score > best.2 {
+                best = (root, mode, score, similarity);
+            }
+        }
+    }
+    (best.0, best.1, best.3)
+}
+
+fn chord_name(root: usize, mode: Mode) -> String {
+    match mode {
+        Mode::Major => PITCH_CLASSES[root].to_string(),
+        Mode::Minor => format!("{}m", PITCH_CLASSES[root]),
+    }
+}
+
+fn add_scaled(target: &mut Chroma, source: &Chroma, scale: f32) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t += s * scale;
+    }
+}
+
+fn argmax(chroma: &Chroma) -> Option<usize> {
+    chroma
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v > 0.0)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+}
+
+fn cosine(a: &Chroma, b: &Chroma) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+fn correlation(a: &Chroma, b: &Chroma) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        0.0
+    } else {
+        cov / (var_a * var_b).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 22050;
+
+    /// 120BPM・4/4（1小節2秒）
+    fn tempo_map() -> TempoMap {
+        TempoMap::new(vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 2.0,
+        }])
+    }
+
+    /// MIDIノート番号の和音（サイン波の和）
+    fn chord(midis: &[f32], seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                midis
+                    .iter()
+                    .map(|midi| {
+                        let hz = 440.0 * 2f32.powf((midi - 69.0) / 12.0);
+                        0.2 * (2.0 * std::f32::consts::PI * hz * t).sin()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn analyze(samples: &[f32]) -> HarmonyAnalysis {
+        let other = TimedChroma::compute(samples, SAMPLE_RATE, OTHER_RANGE_HZ);
+        analyze_harmony(&other, None, &tempo_map())
+    }
+
+    #[test]
+    fn c_major_triad() {
+        // C4・E4・G4
+        let analysis = analyze(&chord(&[60.0, 64.0, 67.0], 4.0));
+
+        let names: Vec<&str> = analysis.chords.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["C", "C"]);
+        assert!(analysis.chords[0].confidence > 0.9);
+        assert_eq!(analysis.key.name, "C major");
+    }
+
+    #[test]
+    fn a_minor_triad() {
+        // A3・C4・E4
+        let analysis = analyze(&chord(&[57.0, 60.0, 64.0], 4.0));
+
+        let names: Vec<&str> = analysis.chords.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Am", "Am"]);
+        assert_eq!(analysis.key.tonic, "A");
+        assert_eq!(analysis.key.mode, Mode::Minor);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::audio_decode::DecodedAudio;
+use crate::stem::{self, StemCodec, StemJob};
+use base64::{engine::general_purpose, Engine as _};
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+use std::collections::VecDeque;
+use std::io::{Seek, Write};
+use tokio::sync::watch;
+
+// STFTとメディアンフィルタの設定
+const FFT_SIZE: usize = 2048;
+const HOP_SIZE: usize = 512; // FFT_SIZEの1/4（75%オーバーラップ）
+const HARMONIC_KERNEL: usize = 17; // 時間方向のメディアンフィルタ長（フレーム）
+const PERCUSSIVE_KERNEL: usize = 17; // 周波数方向のメディアンフィルタ長（ビン）
+const BASS_CUTOFF_HZ: f32 = 200.0; // これ未満の調波成分をbassとみなす
+const VOCAL_BAND_HZ: (f32, f32) = (200.0, 8000.0); // ボーカル推定に使う帯域
+const MASK_EPSILON: f32 = 1e-10;
+const CANCEL_CHECK_INTERVAL: usize = 256; // キャンセル確認間隔（フレーム）
+
+/// 出力するステム（Demucsと同じ順番）
+pub const STEM_NAMES: [&str; 4] = ["bass", "drums", "other", "vocals"];
+const VOCALS: usize = 3;
+
+/// Python/Demucsを使わずにHPSSでステムを生成する
+///
+/// 戻り値はDemucsと同じくbass, drums, other, vocalsのData URLを"\n"で結合したもの。
+/// ボーカル推定を行わない場合（またはモノラル入力の場合）、vocalsは空文字列になる。
+/// 実行枠が空くまで待ち、cancel_rxがtrueになったら中断する（分離処理の終了を待ってから返す）。
+pub async fn run_hpss_job(
+    job: &StemJob<'_>,
+    input_base64: String,
+    mime_type: String,
+    codec: StemCodec,
+    vocals: bool,
+    mut cancel_rx: watch::Receiver<bool>,
+) -> Result<String, String> {
+    // 実行枠が空くまで待機
+    let _permit = job.acquire(&mut cancel_rx).await?;
+
+    log::info!("Running HPSS with MIME type: {}", mime_type);
+
+    // 入力のbase64をデコード
+    let input_data = general_purpose::STANDARD
+        .decode(&input_base64)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+
+    let job_dir = job.dir.clone();
+    let stems = tokio::task::spawn_blocking(move || {
+        let audio = crate::audio_decode::decode_bytes(input_data, Some(&mime_type))?;
+
+        std::fs::create_dir_all(&job_dir)
+            .map_err(|e| format!("Failed to create stem job directory: {}", e))?;
+
+        let paths = separate_to_files(&audio, &job_dir, vocals, || *cancel_rx.borrow())?;
+
+        // 指定形式に変換しData URLにする
+        paths
+            .iter()
+            .map(|path| match path {
+                Some(path) => stem::encode_stem(path, codec),
+                None => Ok(String::new()),
+            })
+            .collect::<Result<Vec<String>, String>>()
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))??;
+
+    log::info!("HPSS done.");
+
+    Ok(stems.join("\n"))
+}
+
+/// HPSSを行い、各ステムをWAVファイルとして書き出す
+///
+/// 書き出さなかったステム（ボーカル推定なし）はNoneになる。
+pub fn separate_to_files(
+    audio: &DecodedAudio,
+    output_dir: &std::path::Path,
+    vocals: bool,
+    is_cancelled: impl Fn() -> bool,
+) -> Result<[Option<std::path::PathBuf>; 4], String> {
+    let vocals = vocals && audio.channels.len() == 2;
+    let spec = hound::WavSpec {
+        channels: audio.channels.len() as u16,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let mut paths: [Option<std::path::PathBuf>; 4] = Default::default();
+    let mut writers = Vec::new();
+    for (i, name) in STEM_NAMES.iter().enumerate() {
+        if i == VOCALS && !vocals {
+            writers.push(None);
+            continue;
+        }
+        let path = output_dir.join(format!("{}.wav", name));
+        let writer = hound::WavWriter::create(&path, spec)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        writers.push(Some(writer));
+        paths[i] = Some(path);
+    }
+
+    separate(audio, vocals, &mut writers, is_cancelled)?;
+
+    for writer in writers.into_iter().flatten() {
+        writer
+            .finalize()
+            .map_err(|e| format!("Failed to finalize WAV: {}", e))?;
+    }
+
+    Ok(paths)
+}
+
+/// STFT 1フレーム分
+struct Frame {
+    spectra: Vec<Vec<Complex<f32>>>, // チャンネルごとのスペクトル
+    magnitude: Vec<f32>,             // 全チャンネル平均（ミッド）の振幅
+}
+
+/// メディアンフィルタによる調波/打楽器音分離
+///
+/// 調波成分は時間方向、打楽器成分は周波数方向のメディアンでそれぞれ強調し、
+/// ソフトマスクとして元のスペクトルに掛けて逆変換する。
+/// 調波成分は帯域でbass/otherに分け、ステレオ入力では中央定位の度合いから
+/// vocalsを推定する。
+pub fn separate<W: Write + Seek>(
+    audio: &DecodedAudio,
+    vocals: bool,
+    writers: &mut [Option<hound::WavWriter<W>>],
+    is_cancelled: impl Fn() -> bool,
+) -> Result<(), String> {
+    let channel_count = audio.channels.len();
+    let len = audio.len();
+    let bins = FFT_SIZE / 2 + 1;
+    let bin_hz = audio.sample_rate as f32 / FFT_SIZE as f32;
+    let vocals = vocals && channel_count == 2;
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(FFT_SIZE);
+    let inverse = planner.plan_fft_inverse(FFT_SIZE);
+
+    // 周期的ハン窓（分析・合成の両方に使用）
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|n| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / FFT_SIZE as f32).cos())
+        .collect();
+
+    // オーバーラップ加算の正規化係数（窓の二乗和）
+    let norm: Vec<f32> = (0..HOP_SIZE)
+        .map(|j| {
+            (0..FFT_SIZE / HOP_SIZE)
+                .map(|m| window[j + m * HOP_SIZE].powi(2))
+                .sum::<f32>()
+                .max(MASK_EPSILON)
+        })
+        .collect();
+
+    // フレームtは t*HOP_SIZE - (FFT_SIZE - HOP_SIZE) から始まる
+    let frame_start = |t: usize| (t * HOP_SIZE) as isize - (FFT_SIZE - HOP_SIZE) as isize;
+    let total_frames = (len + FFT_SIZE - HOP_SIZE).div_ceil(HOP_SIZE);
+    let half = HARMONIC_KERNEL / 2;
+
+    let mut time_buf = forward.make_input_vec();
+    let mut spec_buf = forward.make_output_vec();
+    let mut frames: VecDeque<Frame> = VecDeque::with_capacity(HARMONIC_KERNEL + 1);
+    let mut first_index = 0; // frames[0]のフレーム番号
+
+    let mut masks = vec![[0.0f32; STEM_NAMES.len()]; bins];
+    let mut ola = vec![vec![vec![0.0f32; FFT_SIZE]; channel_count]; STEM_NAMES.len()];
+    let mut median_buf = Vec::with_capacity(HARMONIC_KERNEL.max(PERCUSSIVE_KERNEL));
+
+    for t in 0..total_frames + half {
+        // 分析
+        if t < total_frames {
+            let start = frame_start(t);
+            let mut spectra = Vec::with_capacity(channel_count);
+            for channel in &audio.channels {
+                for (n, sample) in time_buf.iter_mut().enumerate() {
+                    let pos = start + n as isize;
+                    *sample = if pos >= 0 && (pos as usize) < len {
+                        channel[pos as usize] * window[n]
+                    } else {
+                        0.0
+                    };
+                }
+                forward
+                    .process(&mut time_buf, &mut spec_buf)
+                    .map_err(|e| format!("FFT error: {}", e))?;
+                spectra.push(spec_buf.clone());
+            }
+            let magnitude = (0..bins)
+                .map(|k| {
+                    (spectra.iter().map(|s| s[k]).sum::<Complex<f32>>() / channel_count as f32)
+                        .norm()
+                })
+                .collect();
+            frames.push_back(Frame { spectra, magnitude });
+            if frames.len() > HARMONIC_KERNEL {
+                frames.pop_front();
+                first_index += 1;
+            }
+        }
+
+        if t < half {
+            continue;
+        }
+        let c = t - half;
+        if c >= total_frames {
+            break;
+        }
+
+        if c.is_multiple_of(CANCEL_CHECK_INTERVAL) && is_cancelled() {
+            return Err("HPSS was cancelled".to_string());
+        }
+
+        // マスク計算
+        let lo = c.saturating_sub(half).max(first_index) - first_index;
+        let hi = (c + half).min(first_index + frames.len() - 1) - first_index;
+        let center = &frames[c - first_index];
+        for (k, mask) in masks.iter_mut().enumerate() {
+            median_buf.clear();
+            median_buf.extend((lo..=hi).map(|i| frames[i].magnitude[k]));
+            let harmonic = median(&mut median_buf);
+
+            median_buf.clear();
+            let k_lo = k.saturating_sub(PERCUSSIVE_KERNEL / 2);
+            let k_hi = (k + PERCUSSIVE_KERNEL / 2).min(bins - 1);
+            median_buf.extend_from_slice(&center.magnitude[k_lo..=k_hi]);
+            let percussive = median(&mut median_buf);
+
+            let h2 = harmonic * harmonic;
+            let p2 = percussive * percussive;
+            let denom = h2 + p2 + MASK_EPSILON;
+            let harmonic_mask = h2 / denom;
+            let percussive_mask = p2 / denom;
+
+            let freq = k as f32 * bin_hz;
+            let vocal_mask = if vocals && freq >= VOCAL_BAND_HZ.0 && freq <= VOCAL_BAND_HZ.1 {
+                // 左右の差が小さいほど中央定位とみなす
+                let l = center.spectra[0][k];
+                let r = center.spectra[1][k];
+                let centerness = 1.0 - (l - r).norm() / (l.norm() + r.norm() + MASK_EPSILON);
+                harmonic_mask * centerness.clamp(0.0, 1.0).powi(2)
+            } else {
+                0.0
+            };
+
+            let is_bass = freq < BASS_CUTOFF_HZ;
+            mask[0] = if is_bass { harmonic_mask } else { 0.0 };
+            mask[1] = percussive_mask;
+            mask[2] = if is_bass {
+                0.0
+            } else {
+                harmonic_mask - vocal_mask
+            };
+            mask[VOCALS] = vocal_mask;
+        }
+
+        // 合成（オーバーラップ加算）
+        for (stem, stem_ola) in ola.iter_mut().enumerate() {
+            if writers[stem].is_none() {
+                continue;
+            }
+            for (ch, channel_ola) in stem_ola.iter_mut().enumerate() {
+                for (k, value) in spec_buf.iter_mut().enumerate() {
+                    *value = center.spectra[ch][k] * masks[k][stem];
+                }
+                // DCとナイキストの虚部は0でなければならない
+                spec_buf[0].im = 0.0;
+                spec_buf[bins - 1].im = 0.0;
+                inverse
+                    .process(&mut spec_buf, &mut time_buf)
+                    .map_err(|e| format!("Inverse FFT error: {}", e))?;
+                for (n, sample) in channel_ola.iter_mut().enumerate() {
+                    *sample += time_buf[n] * window[n] / FFT_SIZE as f32;
+                }
+            }
+        }
+
+        // 先頭HOP_SIZEサンプルは以降のフレームと重ならないので書き出す
+        let start = frame_start(c);
+        for j in 0..HOP_SIZE {
+            let pos = start + j as isize;
+            if pos < 0 || pos as usize >= len {
+                continue;
+            }
+            for (stem, writer) in writers.iter_mut().enumerate() {
+                if let Some(writer) = writer {
+                    for channel_ola in &ola[stem] {
+                        writer
+                            .write_sample(channel_ola[j] / norm[j])
+                            .map_err(|e| format!("Failed to write WAV sample: {}", e))?;
+                    }
+                }
+            }
+        }
+
+        for stem_ola in ola.iter_mut() {
+            for channel_ola in stem_ola.iter_mut() {
+                channel_ola.copy_within(HOP_SIZE.., 0);
+                channel_ola[FFT_SIZE - HOP_SIZE..].fill(0.0);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    let mid = values.len() / 2;
+    *values.select_nth_unstable_by(mid, |a, b| a.total_cmp(b)).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SAMPLE_RATE: u32 = 22050;
+
+    /// モノラル音声を分離し、ステムごとのサンプルを返す（vocalsは書き出さない）
+    fn separate_mono(samples: Vec<f32>) -> Vec<Vec<f32>> {
+        let audio = DecodedAudio {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![samples],
+        };
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut buffers: Vec<Cursor<Vec<u8>>> = (0..STEM_NAMES.len() - 1)
+            .map(|_| Cursor::new(Vec::new()))
+            .collect();
+        let mut writers: Vec<_> = buffers
+            .iter_mut()
+            .map(|buffer| Some(hound::WavWriter::new(buffer, spec).unwrap()))
+            .collect();
+        writers.push(None);
+        separate(&audio, false, &mut writers, || false).unwrap();
+        for writer in writers.into_iter().flatten() {
+            writer.finalize().unwrap();
+        }
+
+        buffers
+            .into_iter()
+            .map(|buffer| {
+                let mut reader = hound::WavReader::new(Cursor::new(buffer.into_inner())).unwrap();
+                reader.samples::<f32>().map(Result::unwrap).collect()
+            })
+            .collect()
+    }
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum()
+    }
+
+    /// 各ステムのエネルギーの割合（bass, drums, other）
+    fn energy_shares(stems: &[Vec<f32>]) -> Vec<f32> {
+        let energies: Vec<f32> = stems.iter().map(|s| energy(s)).collect();
+        let total: f32 = energies.iter().sum();
+        energies.iter().map(|e| e / total).collect()
+    }
+
+    fn sine(hz: f32) -> Vec<f32> {
+        (0..2 * SAMPLE_RATE as usize)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// 0.25秒ごとのインパルス列
+    fn impulses() -> Vec<f32> {
+        (0..2 * SAMPLE_RATE as usize)
+            .map(|i| {
+                if i % (SAMPLE_RATE as usize / 4) == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steady_sine_is_harmonic() {
+        let stems = separate_mono(sine(440.0));
+        assert_eq!(stems[2].len(), 2 * SAMPLE_RATE as usize);
+
+        let shares = energy_shares(&stems);
+        assert!(shares[2] > 0.9, "{:?}", shares);
+        // 200Hz未満の調波成分はbass
+        let shares = energy_shares(&separate_mono(sine(100.0)));
+        assert!(shares[0] > 0.9, "{:?}", shares);
+    }
+
+    #[test]
+    fn impulse_train_is_percussive() {
+        let shares = energy_shares(&separate_mono(impulses()));
+        assert!(shares[1] > 0.9, "{:?}", shares);
+    }
+
+    #[test]
+    fn mix_is_split_between_drums_and_other() {
+        let sine = sine(440.0);
+        let impulses = impulses();
+        let mix: Vec<f32> = sine.iter().zip(&impulses).map(|(a, b)| a + b).collect();
+        let stems = separate_mono(mix);
+
+        // otherはサイン波とほぼ同じで、インパルスはdrumsに入る
+        let residual: Vec<f32> = stems[2].iter().zip(&sine).map(|(a, b)| a - b).collect();
+        let residual_ratio = energy(&residual) / energy(&sine);
+        let drums_ratio = energy(&stems[1]) / energy(&impulses);
+        assert!(residual_ratio < 0.05, "{}", residual_ratio);
+        assert!(drums_ratio > 0.5, "{}", drums_ratio);
+    }
+}
@@ -0,0 +1,15 @@
+// SOUON Editorのコア（Tauriに依存しない部分）
+//
+// SOFファイルのモデル、テンポの計算、音声の解析、ステム分離のジョブ管理、
+// 音声フォーマットの変換を含む。エディタのTauriコマンドはこれを呼び出すだけの
+// 薄いアダプタで、CLIやサーバーなどからも直接リンクして使える。
+
+pub mod audio_decode;
+pub mod audio_labeling;
+pub mod harmony;
+pub mod hpss;
+pub mod pitch_tracking;
+pub mod sof;
+pub mod stem;
+pub mod stem_activity;
+pub mod tempo;
@@ -0,0 +1,412 @@
+use crate::audio_labeling::is_silence;
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+// YINの設定
+const HOP_SIZE: usize = 512;
+const YIN_THRESHOLD: f32 = 0.15; // 累積平均正規化差分関数の閾値（下回る谷がなければ無声）
+
+// ノート区切りの設定
+const PITCH_CHANGE_SEMITONES: f64 = 0.75; // これ以上ずれたら別ノートの候補
+const PITCH_CHANGE_FRAMES: usize = 3; // ずれが続いたフレーム数で区切る（ビブラート対策）
+const MAX_GAP_FRAMES: usize = 2; // 無声フレームがこれ以下ならノートを継続
+const MIN_NOTE_SECONDS: f64 = 0.06;
+
+/// 音高を追跡する楽器（探索する周波数範囲が変わる）
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PitchInstrument {
+    Vocals,
+    Bass,
+}
+
+impl PitchInstrument {
+    fn range_hz(self) -> (f32, f32) {
+        match self {
+            PitchInstrument::Vocals => (80.0, 1000.0),
+            PitchInstrument::Bass => (30.0, 400.0),
+        }
+    }
+}
+
+/// 音高が一定のノート区間（stemNotesの1要素として保存できる）
+#[derive(Debug, Clone, Serialize)]
+pub struct PitchSegment {
+    /// MIDIノート番号（区間内の中央値）
+    pub pitch: f64,
+    /// 区間内の最大音量（ステム全体の最大を1とする）
+    pub velocity: f64,
+    /// 開始時刻（秒）
+    pub time: f64,
+    /// 終了時刻（秒）
+    pub end: f64,
+    /// 有声判定の確からしさの平均（0〜1）
+    pub confidence: f64,
+}
+
+/// Data URLの音声の音高を追跡し、ノート区間に分割する
+pub fn track_pitch(
+    input_base64_audio: &str,
+    instrument: PitchInstrument,
+) -> Result<Vec<PitchSegment>, String> {
+    let audio = crate::audio_decode::decode_data_url(input_base64_audio)?;
+    let segments = track_segments(&audio.to_mono(), audio.sample_rate, instrument);
+    log::info!("Detected {} pitch segments", segments.len());
+    Ok(segments)
+}
+
+/// 1フレーム分の推定結果
+#[derive(Debug, Clone, Copy)]
+struct PitchFrame {
+    midi: f64,
+    confidence: f32,
+    rms: f32,
+}
+
+/// 単音の音高を追跡し、ノート区間に分割する
+pub fn track_segments(
+    samples: &[f32],
+    sample_rate: u32,
+    instrument: PitchInstrument,
+) -> Vec<PitchSegment> {
+    let frames = track_frames(samples, sample_rate, instrument);
+    let peak_rms = frames
+        .iter()
+        .flatten()
+        .map(|f| f.rms)
+        .fold(0.0f32, f32::max)
+        .max(f32::EPSILON);
+    let frame_seconds = HOP_SIZE as f64 / sample_rate as f64;
+
+    let mut segments = Vec::new();
+    let mut current: Vec<PitchFrame> = Vec::new();
+    let mut current_median = RunningMedian::default();
+    let mut start_frame = 0;
+    let mut gap = 0;
+    let mut deviating = 0;
+
+    let mut finish = |current: &mut Vec<PitchFrame>, start_frame: usize| {
+        if current.is_empty() {
+            return;
+        }
+        let duration = current.len() as f64 * frame_seconds;
+        if duration >= MIN_NOTE_SECONDS {
+            let mut pitches: Vec<f64> = current.iter().map(|f| f.midi).collect();
+            segments.push(PitchSegment {
+                pitch: median(&mut pitches),
+                velocity: (current.iter().map(|f| f.rms).fold(0.0f32, f32::max) / peak_rms) as f64,
+                time: start_frame as f64 * frame_seconds,
+                end: start_frame as f64 * frame_seconds + duration,
+                confidence: current.iter().map(|f| f.confidence as f64).sum::<f64>()
+                    / current.len() as f64,
+            });
+        }
+        current.clear();
+    };
+
+    for (i, frame) in frames.iter().enumerate() {
+        match frame {
+            Some(frame) => {
+                if let Some(center) = current_median.median() {
+                    if (frame.midi - center).abs() > PITCH_CHANGE_SEMITONES {
+                        deviating += 1;
+                    } else {
+                        deviating = 0;
+                    }
+                    // 音高の変化が続いたら、ずれ始めたフレームから新しいノートにする
+                    if deviating >= PITCH_CHANGE_FRAMES {
+                        let split = current.len() + 1 - deviating;
+                        let rest = current.split_off(split);
+                        finish(&mut current, start_frame);
+                        start_frame += split;
+                        current = rest;
+                        current_median = RunningMedian::default();
+                        for f in &current {
+                            current_median.push(f.midi);
+                        }
+                        deviating = 0;
+                    }
+                }
+                if current.is_empty() {
+                    start_frame = i;
+                }
+                // 短い無声区間は直前の音高で埋める
+                if let Some(&last) = current.last() {
+                    for _ in 0..gap {
+                        current.push(last);
+                        current_median.push(last.midi);
+                    }
+                }
+                gap = 0;
+                current.push(*frame);
+                current_median.push(frame.midi);
+            }
+            None => {
+                gap += 1;
+                if gap > MAX_GAP_FRAMES {
+                    finish(&mut current, start_frame);
+                    current_median = RunningMedian::default();
+                    deviating = 0;
+                }
+            }
+        }
+    }
+    finish(&mut current, start_frame);
+
+    segments
+}
+
+/// YINでフレームごとの基本周波数を推定する（無声フレームはNone）
+fn track_frames(
+    samples: &[f32],
+    sample_rate: u32,
+    instrument: PitchInstrument,
+) -> Vec<Option<PitchFrame>> {
+    let (min_hz, max_hz) = instrument.range_hz();
+    let tau_min = ((sample_rate as f32 / max_hz) as usize).max(2);
+    let tau_max = (sample_rate as f32 / min_hz).ceil() as usize;
+    let window = tau_max; // 最低周波数の1周期分
+    let frame_len = window + tau_max;
+
+    let fft_len = frame_len.next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(fft_len);
+    let inverse = planner.plan_fft_inverse(fft_len);
+
+    let mut head_buf = forward.make_input_vec();
+    let mut frame_buf = forward.make_input_vec();
+    let mut head_spec = forward.make_output_vec();
+    let mut frame_spec = forward.make_output_vec();
+    let mut corr = inverse.make_output_vec();
+    let mut energy = vec![0.0f32; frame_len + 1];
+    let mut diff = vec![0.0f32; tau_max + 1];
+
+    let mut frames = Vec::new();
+    let mut start = 0;
+    while start + frame_len <= samples.len() {
+        let frame = &samples[start..start + frame_len];
+        start += HOP_SIZE;
+
+        let rms = (frame[..window].iter().map(|&s| s * s).sum::<f32>() / window as f32).sqrt();
+        if is_silence(&frame[..window]) {
+            frames.push(None);
+            continue;
+        }
+
+        // 自己相関 r(τ) = Σ x[j] x[j+τ]（j < window）をFFTで計算
+        head_buf.fill(0.0);
+        head_buf[..window].copy_from_slice(&frame[..window]);
+        frame_buf.fill(0.0);
+        frame_buf[..frame_len].copy_from_slice(frame);
+        if forward.process(&mut head_buf, &mut head_spec).is_err()
+            || forward.process(&mut frame_buf, &mut frame_spec).is_err()
+        {
+            frames.push(None);
+            continue;
+        }
+        for (h, f) in head_spec.iter_mut().zip(&frame_spec) {
+            *h = h.conj() * f;
+        }
+        head_spec[0].im = 0.0;
+        if let Some(last) = head_spec.last_mut() {
+            last.im = 0.0;
+        }
+        if inverse.process(&mut head_spec, &mut corr).is_err() {
+            frames.push(None);
+            continue;
+        }
+
+        // 差分関数 d(τ) = E(0..window) + E(τ..τ+window) - 2r(τ)
+        energy[0] = 0.0;
+        for (j, &s) in frame.iter().enumerate() {
+            energy[j + 1] = energy[j] + s * s;
+        }
+        for (tau, d) in diff.iter_mut().enumerate() {
+            let r = corr[tau] / fft_len as f32;
+            *d = (energy[window] + energy[tau + window] - energy[tau] - 2.0 * r).max(0.0);
+        }
+
+        // 累積平均正規化差分関数
+        let mut running_sum = 0.0;
+        diff[0] = 1.0;
+        for (tau, d) in diff.iter_mut().enumerate().skip(1) {
+            running_sum += *d;
+            *d = if running_sum > 0.0 {
+                *d * tau as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        // 閾値を下回った最初の谷を探す
+        let mut best = None;
+        let mut tau = tau_min;
+        while tau < tau_max {
+            if diff[tau] < YIN_THRESHOLD {
+                while tau + 1 < tau_max && diff[tau + 1] < diff[tau] {
+                    tau += 1;
+                }
+                best = Some(tau);
+                break;
+            }
+            tau += 1;
+        }
+
+        let frame = best.map(|tau| {
+            let confidence = 1.0 - diff[tau];
+            // 放物線補間
+            let refined = if tau > 0 && tau < tau_max {
+                let (a, b, c) = (diff[tau - 1], diff[tau], diff[tau + 1]);
+                let denom = a - 2.0 * b + c;
+                if denom.abs() > f32::EPSILON {
+                    tau as f32 + 0.5 * (a - c) / denom
+                } else {
+                    tau as f32
+                }
+            } else {
+                tau as f32
+            };
+            let hz = sample_rate as f64 / refined as f64;
+            PitchFrame {
+                midi: 69.0 + 12.0 * (hz / 440.0).log2(),
+                confidence,
+                rms,
+            }
+        });
+        frames.push(frame);
+    }
+
+    frames
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// 音高の中央値を1フレームずつ更新する（下半分の最大ヒープと上半分の最小ヒープ）
+#[derive(Default)]
+struct RunningMedian {
+    lower: BinaryHeap<Midi>,
+    upper: BinaryHeap<Reverse<Midi>>,
+}
+
+impl RunningMedian {
+    fn push(&mut self, value: f64) {
+        if self.lower.peek().is_none_or(|lower| value <= lower.0) {
+            self.lower.push(Midi(value));
+        } else {
+            self.upper.push(Reverse(Midi(value)));
+        }
+        // lowerがupperと同数か1つ多い状態を保つ
+        if self.lower.len() > self.upper.len() + 1 {
+            if let Some(value) = self.lower.pop() {
+                self.upper.push(Reverse(value));
+            }
+        } else if self.upper.len() > self.lower.len() {
+            if let Some(Reverse(value)) = self.upper.pop() {
+                self.lower.push(value);
+            }
+        }
+    }
+
+    fn median(&self) -> Option<f64> {
+        let lower = self.lower.peek()?.0;
+        match self.upper.peek() {
+            Some(Reverse(upper)) if self.lower.len() == self.upper.len() => {
+                Some((lower + upper.0) / 2.0)
+            }
+            _ => Some(lower),
+        }
+    }
+}
+
+/// BinaryHeapに入れるためのf64（total_cmpで比較）
+#[derive(Clone, Copy)]
+struct Midi(f64);
+
+impl PartialEq for Midi {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Midi {}
+
+impl PartialOrd for Midi {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Midi {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn sine(hz: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn sine_is_one_segment_at_its_pitch() {
+        let segments = track_segments(&sine(220.0, 1.0), SAMPLE_RATE, PitchInstrument::Vocals);
+
+        assert_eq!(segments.len(), 1);
+        let segment = &segments[0];
+        assert!((segment.pitch - 57.0).abs() < 0.1, "{}", segment.pitch);
+        assert!(segment.time < 0.05, "{}", segment.time);
+        assert!(segment.end > 0.9, "{}", segment.end);
+        assert!(segment.confidence > 0.9, "{}", segment.confidence);
+        assert_eq!(segment.velocity, 1.0);
+    }
+
+    #[test]
+    fn pitch_change_starts_a_new_segment() {
+        // A3（220Hz）からE4（329.63Hz）へ
+        let mut samples = sine(220.0, 0.5);
+        samples.extend(sine(329.63, 0.5));
+        let segments = track_segments(&samples, SAMPLE_RATE, PitchInstrument::Vocals);
+
+        let pitches: Vec<f64> = segments.iter().map(|s| s.pitch.round()).collect();
+        assert_eq!(pitches, [57.0, 64.0]);
+        assert!(
+            (segments[1].time - 0.5).abs() < 0.05,
+            "{}",
+            segments[1].time
+        );
+    }
+
+    #[test]
+    fn silence_has_no_segments() {
+        let silence = vec![0.0; SAMPLE_RATE as usize];
+        assert!(track_segments(&silence, SAMPLE_RATE, PitchInstrument::Bass).is_empty());
+    }
+
+    #[test]
+    fn running_median_matches_sorted_median() {
+        let values = [60.0, 57.0, 64.0, 57.5, 62.0, 59.0];
+        let mut running = RunningMedian::default();
+        for (i, &value) in values.iter().enumerate() {
+            running.push(value);
+            let mut sorted = values[..=i].to_vec();
+            assert_eq!(running.median(), Some(median(&mut sorted)));
+        }
+    }
+}
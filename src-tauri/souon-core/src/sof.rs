@@ -0,0 +1,284 @@
+use crate::tempo::TempoEvent;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+// SOFファイル（エディタのプロジェクト、JSON）のモデル
+//
+// 譜面とテンポ情報以外の項目（音声のData URL、ステムなど）はそのまま保持し、
+// 読み込んで書き出しても失われないようにする。
+
+/// SOFファイル全体
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SofFile {
+    pub name: String,
+    /// 秒
+    pub music_length: f64,
+    pub charts: Vec<Chart>,
+    pub music_tempo_list: Vec<TempoEvent>,
+    /// その他の項目（music、stems、stemNotesなど）
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl SofFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content =
+            serde_json::to_vec(self).map_err(|e| format!("Failed to serialize SOF file: {}", e))?;
+        std::fs::write(path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// フロントエンドのChartと同じ形式（positionはナノ秒の文字列）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chart {
+    pub uuid: String,
+    pub events: Vec<ChartEvent>,
+    pub lane_number: usize,
+    pub label: String,
+    #[serde(default = "default_level")]
+    pub level: u32,
+}
+
+fn default_level() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChartEvent {
+    SingleNote {
+        uuid: String,
+        #[serde(
+            serialize_with = "serialize_position",
+            deserialize_with = "deserialize_position"
+        )]
+        position: u64,
+        lane: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    LongNote {
+        uuid: String,
+        #[serde(
+            serialize_with = "serialize_position",
+            deserialize_with = "deserialize_position"
+        )]
+        position: u64,
+        lane: usize,
+        #[serde(
+            serialize_with = "serialize_position",
+            deserialize_with = "deserialize_position"
+        )]
+        end_position: u64,
+    },
+    SpeedChange {
+        uuid: String,
+        #[serde(
+            serialize_with = "serialize_position",
+            deserialize_with = "deserialize_position"
+        )]
+        position: u64,
+        speed: f64,
+    },
+}
+
+impl ChartEvent {
+    pub fn position(&self) -> u64 {
+        match self {
+            ChartEvent::SingleNote { position, .. }
+            | ChartEvent::LongNote { position, .. }
+            | ChartEvent::SpeedChange { position, .. } => *position,
+        }
+    }
+
+    /// ノートのレーン（SpeedChangeはNone）
+    pub fn lane(&self) -> Option<usize> {
+        match self {
+            ChartEvent::SingleNote { lane, .. } | ChartEvent::LongNote { lane, .. } => Some(*lane),
+            ChartEvent::SpeedChange { .. } => None,
+        }
+    }
+
+    /// 終了位置（ロングノート以外は開始位置）
+    pub fn end_position(&self) -> u64 {
+        match self {
+            ChartEvent::LongNote { end_position, .. } => *end_position,
+            _ => self.position(),
+        }
+    }
+
+    /// 並べ替え用（位置、レーン）
+    pub fn sort_key(&self) -> (u64, usize) {
+        (self.position(), self.lane().unwrap_or(0))
+    }
+}
+
+/// JavaScriptの数値では精度が足りないため、ナノ秒位置は文字列にする
+fn serialize_position<S: Serializer>(position: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(position)
+}
+
+fn deserialize_position<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Position {
+        Text(String),
+        Number(u64),
+    }
+    match Position::deserialize(deserializer)? {
+        Position::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        Position::Number(position) => Ok(position),
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SofFileMeta {
+    name: String,
+    #[serde(rename = "musicLength")]
+    music_length: f64,
+    charts: Vec<ChartMeta>,
+    #[serde(rename = "musicTempoList")]
+    music_tempo_list: Vec<TempoEventMeta>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ChartMeta {
+    uuid: String,
+    #[serde(rename = "laneNumber")]
+    lane_number: i32,
+    label: String,
+    level: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TempoEventMeta {
+    uuid: String,
+    tempo: f64,
+    beat: i32,
+    length: f64,
+}
+
+/// SOFファイルからメタ情報を抽出する
+///
+/// # Arguments
+/// * `sof_content` - SOFファイルの内容（JSON文字列）
+///
+/// # Returns
+/// 抽出されたメタ情報のJSON文字列、またはエラー
+pub fn extract_meta_from_sof(sof_content: &str) -> Result<String, Box<dyn std::error::Error>> {
+    // SOFファイル全体をパース
+    let full_data: Value = serde_json::from_str(sof_content)?;
+
+    // Charts情報から必要な情報だけを抽出
+    let charts_data = full_data["charts"]
+        .as_array()
+        .ok_or("charts is not an array")?;
+    let charts: Vec<ChartMeta> = charts_data
+        .iter()
+        .map(|chart| ChartMeta {
+            uuid: chart["uuid"].as_str().unwrap_or("").to_string(),
+            lane_number: chart["laneNumber"].as_i64().unwrap_or(0) as i32,
+            label: chart["label"].as_str().unwrap_or("").to_string(),
+            level: chart["level"].as_i64().unwrap_or(1) as i32,
+        })
+        .collect();
+
+    // メタ情報だけを抽出
+    let meta = SofFileMeta {
+        name: full_data["name"].as_str().unwrap_or("Unknown").to_string(),
+        music_length: full_data["musicLength"].as_f64().unwrap_or(0.0),
+        charts,
+        music_tempo_list: serde_json::from_value(full_data["musicTempoList"].clone())?,
+    };
+
+    // JSON文字列に変換して返す
+    Ok(serde_json::to_string_pretty(&meta)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sof_json() -> Value {
+        json!({
+            "name": "song",
+            "musicLength": 12.5,
+            "charts": [{
+                "uuid": "chart",
+                "laneNumber": 4,
+                "label": "Normal",
+                "level": 3,
+                "events": [
+                    { "type": "SingleNote", "uuid": "a", "position": "500000000", "lane": 1 },
+                    {
+                        "type": "LongNote", "uuid": "b", "position": "1000000000", "lane": 2,
+                        "endPosition": "18446744073709551615"
+                    },
+                    { "type": "SpeedChange", "uuid": "c", "position": 2000000000u64, "speed": 1.5 }
+                ]
+            }],
+            "musicTempoList": [{ "uuid": "tempo", "tempo": 120.0, "beat": 4.0, "length": 8.0 }],
+            "music": "data:audio/wav;base64,AAAA",
+            "stemNotes": { "drums": [] }
+        })
+    }
+
+    #[test]
+    fn round_trip_keeps_events_and_other_fields() {
+        let path = std::env::temp_dir().join(format!("souon-sof-{}.sof", std::process::id()));
+        std::fs::write(&path, sof_json().to_string()).unwrap();
+
+        let sof = SofFile::load(&path).unwrap();
+        sof.save(&path).unwrap();
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 数値で書かれた位置も文字列で書き出す
+        let mut expected = sof_json();
+        expected["charts"][0]["events"][2]["position"] = json!("2000000000");
+        assert_eq!(saved, expected);
+
+        let events = &sof.charts[0].events;
+        assert_eq!(events[1].end_position(), u64::MAX);
+        assert_eq!(events[1].lane(), Some(2));
+        assert_eq!(events[2].lane(), None);
+        assert_eq!(events[2].end_position(), 2_000_000_000);
+    }
+
+    #[test]
+    fn load_reports_missing_file() {
+        let path = std::env::temp_dir().join("souon-sof-missing.sof");
+        let error = SofFile::load(&path).unwrap_err();
+        assert!(error.starts_with("Failed to read"), "{}", error);
+    }
+
+    #[test]
+    fn extract_meta_drops_events_and_media() {
+        // フロントエンドが書き出すbeatは整数
+        let mut sof = sof_json();
+        sof["musicTempoList"][0]["beat"] = json!(4);
+        let meta = extract_meta_from_sof(&sof.to_string()).unwrap();
+        let meta: Value = serde_json::from_str(&meta).unwrap();
+
+        assert_eq!(
+            meta,
+            json!({
+                "name": "song",
+                "musicLength": 12.5,
+                "charts": [{ "uuid": "chart", "laneNumber": 4, "label": "Normal", "level": 3 }],
+                "musicTempoList": [{ "uuid": "tempo", "tempo": 120.0, "beat": 4, "length": 8.0 }]
+            })
+        );
+    }
+}
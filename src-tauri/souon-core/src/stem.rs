@@ -0,0 +1,656 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::io::AsyncReadExt;
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
+
+/// 同時に実行できるステム分離ジョブ数の初期値
+const DEFAULT_STEM_JOB_CONCURRENCY: usize = 2;
+
+/// ステム分離ジョブのキュー
+///
+/// ジョブごとに作業ディレクトリを分け、同時実行数をセマフォで制限する。
+pub struct StemJobQueue {
+    semaphore: Semaphore,
+    inner: Mutex<StemJobQueueInner>,
+}
+
+struct StemJobQueueInner {
+    concurrency: usize,
+    /// 同時実行数を減らしたときに実行中のジョブが持っていて、まだ回収していない枠の数
+    excess_permits: usize,
+    jobs: HashMap<String, watch::Sender<bool>>,
+}
+
+impl Default for StemJobQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_STEM_JOB_CONCURRENCY)
+    }
+}
+
+impl StemJobQueue {
+    pub fn new(concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            semaphore: Semaphore::new(concurrency),
+            inner: Mutex::new(StemJobQueueInner {
+                concurrency,
+                excess_permits: 0,
+                jobs: HashMap::new(),
+            }),
+        }
+    }
+
+    /// ジョブを登録し、キャンセル通知を返す
+    fn register(&self, job_id: &str) -> Result<watch::Receiver<bool>, String> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.jobs.contains_key(job_id) {
+            return Err(format!("Stem job {} is already running", job_id));
+        }
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        inner.jobs.insert(job_id.to_string(), cancel_tx);
+        Ok(cancel_rx)
+    }
+
+    /// 実行枠が空くまで待つ
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>, String> {
+        loop {
+            let permit = self
+                .semaphore
+                .acquire()
+                .await
+                .map_err(|e| format!("Stem job queue closed: {}", e))?;
+            let mut inner = self.inner.lock().unwrap();
+            if inner.excess_permits == 0 {
+                return Ok(permit);
+            }
+            // 同時実行数を減らした分の枠を回収する
+            inner.excess_permits -= 1;
+            permit.forget();
+        }
+    }
+
+    fn unregister(&self, job_id: &str) {
+        self.inner.lock().unwrap().jobs.remove(job_id);
+    }
+
+    /// ジョブにキャンセルを通知する（存在しない場合はfalse）
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.inner.lock().unwrap().jobs.get(job_id) {
+            Some(cancel_tx) => {
+                let _ = cancel_tx.send(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 登録中（待機中・実行中）のジョブ数
+    pub fn active_jobs(&self) -> usize {
+        self.inner.lock().unwrap().jobs.len()
+    }
+
+    /// 同時実行数を変更する
+    ///
+    /// 減らした場合、実行中のジョブはそのまま完了し、その枠は次の取得時に回収する。
+    pub fn set_concurrency(&self, concurrency: usize) {
+        let concurrency = concurrency.max(1);
+        let mut inner = self.inner.lock().unwrap();
+        if concurrency > inner.concurrency {
+            let added = concurrency - inner.concurrency;
+            let reclaimed = added.min(inner.excess_permits);
+            inner.excess_permits -= reclaimed;
+            self.semaphore.add_permits(added - reclaimed);
+        } else {
+            let removed = inner.concurrency - concurrency;
+            let forgotten = self.semaphore.forget_permits(removed);
+            inner.excess_permits += removed - forgotten;
+        }
+        inner.concurrency = concurrency;
+    }
+}
+
+/// 実行中のジョブ。破棄時にキューから外し、作業ディレクトリを削除する
+pub struct StemJob<'a> {
+    queue: &'a StemJobQueue,
+    pub id: String,
+    pub dir: PathBuf,
+}
+
+impl StemJob<'_> {
+    /// 実行枠が空くまで待つ（待機中にキャンセルされた場合はエラー）
+    pub async fn acquire(
+        &self,
+        cancel_rx: &mut watch::Receiver<bool>,
+    ) -> Result<SemaphorePermit<'_>, String> {
+        tokio::select! {
+            permit = self.queue.acquire() => permit,
+            _ = wait_cancelled(cancel_rx) => Err(format!("Stem job {} was cancelled", self.id)),
+        }
+    }
+}
+
+impl Drop for StemJob<'_> {
+    fn drop(&mut self) {
+        self.queue.unregister(&self.id);
+        if self.dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+                log::warn!(
+                    "Failed to clean up stem job directory {}: {}",
+                    self.dir.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+fn new_job_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{:x}-{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn validate_job_id(job_id: &str) -> Result<(), String> {
+    // ジョブIDはディレクトリ名に使うため、パス区切りなどを含めない
+    if job_id.is_empty()
+        || !job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid stem job id: {}", job_id));
+    }
+    Ok(())
+}
+
+/// キャンセルが通知されるまで待つ（送信側が破棄された場合は待ち続ける）
+pub async fn wait_cancelled(cancel_rx: &mut watch::Receiver<bool>) {
+    if cancel_rx.wait_for(|cancelled| *cancelled).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// ジョブをキューに登録し、作業ディレクトリ（jobs_dir/<job_id>）を割り当てる
+pub fn start_stem_job<'a>(
+    jobs_dir: &Path,
+    queue: &'a StemJobQueue,
+    job_id: Option<String>,
+) -> Result<(StemJob<'a>, watch::Receiver<bool>), String> {
+    let job_id = job_id.unwrap_or_else(new_job_id);
+    validate_job_id(&job_id)?;
+
+    let job_dir = jobs_dir.join(&job_id);
+
+    let cancel_rx = queue.register(&job_id)?;
+
+    log::info!("Stem job {} queued", job_id);
+
+    Ok((
+        StemJob {
+            queue,
+            id: job_id,
+            dir: job_dir,
+        },
+        cancel_rx,
+    ))
+}
+
+/// Demucsの実行環境
+#[derive(Debug, Clone)]
+pub struct DemucsEnvironment {
+    /// demucsの実行ファイル
+    pub executable: PathBuf,
+    /// モデルの保存先（TORCH_HOME）
+    pub torch_home: PathBuf,
+    /// 元のファイルを渡す場合にDemucsが使うFFmpegのディレクトリ（PATHの先頭に追加する）
+    pub ffmpeg_dir: Option<PathBuf>,
+}
+
+/// Demucsに渡す入力ファイルを書き出し、そのパスを返す
+fn prepare_demucs_input(
+    input_data: Vec<u8>,
+    mime_type: &str,
+    wav_file: PathBuf,
+    original_file: PathBuf,
+) -> Result<PathBuf, String> {
+    let decoded =
+        crate::audio_decode::decode_bytes(input_data.clone(), Some(mime_type)).and_then(|audio| {
+            if audio.is_empty() {
+                return Err("Decoded audio is empty".to_string());
+            }
+            audio.write_wav(&wav_file)
+        });
+
+    match decoded {
+        Ok(()) => Ok(wav_file),
+        Err(e) => {
+            log::warn!("Falling back to FFmpeg decoding in Demucs: {}", e);
+            std::fs::write(&original_file, input_data)
+                .map_err(|e| format!("Failed to write temporary file: {}", e))?;
+            Ok(original_file)
+        }
+    }
+}
+
+/// Demucsでステムを分離し、bass, drums, other, vocalsのData URLを"\n"で結合して返す
+///
+/// 実行枠が空くまで待つ。cancel_rxがtrueになったらdemucsを終了させ、終了を待ってから返す。
+pub async fn run_demucs_job(
+    environment: &DemucsEnvironment,
+    job: &StemJob<'_>,
+    input_base64: String,
+    mime_type: String,
+    codec: StemCodec,
+    mut cancel_rx: watch::Receiver<bool>,
+) -> Result<String, String> {
+    // 実行枠が空くまで待機
+    let _permit = job.acquire(&mut cancel_rx).await?;
+    let job_dir = job.dir.as_path();
+
+    log::info!(
+        "Running Demucs with input base64 and MIME type: {}",
+        mime_type
+    );
+    log::info!("Input data base64 length: {}", input_base64.len());
+    // 入力のbase64をデコード
+    let input_data = general_purpose::STANDARD
+        .decode(&input_base64)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+
+    log::info!("Decoded input data length: {}", input_data.len());
+
+    // 拡張子を取得
+    let extension = match mime_type.as_str() {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/wav" | "audio/wave" => "wav",
+        "audio/flac" => "flac",
+        "audio/ogg" => "ogg",
+        "audio/aac" => "aac",
+        _ => mime_type.split('/').next_back().unwrap_or("wav"),
+    };
+
+    log::info!("Using extension: {}", extension);
+
+    // 出力先を取得（作業ディレクトリ内）
+    let output_dir = job_dir.join("output");
+    tokio::fs::create_dir_all(&output_dir)
+        .await
+        .map_err(|e| format!("Failed to create stem job directory: {}", e))?;
+
+    // SymphoniaでWAVにデコードしておく（DemucsがFFmpegなしで読める）
+    // デコードできない形式の場合は元のファイルを渡し、Demucs側のFFmpegに任せる
+    let wav_file = job_dir.join("input.wav");
+    let original_file = job_dir.join(format!("input.{}", extension));
+    let temp_file = {
+        let mime_type = mime_type.clone();
+        tokio::task::spawn_blocking(move || {
+            prepare_demucs_input(input_data, &mime_type, wav_file, original_file)
+        })
+        .await
+        .map_err(|e| format!("Task join error: {}", e))??
+    };
+
+    log::info!("Running Demucs...");
+
+    // demucsを実行
+    let mut command = tokio::process::Command::new(&environment.executable);
+    command
+        .arg(&temp_file)
+        .arg("-o")
+        .arg(&output_dir)
+        .env("PYTHONUSERBASE", "") // ユーザーサイトパッケージを無効化
+        .env("PYTHONPATH", "") // PYTHONPATH をクリア
+        .env("TORCH_HOME", &environment.torch_home)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true); // 途中でFutureが破棄された場合もプロセスを終了
+
+    // 元のファイルを渡す場合に備え、設定されたFFmpegをPATHの先頭に追加
+    if let Some(ffmpeg_dir) = &environment.ffmpeg_dir {
+        let mut paths = vec![ffmpeg_dir.clone()];
+        if let Some(path) = std::env::var_os("PATH") {
+            paths.extend(std::env::split_paths(&path));
+        }
+        if let Ok(path) = std::env::join_paths(paths) {
+            command.env("PATH", path);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to execute demucs: {}", e))?;
+    let mut stderr_pipe = child.stderr.take();
+    let mut stderr = Vec::new();
+
+    let finished = tokio::select! {
+        result = async {
+            // stderrを読み切らないとパイプが詰まってdemucsが止まる
+            let read_stderr = async {
+                match stderr_pipe.as_mut() {
+                    Some(pipe) => pipe.read_to_end(&mut stderr).await.map(|_| ()),
+                    None => Ok(()),
+                }
+            };
+            let (status, read) = tokio::join!(child.wait(), read_stderr);
+            read.map_err(|e| format!("Failed to read demucs output: {}", e))?;
+            status.map_err(|e| format!("Failed to execute demucs: {}", e))
+        } => Some(result),
+        _ = wait_cancelled(&mut cancel_rx) => None,
+    };
+    let Some(status) = finished else {
+        // 終了前に作業ディレクトリを消すとWindowsではファイルが開かれたままで失敗するため、
+        // 終了を待ってから返す
+        if let Err(e) = child.kill().await {
+            log::warn!("Failed to stop demucs for stem job {}: {}", job.id, e);
+        }
+        return Err(format!("Stem job {} was cancelled", job.id));
+    };
+    let status = status?;
+
+    log::info!("Done.");
+
+    if !status.success() {
+        return Err(format!(
+            "Failed to run demucs: {}",
+            String::from_utf8_lossy(&stderr)
+        ));
+    }
+
+    // 出力先のフォルダ内に存在するwavファイルを再帰的に検索
+    let mut output_files = Vec::new();
+    search_wav_files(&output_dir, &mut output_files).await?;
+
+    if output_files.is_empty() {
+        return Err("No output files found".to_string());
+    }
+
+    if output_files.len() != 4 {
+        return Err("Unexpected number of output files found".to_string());
+    }
+
+    // bass,drums,other,vocalsの順で出力ファイルを並べ替え
+    output_files.sort_by(|a, b| {
+        let a_path = std::path::Path::new(a);
+        let b_path = std::path::Path::new(b);
+        let a_name = a_path.file_stem().unwrap_or_default();
+        let b_name = b_path.file_stem().unwrap_or_default();
+        a_name.cmp(b_name)
+    });
+
+    // その順番で指定形式に変換しData URLにする
+    let mut encoded_files = Vec::new();
+    for wav_file in output_files {
+        let wav_path = std::path::Path::new(&wav_file);
+        let data_url = tokio::task::spawn_blocking({
+            let wav_path = wav_path.to_owned();
+            move || encode_stem(&wav_path, codec)
+        })
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+        .map_err(|e| format!("Failed to encode stem {}: {}", wav_file, e))?;
+        encoded_files.push(data_url);
+    }
+
+    // encoded_filesを\nで結合して返す
+    Ok(encoded_files.join("\n"))
+}
+
+/// ステムごとの値（Project.stemsと同じ並び）
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StemSet<T> {
+    pub bass: T,
+    pub drums: T,
+    pub other: T,
+    pub vocals: T,
+}
+
+impl<T> StemSet<T> {
+    pub fn map<U>(self, mut f: impl FnMut(&'static str, T) -> U) -> StemSet<U> {
+        StemSet {
+            bass: f("bass", self.bass),
+            drums: f("drums", self.drums),
+            other: f("other", self.other),
+            vocals: f("vocals", self.vocals),
+        }
+    }
+
+    pub fn try_map<U, E>(
+        self,
+        mut f: impl FnMut(&'static str, T) -> Result<U, E>,
+    ) -> Result<StemSet<U>, E> {
+        Ok(StemSet {
+            bass: f("bass", self.bass)?,
+            drums: f("drums", self.drums)?,
+            other: f("other", self.other)?,
+            vocals: f("vocals", self.vocals)?,
+        })
+    }
+}
+
+/// ステムの出力形式
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum StemCodec {
+    /// Ogg Vorbis（quality: -0.1〜1.0）
+    Vorbis { quality: f32 },
+    /// FLAC（可逆圧縮）
+    Flac,
+    /// 無圧縮WAV
+    Wav,
+}
+
+impl Default for StemCodec {
+    fn default() -> Self {
+        StemCodec::Vorbis { quality: 1.0 }
+    }
+}
+
+impl StemCodec {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            StemCodec::Vorbis { .. } => "audio/ogg",
+            StemCodec::Flac => "audio/flac",
+            StemCodec::Wav => "audio/wav",
+        }
+    }
+}
+
+/// WAVファイルをエンコードし、Data URLとして返す
+pub fn encode_stem(wav_path: &std::path::Path, codec: StemCodec) -> Result<String, String> {
+    let (spec, samples) = read_wav_samples(wav_path)?;
+
+    let encoded = match codec {
+        StemCodec::Vorbis { quality } => encode_vorbis(spec, &samples, quality)?,
+        StemCodec::Flac => encode_flac(spec, &samples)?,
+        StemCodec::Wav => encode_wav(spec, &samples)?,
+    };
+
+    // Base64エンコード
+    Ok(format!(
+        "data:{};base64,{}",
+        codec.mime_type(),
+        general_purpose::STANDARD.encode(&encoded)
+    ))
+}
+
+/// WAVファイルを読み込み、-1.0〜1.0のf32サンプル（インターリーブ）に変換する
+pub fn read_wav_samples(wav_path: &std::path::Path) -> Result<(hound::WavSpec, Vec<f32>), String> {
+    // WAVファイルを読み込み
+    let mut wav_reader = hound::WavReader::open(wav_path)
+        .map_err(|e| format!("Failed to open WAV file {}: {}", wav_path.display(), e))?;
+
+    let spec = wav_reader.spec();
+    log::info!(
+        "WAV spec: channels={}, sample_rate={}, bits_per_sample={}, format={:?}",
+        spec.channels,
+        spec.sample_rate,
+        spec.bits_per_sample,
+        spec.sample_format
+    );
+
+    // サンプルデータを読み取り（Demucsはfloat32や24bitで出力することがある）
+    let samples: Result<Vec<f32>, _> = match spec.sample_format {
+        hound::SampleFormat::Float => wav_reader.samples::<f32>().collect(),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            wav_reader
+                .samples::<i32>()
+                .map(|s| s.map(|sample| sample as f32 * scale))
+                .collect()
+        }
+    };
+
+    let samples = samples.map_err(|e| format!("Failed to read samples from WAV file: {}", e))?;
+
+    Ok((spec, samples))
+}
+
+fn encode_vorbis(spec: hound::WavSpec, samples: &[f32], quality: f32) -> Result<Vec<u8>, String> {
+    if !(-0.1..=1.0).contains(&quality) {
+        return Err(format!(
+            "Vorbis quality must be between -0.1 and 1.0, got {}",
+            quality
+        ));
+    }
+
+    // Vorbisエンコーダーを作成
+    let mut encoder =
+        vorbis_encoder::Encoder::new(spec.channels as u32, spec.sample_rate as u64, quality)
+            .map_err(|e| format!("Failed to create Vorbis encoder: {}", e))?;
+
+    // エンコード用のバッファを準備
+    let mut output_data = Vec::new();
+
+    // f32サンプルをi16に変換
+    let i16_samples: Vec<i16> = samples
+        .iter()
+        .map(|&sample| (sample * 32767.0).clamp(-32768.0, 32767.0) as i16)
+        .collect();
+
+    // エンコード実行
+    let data = encoder
+        .encode(&i16_samples)
+        .map_err(|e| format!("Failed to encode audio data: {}", e))?;
+    output_data.extend_from_slice(&data);
+
+    // ファイナライズ
+    let data = encoder
+        .flush()
+        .map_err(|e| format!("Failed to flush encoder: {}", e))?;
+    output_data.extend_from_slice(&data);
+
+    Ok(output_data)
+}
+
+fn encode_flac(spec: hound::WavSpec, samples: &[f32]) -> Result<Vec<u8>, String> {
+    use flacenc::component::BitRepr;
+    use flacenc::error::Verify;
+
+    // 16bitを超える入力は24bitで保存する
+    let bits_per_sample: usize = if spec.bits_per_sample > 16 { 24 } else { 16 };
+    let max = ((1i32 << (bits_per_sample - 1)) - 1) as f32;
+    let int_samples: Vec<i32> = samples
+        .iter()
+        .map(|&sample| (sample * max).round().clamp(-max - 1.0, max) as i32)
+        .collect();
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| format!("Invalid FLAC encoder config: {:?}", e))?;
+    let source = flacenc::source::MemSource::from_samples(
+        &int_samples,
+        spec.channels as usize,
+        bits_per_sample,
+        spec.sample_rate as usize,
+    );
+
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| format!("Failed to encode FLAC: {:?}", e))?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| format!("Failed to write FLAC stream: {:?}", e))?;
+
+    Ok(sink.as_slice().to_vec())
+}
+
+fn encode_wav(spec: hound::WavSpec, samples: &[f32]) -> Result<Vec<u8>, String> {
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    {
+        let mut writer = hound::WavWriter::new(&mut cursor, spec)
+            .map_err(|e| format!("Failed to create WAV writer: {}", e))?;
+
+        match spec.sample_format {
+            hound::SampleFormat::Float => {
+                for &sample in samples {
+                    writer
+                        .write_sample(sample)
+                        .map_err(|e| format!("Failed to write WAV sample: {}", e))?;
+                }
+            }
+            hound::SampleFormat::Int => {
+                let max = ((1i64 << (spec.bits_per_sample - 1)) - 1) as f32;
+                for &sample in samples {
+                    let value = (sample * max).round().clamp(-max - 1.0, max) as i32;
+                    writer
+                        .write_sample(value)
+                        .map_err(|e| format!("Failed to write WAV sample: {}", e))?;
+                }
+            }
+        }
+
+        writer
+            .finalize()
+            .map_err(|e| format!("Failed to finalize WAV: {}", e))?;
+    }
+    Ok(cursor.into_inner())
+}
+
+pub async fn search_wav_files(
+    dir: &std::path::Path,
+    output_files: &mut Vec<String>,
+) -> Result<(), String> {
+    search_wav_files_impl(dir, output_files).await
+}
+
+fn search_wav_files_impl<'a>(
+    dir: &'a std::path::Path,
+    output_files: &'a mut Vec<String>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), String>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to read entry in directory {}: {}", dir.display(), e))?
+        {
+            let path = entry.path();
+            let metadata = entry
+                .metadata()
+                .await
+                .map_err(|e| format!("Failed to get metadata: {}", e))?;
+            if metadata.is_dir() {
+                search_wav_files_impl(&path, output_files).await?;
+            } else if path.extension().and_then(|s| s.to_str()) == Some("wav") {
+                output_files.push(path.to_string_lossy().to_string());
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,343 @@
+use crate::audio_decode::DecodedAudio;
+use crate::audio_labeling::is_silence;
+use crate::stem::StemSet;
+use crate::tempo::{self, Bar, TempoEvent, TempoMap};
+use serde::Serialize;
+
+const MIN_DB: f32 = -120.0; // 無音時の下限（-infはJSONにできないため）
+
+/// 拍ごとの音量と発音状態
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeatActivity {
+    pub start_ns: u64,
+    pub rms_db: f32,
+    pub lufs: f32,
+    pub active: bool,
+}
+
+/// 小節ごとの音量と発音状態
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarActivity {
+    /// 小節番号（1始まり）
+    pub bar: usize,
+    pub start_ns: u64,
+    pub rms_db: f32,
+    pub lufs: f32,
+    /// いずれかの拍が発音していればtrue
+    pub active: bool,
+    pub beats: Vec<BeatActivity>,
+}
+
+/// 連続して発音している小節の範囲（両端を含む）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivitySpan {
+    pub start_bar: usize,
+    pub end_bar: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StemActivity {
+    pub bars: Vec<BarActivity>,
+    pub spans: Vec<ActivitySpan>,
+}
+
+/// 各ステム（Data URL）の小節ごとの活動状況を計算する
+///
+/// 空のステムはNoneを返す。
+pub fn stem_activity(
+    music_tempo_list: Vec<TempoEvent>,
+    stems: StemSet<String>,
+) -> Result<StemSet<Option<StemActivity>>, String> {
+    let tempo_map = TempoMap::new(music_tempo_list);
+    if tempo_map.is_empty() {
+        return Err("musicTempoList is empty".to_string());
+    }
+
+    stems.try_map(|name, data_url| {
+        if data_url.is_empty() {
+            return Ok(None);
+        }
+        log::info!("Computing activity for {} stem", name);
+        let audio = crate::audio_decode::decode_data_url(&data_url)?;
+        Ok(Some(analyze_activity(&audio, &tempo_map)))
+    })
+}
+
+/// 音声を小節・拍に区切り、RMS・ラウドネス・発音状態を求める
+pub fn analyze_activity(audio: &DecodedAudio, tempo_map: &TempoMap) -> StemActivity {
+    let sample_rate = audio.sample_rate;
+    let mono = audio.to_mono();
+    let weighted: Vec<Vec<f32>> = audio
+        .channels
+        .iter()
+        .map(|channel| k_weighted(channel, sample_rate))
+        .collect();
+
+    let duration_ns = tempo::seconds_to_ns(audio.len() as f64 / sample_rate as f64);
+    let bars: Vec<Bar> = tempo_map
+        .bars_until(duration_ns)
+        .into_iter()
+        .filter(|bar| bar.start_ns < duration_ns)
+        .collect();
+
+    let mut bar_activities = Vec::with_capacity(bars.len());
+    for bar in &bars {
+        let mut beats = Vec::with_capacity(bar.beats as usize);
+        let mut bar_level = Level::default();
+        for beat in 0..bar.beats {
+            let start_ns = bar.beat_start_ns(beat);
+            let end_ns = if beat + 1 == bar.beats {
+                bar.end_ns()
+            } else {
+                bar.beat_start_ns(beat + 1)
+            };
+            let start = tempo::ns_to_sample(start_ns, sample_rate).min(mono.len());
+            let end = tempo::ns_to_sample(end_ns, sample_rate).min(mono.len());
+
+            let level = Level::measure(&mono[start..end], &weighted, start..end);
+            bar_level.add(&level);
+            beats.push(BeatActivity {
+                start_ns,
+                rms_db: level.rms_db(),
+                lufs: level.lufs(),
+                active: start < end && !is_silence(&mono[start..end]),
+            });
+        }
+
+        bar_activities.push(BarActivity {
+            bar: bar.index + 1,
+            start_ns: bar.start_ns,
+            rms_db: bar_level.rms_db(),
+            lufs: bar_level.lufs(),
+            active: beats.iter().any(|b| b.active),
+            beats,
+        });
+    }
+
+    let spans = activity_spans(&bar_activities);
+    StemActivity {
+        bars: bar_activities,
+        spans,
+    }
+}
+
+fn activity_spans(bars: &[BarActivity]) -> Vec<ActivitySpan> {
+    let mut spans: Vec<ActivitySpan> = Vec::new();
+    for bar in bars.iter().filter(|b| b.active) {
+        match spans.last_mut() {
+            Some(span) if span.end_bar + 1 == bar.bar => span.end_bar = bar.bar,
+            _ => spans.push(ActivitySpan {
+                start_bar: bar.bar,
+                end_bar: bar.bar,
+            }),
+        }
+    }
+    spans
+}
+
+/// 区間の二乗和（RMSとラウドネスの計算用）
+#[derive(Default)]
+struct Level {
+    count: usize,
+    sum_squares: f64,
+    weighted_sum_squares: Vec<f64>, // チャンネルごと
+}
+
+impl Level {
+    fn measure(mono: &[f32], weighted: &[Vec<f32>], range: std::ops::Range<usize>) -> Self {
+        Self {
+            count: mono.len(),
+            sum_squares: mono.iter().map(|&s| (s as f64).powi(2)).sum(),
+            weighted_sum_squares: weighted
+                .iter()
+                .map(|channel| {
+                    channel[range.clone()]
+                        .iter()
+                        .map(|&s| (s as f64).powi(2))
+                        .sum()
+                })
+                .collect(),
+        }
+    }
+
+    fn add(&mut self, other: &Level) {
+        self.count += other.count;
+        self.sum_squares += other.sum_squares;
+        if self.weighted_sum_squares.len() < other.weighted_sum_squares.len() {
+            self.weighted_sum_squares
+                .resize(other.weighted_sum_squares.len(), 0.0);
+        }
+        for (sum, value) in self
+            .weighted_sum_squares
+            .iter_mut()
+            .zip(&other.weighted_sum_squares)
+        {
+            *sum += value;
+        }
+    }
+
+    fn rms_db(&self) -> f32 {
+        if self.count == 0 {
+            return MIN_DB;
+        }
+        let rms = (self.sum_squares / self.count as f64).sqrt();
+        (20.0 * rms.log10()).max(MIN_DB as f64) as f32
+    }
+
+    /// ITU-R BS.1770のラウドネス（ゲートなし）
+    fn lufs(&self) -> f32 {
+        if self.count == 0 {
+            return MIN_DB;
+        }
+        let power: f64 = self
+            .weighted_sum_squares
+            .iter()
+            .map(|sum| sum / self.count as f64)
+            .sum();
+        (-0.691 + 10.0 * power.log10()).max(MIN_DB as f64) as f32
+    }
+}
+
+/// BS.1770のK特性フィルタ（高域シェルフ＋ハイパス）を掛ける
+fn k_weighted(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let fs = sample_rate as f64;
+    let mut shelf = Biquad::high_shelf(fs);
+    let mut high_pass = Biquad::high_pass(fs);
+    samples
+        .iter()
+        .map(|&s| high_pass.process(shelf.process(s as f64)) as f32)
+        .collect()
+}
+
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    // 係数はlibebur128と同じ式で任意のサンプリング周波数から求める
+    fn high_shelf(fs: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b0: (vh + vb * k / q + k * k) / a0,
+            b1: 2.0 * (k * k - vh) / a0,
+            b2: (vh - vb * k / q + k * k) / a0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn high_pass(fs: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b0: 1.0,
+            b1: -2.0,
+            b2: 1.0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    // 転置直接形II
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 120BPM・4/4（1小節2秒）
+    fn tempo_map() -> TempoMap {
+        TempoMap::new(vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 3.0,
+        }])
+    }
+
+    /// 997Hzのサイン波（onの小節のみ）を1小節2秒で並べる
+    fn audio(bars_on: &[bool], amplitude: f32) -> DecodedAudio {
+        let bar_samples = 2 * SAMPLE_RATE as usize;
+        let samples = (0..bars_on.len() * bar_samples)
+            .map(|i| {
+                if bars_on[i / bar_samples] {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    amplitude * (2.0 * std::f32::consts::PI * 997.0 * t).sin()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        DecodedAudio {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![samples],
+        }
+    }
+
+    #[test]
+    fn silent_stem_is_inactive() {
+        let activity = analyze_activity(&audio(&[false, false, false], 0.5), &tempo_map());
+
+        assert_eq!(activity.bars.len(), 3);
+        for bar in &activity.bars {
+            assert!(!bar.active);
+            assert_eq!(bar.rms_db, MIN_DB);
+            assert_eq!(bar.lufs, MIN_DB);
+        }
+        assert!(activity.spans.is_empty());
+    }
+
+    #[test]
+    fn loud_sine_is_active_at_its_loudness() {
+        let activity = analyze_activity(&audio(&[true, false, true], 0.5), &tempo_map());
+
+        let active: Vec<bool> = activity.bars.iter().map(|b| b.active).collect();
+        assert_eq!(active, [true, false, true]);
+        let spans: Vec<(usize, usize)> = activity
+            .spans
+            .iter()
+            .map(|s| (s.start_bar, s.end_bar))
+            .collect();
+        assert_eq!(spans, [(1, 1), (3, 3)]);
+
+        // 振幅0.5のサイン波のRMSは-9.03dB、1kHz付近のK特性はほぼ0dBなのでラウドネスも同程度
+        let bar = &activity.bars[2];
+        assert!((bar.rms_db + 9.03).abs() < 0.05, "{}", bar.rms_db);
+        assert!((bar.lufs + 9.03).abs() < 0.2, "{}", bar.lufs);
+        assert_eq!(bar.beats.len(), 4);
+        assert!(bar.beats.iter().all(|b| b.active));
+    }
+}
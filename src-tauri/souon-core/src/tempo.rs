@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// musicTempoListの1要素
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TempoEvent {
+    pub uuid: String,
+    pub tempo: f64,
+    pub beat: f64,
+    pub length: f64,
+}
+
+impl TempoEvent {
+    /// 1小節の拍数
+    pub fn beats_per_bar(&self) -> u32 {
+        self.beat.floor().max(1.0) as u32
+    }
+
+    /// 1小節の長さ（フロントエンドのgetBarTemporalUnitと同じ整数演算）
+    pub fn bar_length_ns(&self) -> u64 {
+        let tempo = self.tempo.floor().max(1.0) as u64;
+        60 * NANOS_PER_SECOND / tempo * self.beats_per_bar() as u64
+    }
+
+    /// 小節数
+    pub fn bar_count(&self) -> u64 {
+        self.length.floor().max(0.0) as u64
+    }
+
+    /// テンポ情報全体の長さ（getTemporalLengthと同じ）
+    pub fn length_ns(&self) -> u64 {
+        self.bar_length_ns() * self.bar_count()
+    }
+}
+
+/// 小節
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bar {
+    /// 曲頭からの小節番号（0始まり）
+    pub index: usize,
+    pub start_ns: u64,
+    pub length_ns: u64,
+    pub beats: u32,
+    pub tempo: f64,
+}
+
+impl Bar {
+    pub fn end_ns(&self) -> u64 {
+        self.start_ns + self.length_ns
+    }
+
+    pub fn beat_length_ns(&self) -> u64 {
+        self.length_ns / self.beats as u64
+    }
+
+    /// 小節内のbeat拍目（0始まり）の開始位置
+    pub fn beat_start_ns(&self, beat: u32) -> u64 {
+        self.start_ns + self.beat_length_ns() * beat as u64
+    }
+}
+
+/// musicTempoListから小節の位置を計算する
+pub struct TempoMap {
+    events: Vec<TempoEvent>,
+}
+
+impl TempoMap {
+    pub fn new(events: Vec<TempoEvent>) -> Self {
+        Self { events }
+    }
+
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// テンポ情報に含まれる全小節
+    pub fn bars(&self) -> Vec<Bar> {
+        let mut bars = Vec::new();
+        let mut position = 0;
+        for event in &self.events {
+            let length_ns = event.bar_length_ns();
+            for _ in 0..event.bar_count() {
+                bars.push(Bar {
+                    index: bars.len(),
+                    start_ns: position,
+                    length_ns,
+                    beats: event.beats_per_bar(),
+                    tempo: event.tempo,
+                });
+                position += length_ns;
+            }
+        }
+        bars
+    }
+
+    /// end_nsまでの小節（テンポ情報が足りない分は最後のテンポで延長する）
+    pub fn bars_until(&self, end_ns: u64) -> Vec<Bar> {
+        let mut bars = self.bars();
+        if let Some(last) = self.events.last() {
+            let length_ns = last.bar_length_ns();
+            let mut position = bars.last().map_or(0, |b| b.end_ns());
+            while position < end_ns {
+                bars.push(Bar {
+                    index: bars.len(),
+                    start_ns: position,
+                    length_ns,
+                    beats: last.beats_per_bar(),
+                    tempo: last.tempo,
+                });
+                position += length_ns;
+            }
+        }
+        bars
+    }
+}
+
+pub fn seconds_to_ns(seconds: f64) -> u64 {
+    (seconds.max(0.0) * NANOS_PER_SECOND as f64) as u64
+}
+
+pub fn ns_to_seconds(ns: u64) -> f64 {
+    ns as f64 / NANOS_PER_SECOND as f64
+}
+
+/// ナノ秒位置をサンプル位置に変換する
+pub fn ns_to_sample(ns: u64, sample_rate: u32) -> usize {
+    (ns as u128 * sample_rate as u128 / NANOS_PER_SECOND as u128) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 120BPM・4/4で2小節（1小節2秒）
+    fn tempo_map() -> TempoMap {
+        TempoMap::new(vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 2.0,
+        }])
+    }
+
+    #[test]
+    fn bars_follow_each_tempo_event() {
+        let tempo_map = TempoMap::new(vec![
+            TempoEvent {
+                uuid: "a".to_string(),
+                tempo: 120.0,
+                beat: 4.0,
+                length: 2.0,
+            },
+            // 拍子とテンポの小数部は切り捨てる
+            TempoEvent {
+                uuid: "b".to_string(),
+                tempo: 90.9,
+                beat: 3.5,
+                length: 1.0,
+            },
+        ]);
+        let bars = tempo_map.bars();
+
+        let starts: Vec<u64> = bars.iter().map(|bar| bar.start_ns).collect();
+        assert_eq!(starts, [0, 2_000_000_000, 4_000_000_000]);
+        assert_eq!(bars[2].index, 2);
+        assert_eq!(bars[2].beats, 3);
+        // 60秒/90 = 666_666_666ナノ秒（整数の割り算）を3拍
+        assert_eq!(bars[2].length_ns, 1_999_999_998);
+        assert_eq!(bars[2].beat_start_ns(1), 4_666_666_666);
+        assert_eq!(bars[2].tempo, 90.9);
+    }
+
+    #[test]
+    fn bars_until_extends_only_past_the_last_bar_line() {
+        let tempo_map = tempo_map();
+        let bar_ns = 2 * NANOS_PER_SECOND;
+
+        assert_eq!(tempo_map.bars_until(0).len(), 2);
+        // 最後の小節線ちょうどで終わるなら延ばさない
+        assert_eq!(tempo_map.bars_until(2 * bar_ns).len(), 2);
+        assert_eq!(tempo_map.bars_until(2 * bar_ns + 1).len(), 3);
+        assert_eq!(tempo_map.bars_until(3 * bar_ns).len(), 3);
+
+        let bars = tempo_map.bars_until(3 * bar_ns + 1);
+        let last = bars.last().unwrap();
+        assert_eq!(last.index, 3);
+        assert_eq!(last.start_ns, 3 * bar_ns);
+        assert_eq!(last.length_ns, bar_ns);
+        assert_eq!(last.beats, 4);
+    }
+
+    #[test]
+    fn bars_until_without_tempo_is_empty() {
+        assert!(TempoMap::new(Vec::new())
+            .bars_until(NANOS_PER_SECOND)
+            .is_empty());
+    }
+}
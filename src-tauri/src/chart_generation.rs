@@ -0,0 +1,994 @@
+use crate::json_schema::{self, SchemaError};
+use crate::json_stream::ObjectMemberStream;
+use crate::language_model::{self, ChatClient, ChatOptions, ChatProvider, Message, Role, Usage};
+use crate::ollama;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use souon_core::audio_labeling::DrumKind;
+use souon_core::sof::{Chart, ChartEvent};
+use souon_core::stem::wait_cancelled;
+use souon_core::tempo::{ns_to_seconds, seconds_to_ns, Bar, TempoEvent, TempoMap};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use tauri::Emitter;
+use tokio::sync::watch;
+
+/// 不正な応答に対して再生成を依頼する回数を含めた試行回数
+pub(crate) const MAX_ATTEMPTS: usize = 3;
+/// 1拍あたりの分割数（16分音符単位）
+pub(crate) const STEPS_PER_BEAT: f64 = 4.0;
+
+/// stemNotesの1要素
+#[derive(Debug, Clone, Deserialize)]
+pub struct StemNote {
+    pub pitch: f64,
+    pub velocity: f64,
+    /// 秒
+    pub time: f64,
+    pub kind: Option<DrumKind>,
+    /// 音高区間の終了（秒）
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StemNotes {
+    pub bass: Vec<StemNote>,
+    pub drums: Vec<StemNote>,
+    pub other: Vec<StemNote>,
+    pub vocals: Vec<StemNote>,
+}
+
+impl StemNotes {
+    /// 最後のノートの時刻（音高区間があればその終了、秒）
+    pub fn end_time(&self) -> f64 {
+        [&self.bass, &self.drums, &self.other, &self.vocals]
+            .into_iter()
+            .flatten()
+            .map(|note| note.end.unwrap_or(note.time))
+            .fold(0.0, f64::max)
+    }
+}
+
+/// 曲全体の小節（曲の長さ、ステムの最後のノート、譜面の最後のイベントのうち遅いものまで）
+///
+/// 譜面のノートが途中までしかなくても、その後の小節にノートを置けるようにする。
+pub(crate) fn song_bars(
+    tempo_map: &TempoMap,
+    music_length: Option<f64>,
+    stem_notes: &StemNotes,
+    events: &[ChartEvent],
+) -> Vec<Bar> {
+    let events_end_ns = events.iter().map(ChartEvent::end_position).max();
+    let end_ns = [
+        music_length.map_or(0, seconds_to_ns),
+        seconds_to_ns(stem_notes.end_time()) + 1,
+        events_end_ns.map_or(0, |end_ns| end_ns + 1),
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(0);
+    tempo_map.bars_until(end_ns)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    White,
+    Black,
+}
+
+/// 譜面生成の設定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartGenerationOptions {
+    pub key_count: usize,
+    pub enabled_keys: Vec<bool>,
+    pub allow_simultaneous_white_black: bool,
+    pub key_types: Vec<KeyType>,
+    #[serde(default)]
+    pub custom_instructions: String,
+    /// 1回のリクエストで生成する小節数
+    pub bars_per_batch: usize,
+    pub label: String,
+}
+
+/// generate_chartの戻り値（譜面にLLMの使用量の合計を加えたもの）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneratedChart {
+    #[serde(flatten)]
+    pub chart: Chart,
+    pub usage: Usage,
+    /// 有効な譜面を生成できなかった小節番号（1始まり）
+    pub failed_bars: Vec<usize>,
+}
+
+/// generate_chart_progressイベントの内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartGenerationProgress {
+    /// 処理中のバッチの最初と最後の小節番号（1始まり）
+    pub start_bar: usize,
+    pub end_bar: usize,
+    /// 処理を終えた小節数
+    pub processed_bars: usize,
+    pub total_bars: usize,
+}
+
+/// generate_chart_barイベントの内容（検証を通った1小節分のノート）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartBarGenerated {
+    /// 小節番号（1始まり）
+    pub bar: usize,
+    pub events: Vec<ChartEvent>,
+}
+
+/// generate_chart_tokenイベントの内容
+#[derive(Debug, Clone, Serialize)]
+struct ChartGenerationToken<'a> {
+    text: &'a str,
+}
+
+/// 生成中の通知（generate_chartはそれぞれをイベントとしてフロントエンドに送る）
+pub(crate) enum GenerationEvent<'a> {
+    Progress(ChartGenerationProgress),
+    Bar(ChartBarGenerated),
+    Token(&'a str),
+}
+
+fn emit_generation_event(app_handle: &tauri::AppHandle, event: GenerationEvent) {
+    let result = match event {
+        GenerationEvent::Progress(progress) => app_handle.emit("generate_chart_progress", progress),
+        GenerationEvent::Bar(generated) => app_handle.emit("generate_chart_bar", generated),
+        GenerationEvent::Token(text) => {
+            app_handle.emit("generate_chart_token", ChartGenerationToken { text })
+        }
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to emit chart generation event: {}", e);
+    }
+}
+
+/// 実行中の譜面生成（同時に1つだけ実行し、中止できるようにする）
+#[derive(Default)]
+pub struct ChartGeneration {
+    cancel_tx: Mutex<Option<watch::Sender<bool>>>,
+}
+
+impl ChartGeneration {
+    fn start(&self) -> Result<(ChartGenerationGuard<'_>, watch::Receiver<bool>), String> {
+        let mut cancel_tx = self.cancel_tx.lock().unwrap();
+        if cancel_tx.is_some() {
+            return Err("Chart generation is already running".to_string());
+        }
+        let (tx, rx) = watch::channel(false);
+        *cancel_tx = Some(tx);
+        Ok((ChartGenerationGuard(self), rx))
+    }
+
+    /// 実行中の生成を中止する（実行中でなければfalse）
+    pub fn cancel(&self) -> bool {
+        match self.cancel_tx.lock().unwrap().as_ref() {
+            Some(cancel_tx) => {
+                let _ = cancel_tx.send(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 生成が終わったら（中止・エラーを含む）実行中の状態を解除する
+struct ChartGenerationGuard<'a>(&'a ChartGeneration);
+
+impl Drop for ChartGenerationGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.cancel_tx.lock().unwrap() = None;
+    }
+}
+
+/// LLMが出力する1ノート
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratedNote {
+    pub key: usize,
+    pub beat: f64,
+}
+
+/// プロンプトに渡す小節内のノート（beatは1始まりの拍位置）
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct QuantizedNote {
+    pub beat: f64,
+    pub pitch: f64,
+    pub velocity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<DrumKind>,
+    /// 音高区間の長さ（拍数）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct BarStemNotes {
+    pub drums: Vec<QuantizedNote>,
+    pub bass: Vec<QuantizedNote>,
+    pub vocals: Vec<QuantizedNote>,
+    pub other: Vec<QuantizedNote>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BarData {
+    pub bar: Bar,
+    pub notes: BarStemNotes,
+}
+
+impl BarData {
+    /// プロンプトと応答で使う小節番号（1始まり）
+    pub fn number(&self) -> usize {
+        self.bar.index + 1
+    }
+}
+
+/// stemNotesとテンポ情報からLLMで譜面を生成する
+///
+/// 進行状況はgenerate_chart_progress、LLMの応答はgenerate_chart_token、
+/// 検証を通った小節はgenerate_chart_barイベントで随時通知する。
+#[tauri::command]
+pub async fn generate_chart(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ChartGeneration>,
+    provider: ChatProvider,
+    music_tempo_list: Vec<TempoEvent>,
+    stem_notes: StemNotes,
+    options: ChartGenerationOptions,
+    chat_options: Option<ChatOptions>,
+) -> Result<GeneratedChart, String> {
+    validate_options(&options)?;
+    let client = ChatClient::new(&app_handle, provider, chat_options.unwrap_or_default())?;
+    let (_guard, mut cancel_rx) = state.start()?;
+    let on_event = |event: GenerationEvent| emit_generation_event(&app_handle, event);
+
+    tokio::select! {
+        result = run_generation(&client, music_tempo_list, &stem_notes, options, &on_event) => result,
+        _ = wait_cancelled(&mut cancel_rx) => {
+            log::info!("Chart generation cancelled");
+            Err("Chart generation was cancelled".to_string())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn cancel_chart_generation(state: tauri::State<'_, ChartGeneration>) -> bool {
+    state.cancel()
+}
+
+async fn run_generation(
+    client: &ChatClient,
+    music_tempo_list: Vec<TempoEvent>,
+    stem_notes: &StemNotes,
+    options: ChartGenerationOptions,
+    on_event: &(dyn Fn(GenerationEvent) + Sync),
+) -> Result<GeneratedChart, String> {
+    let tempo_map = TempoMap::new(music_tempo_list);
+    let bars = tempo_map.bars();
+    if bars.is_empty() {
+        return Err("musicTempoList is empty".to_string());
+    }
+
+    let bar_data = quantize_stem_notes(&bars, stem_notes);
+    let total_bars = bar_data.len();
+    log::info!("Generating chart for {} bars with notes", total_bars);
+
+    let mut events: Vec<ChartEvent> = Vec::new();
+    let mut failed_bars: Vec<usize> = Vec::new();
+    let mut processed_bars = 0;
+    let mut usage = Usage::default();
+    let mut last_error: Option<String> = None;
+
+    for batch in bar_data.chunks(options.bars_per_batch.max(1)) {
+        let progress = ChartGenerationProgress {
+            start_bar: batch[0].number(),
+            end_bar: batch[batch.len() - 1].number(),
+            processed_bars,
+            total_bars,
+        };
+        on_event(GenerationEvent::Progress(progress));
+
+        // 1バッチの失敗で全体を止めず、そのバッチで生成できた小節だけ残して続ける
+        let mut generated = BTreeMap::new();
+        if let Err(e) = generate_batch(
+            client,
+            batch,
+            &options,
+            on_event,
+            &mut generated,
+            &mut usage,
+        )
+        .await
+        {
+            // モデルがない場合は残りのバッチも失敗するのでここで止める
+            if e.starts_with(ollama::MODEL_NOT_FOUND) {
+                return Err(e);
+            }
+            log::warn!(
+                "Failed to generate bars {}-{}: {}",
+                batch[0].number(),
+                batch[batch.len() - 1].number(),
+                e
+            );
+            last_error = Some(e);
+        }
+        for data in batch {
+            match generated.remove(&data.number()) {
+                Some(bar_events) => events.extend(bar_events),
+                None => failed_bars.push(data.number()),
+            }
+        }
+        processed_bars += batch.len();
+    }
+
+    if !failed_bars.is_empty() {
+        log::warn!("No valid chart was generated for bars {:?}", failed_bars);
+    }
+    // どの小節も生成できなかった場合は空の譜面ではなくエラーにする
+    if failed_bars.len() == total_bars {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+
+    events.sort_by_key(ChartEvent::sort_key);
+
+    log::info!(
+        "Generated {} notes (prompt {:?}, completion {:?} tokens, {}ms)",
+        events.len(),
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.latency_ms
+    );
+
+    Ok(GeneratedChart {
+        chart: Chart {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            events,
+            lane_number: options.key_count,
+            label: options.label,
+            level: 1,
+        },
+        usage,
+        failed_bars,
+    })
+}
+
+pub(crate) fn validate_options(options: &ChartGenerationOptions) -> Result<(), String> {
+    if options.key_count == 0 {
+        return Err("keyCount must be at least 1".to_string());
+    }
+    if options.enabled_keys.len() != options.key_count {
+        return Err(format!(
+            "enabledKeys has {} entries but keyCount is {}",
+            options.enabled_keys.len(),
+            options.key_count
+        ));
+    }
+    if options.key_types.len() < options.key_count {
+        return Err(format!(
+            "keyTypes has {} entries but keyCount is {}",
+            options.key_types.len(),
+            options.key_count
+        ));
+    }
+    if !options.enabled_keys.contains(&true) {
+        return Err("No keys are enabled".to_string());
+    }
+    Ok(())
+}
+
+/// 1バッチ分をストリーミングで生成し、小節ごとのイベントをacceptedに加える
+///
+/// 小節のJSONが閉じた時点で検証し、通ったものはgenerate_chart_barで通知する。
+/// 不正な小節だけを最大MAX_ATTEMPTS回まで生成し直し、それでも不正な小節は含めない。
+/// 通信エラーで終わった場合も、それまでに通知した小節はacceptedに残る。
+async fn generate_batch(
+    client: &ChatClient,
+    batch: &[BarData],
+    options: &ChartGenerationOptions,
+    on_event: &(dyn Fn(GenerationEvent) + Sync),
+    accepted: &mut BTreeMap<usize, Vec<ChartEvent>>,
+    usage: &mut Usage,
+) -> Result<(), String> {
+    let mut messages = vec![Message::user(create_prompt(batch, options))];
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let pending: Vec<BarData> = batch
+            .iter()
+            .filter(|data| !accepted.contains_key(&data.number()))
+            .cloned()
+            .collect();
+        let schema = response_schema(&pending, options);
+
+        // 検証を通った小節をイベントにして通知する
+        let accept = |key: &str,
+                      value: &Value,
+                      accepted: &mut BTreeMap<usize, Vec<ChartEvent>>|
+         -> Result<(), SchemaError> {
+            let (number, notes) = parse_bar(&schema, key, value, options)?;
+            let Some(data) = pending.iter().find(|data| data.number() == number) else {
+                return Ok(());
+            };
+            if accepted.contains_key(&number) {
+                return Ok(());
+            }
+            let events = chart_events(&data.bar, &notes);
+            on_event(GenerationEvent::Bar(ChartBarGenerated {
+                bar: number,
+                events: events.clone(),
+            }));
+            accepted.insert(number, events);
+            Ok(())
+        };
+
+        let mut members = ObjectMemberStream::new();
+        let mut bar_error: Option<SchemaError> = None;
+        let reply = client
+            .send_stream(&messages, Some(&schema), &mut |text| {
+                on_event(GenerationEvent::Token(text));
+                for (key, raw) in members.push(text) {
+                    let result = serde_json::from_str::<Value>(&raw)
+                        .map_err(|e| SchemaError {
+                            path: format!("/{}", key),
+                            message: format!("invalid JSON: {}", e),
+                        })
+                        .and_then(|value| accept(&key, &value, accepted));
+                    if let Err(e) = result {
+                        bar_error.get_or_insert(e);
+                    }
+                }
+            })
+            .await?;
+        usage.add(reply.usage);
+        let response = reply.content;
+
+        // 途中で取り出せなかった小節がないか、応答全体でも確認する
+        let mut error = bar_error.map(|e| e.to_string());
+        match language_model::parse_structured::<Map<String, Value>>(response.clone(), &schema) {
+            Ok(object) => {
+                for (key, value) in &object {
+                    if let Err(e) = accept(key, value, accepted) {
+                        error.get_or_insert(e.to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                error.get_or_insert(e.to_string());
+            }
+        }
+
+        let remaining: Vec<String> = pending
+            .iter()
+            .map(BarData::number)
+            .filter(|number| !accepted.contains_key(number))
+            .map(|number| number.to_string())
+            .collect();
+        if remaining.is_empty() {
+            break;
+        }
+        let error = error.unwrap_or_else(|| format!("missing bars {}", remaining.join(", ")));
+
+        log::warn!(
+            "Invalid response for bars {} (attempt {}/{}): {}",
+            remaining.join(", "),
+            attempt,
+            MAX_ATTEMPTS,
+            error
+        );
+        // エラー内容を伝えて、不正だった小節だけ出力し直してもらう
+        messages.push(Message {
+            role: Role::Assistant,
+            content: response,
+        });
+        messages.push(Message::user(format!(
+            "Your output was invalid: {}\nOutput a corrected JSON object containing only bars {}.",
+            error,
+            remaining.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// 小節1つ分の値を検証し、小節番号とノートを返す
+fn parse_bar(
+    schema: &Value,
+    key: &str,
+    value: &Value,
+    options: &ChartGenerationOptions,
+) -> Result<(usize, Vec<GeneratedNote>), SchemaError> {
+    let path = format!("/{}", key.replace('~', "~0").replace('/', "~1"));
+    let error = |message: String| SchemaError {
+        path: path.clone(),
+        message,
+    };
+
+    let Some(bar_schema) = schema["properties"].get(key) else {
+        return Err(error("unexpected property".to_string()));
+    };
+    json_schema::validate(bar_schema, value).map_err(|e| SchemaError {
+        path: format!("{}{}", path, e.path),
+        message: e.message,
+    })?;
+    let notes: Vec<GeneratedNote> =
+        serde_json::from_value(value.clone()).map_err(|e| error(e.to_string()))?;
+    check_bar_notes(&path, &notes, options)?;
+
+    // キーはスキーマで小節番号に限定している
+    let number = key
+        .parse()
+        .map_err(|_| error("not a bar number".to_string()))?;
+    Ok((number, notes))
+}
+
+/// 小節内のノートをイベントに変換する
+pub(crate) fn chart_events(bar: &Bar, notes: &[GeneratedNote]) -> Vec<ChartEvent> {
+    let mut events: Vec<ChartEvent> = notes
+        .iter()
+        .map(|note| ChartEvent::SingleNote {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            position: note_position_ns(bar, note.beat),
+            lane: note.key - 1,
+        })
+        .collect();
+    events.sort_by_key(ChartEvent::sort_key);
+    events
+}
+
+/// stemNotesを小節ごとに分け、小節内の拍位置を16分音符でクオンタイズする
+///
+/// ノートのない小節は含めない。
+pub(crate) fn quantize_stem_notes(bars: &[Bar], stem_notes: &StemNotes) -> Vec<BarData> {
+    type Target = fn(&mut BarStemNotes) -> &mut Vec<QuantizedNote>;
+    let instruments: [(&[StemNote], Target); 4] = [
+        (&stem_notes.drums, |n| &mut n.drums),
+        (&stem_notes.bass, |n| &mut n.bass),
+        (&stem_notes.vocals, |n| &mut n.vocals),
+        (&stem_notes.other, |n| &mut n.other),
+    ];
+
+    let mut by_bar: BTreeMap<usize, BarStemNotes> = BTreeMap::new();
+    for (notes, target) in instruments {
+        for note in notes {
+            // velocityが0のノートは除外
+            if note.velocity == 0.0 {
+                continue;
+            }
+
+            let time_ns = seconds_to_ns(note.time);
+            let index = bars.partition_point(|b| b.end_ns() <= time_ns);
+            let Some(bar) = bars.get(index) else {
+                continue;
+            };
+
+            let beat_seconds = ns_to_seconds(bar.beat_length_ns());
+            let beat =
+                quantize_beat(1.0 + (time_ns - bar.start_ns) as f64 / bar.beat_length_ns() as f64);
+            // 小節の範囲内に収める（1拍目～beats拍目）
+            if beat > bar.beats as f64 {
+                continue;
+            }
+
+            target(by_bar.entry(bar.index).or_default()).push(QuantizedNote {
+                beat,
+                pitch: note.pitch,
+                velocity: note.velocity,
+                kind: note.kind,
+                length: note
+                    .end
+                    .map(|end| quantize_beat((end - note.time) / beat_seconds)),
+            });
+        }
+    }
+
+    by_bar
+        .into_iter()
+        .map(|(index, mut notes)| {
+            for list in [
+                &mut notes.drums,
+                &mut notes.bass,
+                &mut notes.vocals,
+                &mut notes.other,
+            ] {
+                list.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+            }
+            BarData {
+                bar: bars[index].clone(),
+                notes,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn quantize_beat(beat: f64) -> f64 {
+    (beat * STEPS_PER_BEAT).round() / STEPS_PER_BEAT
+}
+
+/// 小節内の拍位置（1始まり）をナノ秒位置に変換する
+pub(crate) fn note_position_ns(bar: &Bar, beat: f64) -> u64 {
+    let steps = ((beat - 1.0) * STEPS_PER_BEAT).round().max(0.0) as u64;
+    bar.start_ns + bar.beat_length_ns() * steps / STEPS_PER_BEAT as u64
+}
+
+/// プロンプトに書く有効な鍵盤の一覧（"Key1(white), Key2(black)"の形式と番号のみの形式）
+pub(crate) fn enabled_key_info(options: &ChartGenerationOptions) -> (String, String) {
+    let enabled: Vec<usize> = (0..options.key_count)
+        .filter(|&i| options.enabled_keys[i])
+        .collect();
+    let info = enabled
+        .iter()
+        .map(|&i| {
+            let key_type = match options.key_types[i] {
+                KeyType::White => "white",
+                KeyType::Black => "black",
+            };
+            format!("Key{}({})", i + 1, key_type)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let numbers = enabled
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    (info, numbers)
+}
+
+fn create_prompt(batch: &[BarData], options: &ChartGenerationOptions) -> String {
+    let (enabled_key_info, enabled_numbers) = enabled_key_info(options);
+
+    let bars_text = batch
+        .iter()
+        .map(|data| {
+            format!(
+                "Bar {} ({}/4, {} BPM):\n{}",
+                data.number(),
+                data.bar.beats,
+                data.bar.tempo,
+                serde_json::to_string_pretty(&data.notes).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let bar_numbers = batch
+        .iter()
+        .map(|data| data.number().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let example_bar = batch[0].number();
+
+    let mut rules = vec![
+        "Output ONLY a valid JSON object with bar numbers as keys, each containing an array of notes.".to_string(),
+        format!(
+            "Format: {{\"{}\": [{{\"key\": 1, \"beat\": 1.0}}, {{\"key\": 3, \"beat\": 1.5}}]}}",
+            example_bar
+        ),
+        format!("Include every bar: {}", bar_numbers),
+        format!("Key numbers range from 1 to {}", options.key_count),
+        "Beat positions must be quantized to 16th notes (0.25 increments): 1.0, 1.25, 1.5, 1.75, 2.0, etc.".to_string(),
+        "Beat positions must be within 1.0 to the respective bar's beat count".to_string(),
+        format!("Only use enabled keys: {}", enabled_numbers),
+    ];
+    if !options.allow_simultaneous_white_black {
+        rules.push(
+            "Do not place white and black keys simultaneously at the same beat position"
+                .to_string(),
+        );
+    }
+    rules.push(
+        "Multiple notes can have the same beat position if they use different keys".to_string(),
+    );
+    rules.push("Ensure all JSON syntax is correct (proper commas, brackets, quotes)".to_string());
+    let rules_text = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| format!("{}. {}", i + 1, rule))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let custom_instructions = if options.custom_instructions.trim().is_empty() {
+        String::new()
+    } else {
+        format!("CUSTOM INSTRUCTIONS:\n{}\n\n", options.custom_instructions)
+    };
+
+    format!(
+        r#"You are a professional rhythm game chart designer. Create charts for bars {bar_numbers}.
+
+CONFIGURATION:
+- Total keys: {key_count}
+- Available keys: {enabled_key_info}
+- White/Black key simultaneous press: {simultaneous}
+
+STEM AUDIO DATA:
+{bars_text}
+
+RULES:
+{rules_text}
+
+DESIGN PHILOSOPHY:
+- Follow the rhythm and intensity of the stem audio data
+- Place notes where drums, bass, vocals, or other instruments have strong beats
+- Drum notes have a "kind" (kick, snare, hihat, tom, cymbal); keep kicks and snares on consistent key groups
+- Create engaging patterns that match the musical flow
+- Balance difficulty appropriately
+- Consider musical continuity between bars
+
+{custom_instructions}IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks, no other text:"#,
+        key_count = options.key_count,
+        simultaneous = if options.allow_simultaneous_white_black {
+            "Allowed"
+        } else {
+            "Not allowed"
+        },
+    )
+}
+
+/// 応答のJSON Schema（小節番号をキーとしたノートの配列）
+fn response_schema(batch: &[BarData], options: &ChartGenerationOptions) -> Value {
+    let enabled_keys: Vec<usize> = (1..=options.key_count)
+        .filter(|&key| options.enabled_keys[key - 1])
+        .collect();
+
+    let properties: Map<String, Value> = batch
+        .iter()
+        .map(|data| {
+            let notes = json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "key": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": options.key_count,
+                            "enum": enabled_keys,
+                        },
+                        "beat": {
+                            "type": "number",
+                            "minimum": 1.0,
+                            "maximum": data.bar.beats,
+                            "multipleOf": 1.0 / STEPS_PER_BEAT,
+                        },
+                    },
+                    "required": ["key", "beat"],
+                    "additionalProperties": false,
+                },
+            });
+            (data.number().to_string(), notes)
+        })
+        .collect();
+    let required: Vec<String> = batch.iter().map(|data| data.number().to_string()).collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// スキーマで表せない制約（同じ鍵盤の重複、白鍵と黒鍵の同時押し）を検証する
+///
+/// pathは小節のJSON Pointer（例: "/3"）。
+pub(crate) fn check_bar_notes(
+    path: &str,
+    notes: &[GeneratedNote],
+    options: &ChartGenerationOptions,
+) -> Result<(), SchemaError> {
+    let mut seen = HashSet::new();
+    let mut types_at_step: BTreeMap<u64, KeyType> = BTreeMap::new();
+
+    for (i, note) in notes.iter().enumerate() {
+        let error = |message: String| SchemaError {
+            path: format!("{}/{}", path, i),
+            message,
+        };
+        let step = ((note.beat - 1.0) * STEPS_PER_BEAT).round() as u64;
+
+        if !seen.insert((note.key, step)) {
+            return Err(error(format!(
+                "key {} is used twice at beat {}",
+                note.key, note.beat
+            )));
+        }
+
+        if !options.allow_simultaneous_white_black {
+            let key_type = options.key_types[note.key - 1];
+            if types_at_step
+                .get(&step)
+                .is_some_and(|&other| other != key_type)
+            {
+                return Err(error(format!(
+                    "white and black keys are pressed together at beat {}",
+                    note.beat
+                )));
+            }
+            types_at_step.insert(step, key_type);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(time: f64) -> StemNote {
+        StemNote {
+            pitch: 60.0,
+            velocity: 0.8,
+            time,
+            kind: None,
+            end: None,
+        }
+    }
+
+    /// 120BPM・4/4で4小節（1小節2秒）
+    fn tempo_list() -> Vec<TempoEvent> {
+        vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 4.0,
+        }]
+    }
+
+    fn options() -> ChartGenerationOptions {
+        ChartGenerationOptions {
+            key_count: 4,
+            enabled_keys: vec![true; 4],
+            allow_simultaneous_white_black: false,
+            key_types: vec![
+                KeyType::White,
+                KeyType::Black,
+                KeyType::White,
+                KeyType::Black,
+            ],
+            custom_instructions: String::new(),
+            bars_per_batch: 2,
+            label: "test".to_string(),
+        }
+    }
+
+    fn mock_client(script: Option<&std::path::Path>) -> ChatClient {
+        let provider = ChatProvider::Mock {
+            script: script.map(|path| path.to_string_lossy().to_string()),
+        };
+        ChatClient::without_cache(provider, ChatOptions::default())
+    }
+
+    /// 生成中の通知を種類ごとに記録する
+    #[derive(Default)]
+    struct Recorded {
+        progress: Vec<(usize, usize)>,
+        bars: Vec<usize>,
+        tokens: String,
+    }
+
+    async fn generate(client: &ChatClient) -> (Result<GeneratedChart, String>, Recorded) {
+        let stem_notes = StemNotes {
+            drums: vec![note(0.0), note(0.5)],
+            bass: vec![note(2.0)],
+            vocals: vec![note(4.25)],
+            ..Default::default()
+        };
+        let recorded = Mutex::new(Recorded::default());
+        let on_event = |event: GenerationEvent| {
+            let mut recorded = recorded.lock().unwrap();
+            match event {
+                GenerationEvent::Progress(progress) => recorded
+                    .progress
+                    .push((progress.start_bar, progress.end_bar)),
+                GenerationEvent::Bar(generated) => recorded.bars.push(generated.bar),
+                GenerationEvent::Token(text) => recorded.tokens.push_str(text),
+            }
+        };
+        let result = run_generation(client, tempo_list(), &stem_notes, options(), &on_event).await;
+        (result, recorded.into_inner().unwrap())
+    }
+
+    #[test]
+    fn quantize_skips_bars_without_notes() {
+        let bars = TempoMap::new(tempo_list()).bars();
+        let stem_notes = StemNotes {
+            drums: vec![note(0.0), note(0.5), note(6.0)],
+            bass: vec![note(2.1)],
+            ..Default::default()
+        };
+
+        let bar_data = quantize_stem_notes(&bars, &stem_notes);
+        let numbers: Vec<usize> = bar_data.iter().map(BarData::number).collect();
+        assert_eq!(numbers, [1, 2, 4]);
+        let beats: Vec<f64> = bar_data[0].notes.drums.iter().map(|n| n.beat).collect();
+        assert_eq!(beats, [1.0, 2.0]);
+        // 2.1秒は2小節目の1.2拍目なので1.25拍目になる
+        assert_eq!(bar_data[1].notes.bass[0].beat, 1.25);
+    }
+
+    #[test]
+    fn song_bars_cover_the_latest_end() {
+        let tempo_map = TempoMap::new(tempo_list());
+        let stem_notes = StemNotes {
+            vocals: vec![StemNote {
+                end: Some(9.5),
+                ..note(9.0)
+            }],
+            ..Default::default()
+        };
+
+        // ステムの最後の音高区間（9.5秒）は5小節目
+        assert_eq!(song_bars(&tempo_map, None, &stem_notes, &[]).len(), 5);
+        // 曲の長さがそれより長ければ曲の最後まで
+        assert_eq!(song_bars(&tempo_map, Some(16.0), &stem_notes, &[]).len(), 8);
+    }
+
+    #[tokio::test]
+    async fn generates_through_mock_and_retries_invalid_bars() {
+        let dir =
+            std::env::temp_dir().join(format!("souon-chart-generation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script.json");
+        // 2小節目は白鍵と黒鍵の同時押しで不正、再生成で正しくなる。3小節目は鍵盤の範囲外で毎回不正。
+        std::fs::write(
+            &script,
+            json!({
+                "rules": [
+                    { "contains": "only bars 2.", "reply": { "2": [{ "key": 3, "beat": 1.0 }] } },
+                    { "contains": "only bars 3.", "reply": { "3": [{ "key": 5, "beat": 1.0 }] } },
+                    {
+                        "contains": "Create charts for bars 1, 2.",
+                        "reply": {
+                            "1": [{ "key": 1, "beat": 1.0 }, { "key": 3, "beat": 2.0 }],
+                            "2": [{ "key": 1, "beat": 1.0 }, { "key": 2, "beat": 1.0 }]
+                        }
+                    },
+                    { "contains": "Create charts for bars 3.", "reply": { "3": [{ "key": 5, "beat": 1.0 }] } }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let (result, recorded) = generate(&mock_client(Some(&script))).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        let generated = result.unwrap();
+
+        assert_eq!(generated.failed_bars, [3]);
+        let notes: Vec<(u64, usize)> = generated
+            .chart
+            .events
+            .iter()
+            .map(|event| (event.position(), event.lane().unwrap()))
+            .collect();
+        assert_eq!(notes, [(0, 0), (500_000_000, 2), (2_000_000_000, 2)]);
+        assert_eq!(generated.chart.lane_number, 4);
+        assert_eq!(generated.chart.label, "test");
+        assert!(generated.usage.completion_tokens.is_some());
+
+        assert_eq!(recorded.progress, [(1, 2), (3, 3)]);
+        assert_eq!(recorded.bars, [1, 2]);
+        assert!(recorded.tokens.contains("\"1\""));
+    }
+
+    #[tokio::test]
+    async fn fails_when_every_batch_fails() {
+        let missing = std::env::temp_dir().join("souon-chart-generation-missing.json");
+        let (result, recorded) = generate(&mock_client(Some(&missing))).await;
+
+        let error = result.unwrap_err();
+        assert!(error.starts_with("Failed to read mock script"), "{}", error);
+        // 1バッチ目の失敗で止めずに最後まで試す
+        assert_eq!(recorded.progress, [(1, 2), (3, 3)]);
+        assert!(recorded.bars.is_empty());
+    }
+}
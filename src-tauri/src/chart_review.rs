@@ -0,0 +1,443 @@
+use crate::chart_generation::{self, StemNotes, MAX_ATTEMPTS};
+use crate::language_model::{self, ChatClient, ChatOptions, ChatProvider, Message, Role, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use souon_core::sof::ChartEvent;
+use souon_core::tempo::{Bar, TempoEvent, TempoMap};
+use std::collections::BTreeMap;
+
+// 既存の譜面のレビュー
+//
+// 譜面を小節ごとの特徴（密度、使っているレーン、ロングノートの割合、縦連、同時押し）と
+// ステムの発音数にまとめてLLMに渡し、問題のある小節・難易度の急な変化・修正案を
+// 小節に紐づいたコメントとして返してもらう。
+
+/// 小節ごとのステムのノート数
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StemCounts {
+    pub drums: usize,
+    pub bass: usize,
+    pub vocals: usize,
+    pub other: usize,
+}
+
+/// 小節ごとの譜面の特徴
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarFeatures {
+    /// 小節番号（1始まり）
+    pub bar: usize,
+    pub beats: u32,
+    pub tempo: f64,
+    /// ノート数（ロングノートは始点で数える）
+    pub notes: usize,
+    /// 1拍あたりのノート数
+    pub density: f64,
+    /// 使っているレーン（1始まり）
+    pub lanes: Vec<usize>,
+    pub long_notes: usize,
+    /// ロングノートが押されている時間の割合（0～1）
+    pub long_note_coverage: f64,
+    /// 直前の同時押し（または単音）から1拍以内に同じレーンに続けて置かれたノート数
+    pub jacks: usize,
+    /// 2つ以上のノートを同時に押す箇所の数
+    pub chords: usize,
+    pub max_chord_size: usize,
+    pub stems: StemCounts,
+}
+
+/// レビューコメントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReviewCategory {
+    /// 音楽と合っていない、配置が不自然などの問題
+    Problem,
+    /// 前後に比べて急に難しくなる箇所
+    DifficultySpike,
+    /// 改善の提案
+    Suggestion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewSeverity {
+    Low,
+    Medium,
+    High,
+}
+
+/// 小節に紐づいたコメント（bar～endBarの範囲、両端を含む）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ReviewComment {
+    pub bar: usize,
+    pub end_bar: usize,
+    pub category: ReviewCategory,
+    pub severity: ReviewSeverity,
+    pub message: String,
+    /// 修正案
+    pub fix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReviewResponse {
+    summary: String,
+    comments: Vec<ReviewComment>,
+}
+
+/// review_chartの戻り値
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartReview {
+    /// 譜面全体の講評
+    pub summary: String,
+    /// 小節順に並べたコメント
+    pub comments: Vec<ReviewComment>,
+    /// LLMに渡した小節ごとの特徴
+    pub bars: Vec<BarFeatures>,
+    pub usage: Usage,
+}
+
+/// 譜面をレビューし、小節に紐づいたコメントを返す
+///
+/// eventsはSingleNoteとLongNoteのみ。laneNumberは譜面のレーン数。
+/// 小節は曲の長さ（秒）とステムのノートから曲の最後まで用意する。
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn review_chart(
+    app_handle: tauri::AppHandle,
+    provider: ChatProvider,
+    music_tempo_list: Vec<TempoEvent>,
+    music_length: Option<f64>,
+    stem_notes: StemNotes,
+    events: Vec<ChartEvent>,
+    lane_number: usize,
+    chat_options: Option<ChatOptions>,
+) -> Result<ChartReview, String> {
+    let tempo_map = TempoMap::new(music_tempo_list);
+    if tempo_map.is_empty() {
+        return Err("musicTempoList is empty".to_string());
+    }
+    if events.is_empty() {
+        return Err("Chart has no notes".to_string());
+    }
+    let bars = chart_generation::song_bars(&tempo_map, music_length, &stem_notes, &events);
+    let features = bar_features(&bars, &events, &stem_notes);
+    let client = ChatClient::new(&app_handle, provider, chat_options.unwrap_or_default())?;
+
+    let schema = response_schema(bars.len());
+    let mut messages = vec![Message::user(create_prompt(&features, lane_number))];
+    let mut usage = Usage::default();
+    let mut last_error = String::new();
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let reply = client.send(&messages, Some(&schema)).await?;
+        usage.add(reply.usage);
+        let response = reply.content;
+
+        let error =
+            match language_model::parse_structured::<ReviewResponse>(response.clone(), &schema) {
+                Ok(review) => match check_comments(&review.comments) {
+                    Ok(()) => {
+                        let mut comments = review.comments;
+                        comments.sort_by_key(|comment| (comment.bar, comment.end_bar));
+                        log::info!(
+                            "Reviewed chart: {} comments for {} bars ({}ms)",
+                            comments.len(),
+                            features.len(),
+                            usage.latency_ms
+                        );
+                        return Ok(ChartReview {
+                            summary: review.summary,
+                            comments,
+                            bars: features,
+                            usage,
+                        });
+                    }
+                    Err(e) => e,
+                },
+                Err(e) => e.to_string(),
+            };
+
+        log::warn!(
+            "Invalid chart review (attempt {}/{}): {}",
+            attempt,
+            MAX_ATTEMPTS,
+            error
+        );
+        messages.push(Message {
+            role: Role::Assistant,
+            content: response,
+        });
+        messages.push(Message::user(format!(
+            "Your output was invalid: {}\nOutput the corrected JSON object.",
+            error
+        )));
+        last_error = error;
+    }
+
+    Err(format!("Failed to review chart: {}", last_error))
+}
+
+/// スキーマで表せない制約（endBarがbar以上）を検証する
+fn check_comments(comments: &[ReviewComment]) -> Result<(), String> {
+    for (i, comment) in comments.iter().enumerate() {
+        if comment.end_bar < comment.bar {
+            return Err(format!(
+                "/comments/{}: endBar {} is before bar {}",
+                i, comment.end_bar, comment.bar
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 譜面とステムのノートを小節ごとの特徴にまとめる
+pub fn bar_features(
+    bars: &[Bar],
+    events: &[ChartEvent],
+    stem_notes: &StemNotes,
+) -> Vec<BarFeatures> {
+    let bar_index = |position: u64| bars.partition_point(|b| b.end_ns() <= position);
+
+    // 同時に押すノートをまとめた行（位置 -> レーン）
+    let mut rows: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    let mut long_spans: Vec<(u64, u64)> = Vec::new();
+    for event in events {
+        let (position, lane) = match event {
+            ChartEvent::SingleNote { position, lane, .. } => (*position, *lane),
+            ChartEvent::LongNote {
+                position,
+                lane,
+                end_position,
+                ..
+            } => {
+                long_spans.push((*position, *end_position));
+                (*position, *lane)
+            }
+            ChartEvent::SpeedChange { .. } => continue,
+        };
+        rows.entry(position).or_default().push(lane);
+    }
+    long_spans.sort_unstable();
+
+    let mut features: Vec<BarFeatures> = bars
+        .iter()
+        .map(|bar| BarFeatures {
+            bar: bar.index + 1,
+            beats: bar.beats,
+            tempo: bar.tempo,
+            notes: 0,
+            density: 0.0,
+            lanes: Vec::new(),
+            long_notes: 0,
+            long_note_coverage: long_note_coverage(bar, &long_spans),
+            jacks: 0,
+            chords: 0,
+            max_chord_size: 0,
+            stems: StemCounts::default(),
+        })
+        .collect();
+
+    let mut previous: Option<(u64, &Vec<usize>)> = None;
+    for (&position, lanes) in &rows {
+        let index = bar_index(position);
+        let Some(feature) = features.get_mut(index) else {
+            continue;
+        };
+        feature.notes += lanes.len();
+        feature.lanes.extend(lanes.iter().map(|lane| lane + 1));
+        if lanes.len() >= 2 {
+            feature.chords += 1;
+        }
+        feature.max_chord_size = feature.max_chord_size.max(lanes.len());
+        // 1拍より離れていれば続けて押すことにはならない
+        let beat_ns = bars[index].length_ns / u64::from(bars[index].beats.max(1));
+        if let Some((previous_position, previous_lanes)) = previous {
+            if position - previous_position <= beat_ns {
+                feature.jacks += lanes
+                    .iter()
+                    .filter(|lane| previous_lanes.contains(lane))
+                    .count();
+            }
+        }
+        previous = Some((position, lanes));
+    }
+    for &(position, _) in &long_spans {
+        if let Some(feature) = features.get_mut(bar_index(position)) {
+            feature.long_notes += 1;
+        }
+    }
+
+    for data in chart_generation::quantize_stem_notes(bars, stem_notes) {
+        features[data.bar.index].stems = StemCounts {
+            drums: data.notes.drums.len(),
+            bass: data.notes.bass.len(),
+            vocals: data.notes.vocals.len(),
+            other: data.notes.other.len(),
+        };
+    }
+
+    for feature in &mut features {
+        feature.lanes.sort_unstable();
+        feature.lanes.dedup();
+        feature.density = feature.notes as f64 / feature.beats.max(1) as f64;
+    }
+    features
+}
+
+/// 小節のうちロングノートが押されている時間の割合（重なりは1回と数える）
+///
+/// spansは始点の順に並べておく。
+fn long_note_coverage(bar: &Bar, spans: &[(u64, u64)]) -> f64 {
+    if bar.length_ns == 0 {
+        return 0.0;
+    }
+    let mut covered = 0;
+    let mut cursor = bar.start_ns;
+    for &(start, end) in spans.iter() {
+        let start = start.max(cursor);
+        let end = end.min(bar.end_ns());
+        if start < end {
+            covered += end - start;
+            cursor = end;
+        }
+    }
+    covered as f64 / bar.length_ns as f64
+}
+
+fn create_prompt(features: &[BarFeatures], lane_number: usize) -> String {
+    let bars_text = features
+        .iter()
+        .map(|feature| serde_json::to_string(feature).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"You are a professional rhythm game chart designer reviewing another designer's chart.
+
+CONFIGURATION:
+- Lanes: {lane_number}
+- Bars: {bar_count}
+
+FEATURES PER BAR (one JSON object per line):
+- notes: note count (long notes counted at their start), density: notes per beat
+- lanes: lanes used (1 to {lane_number})
+- longNotes: long notes starting in the bar, longNoteCoverage: fraction of the bar with a long note held
+- jacks: notes on the same lane as the previous note or chord within one beat
+- chords: positions with two or more simultaneous notes, maxChordSize: the largest of them
+- stems: note counts of each stem in the bar (the music's activity)
+
+{bars_text}
+
+TASK:
+1. Find problem bars: notes that do not follow the music (e.g. dense notes where the stems are quiet, or empty bars where the music is busy), awkward lane usage, uncomfortable jacks or chords.
+2. Find difficulty spikes: bars that are much harder than their neighbours without a musical reason.
+3. Suggest concrete fixes for each finding.
+
+RULES:
+1. Output ONLY a valid JSON object: {{"summary": "...", "comments": [{{"bar": 9, "endBar": 12, "category": "difficultySpike", "severity": "high", "message": "...", "fix": "..."}}]}}
+2. "bar" and "endBar" are the first and last bar of the finding (1 to {bar_count}); use the same number for a single bar
+3. "category" is "problem", "difficultySpike" or "suggestion"; "severity" is "low", "medium" or "high"
+4. Refer to specific bars and features; do not comment on bars without findings
+5. "summary" is a short overall assessment of the chart"#,
+        bar_count = features.len(),
+    )
+}
+
+/// 応答のJSON Schema
+fn response_schema(bar_count: usize) -> Value {
+    let bar = json!({ "type": "integer", "minimum": 1, "maximum": bar_count });
+    json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string" },
+            "comments": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "bar": bar,
+                        "endBar": bar,
+                        "category": {
+                            "type": "string",
+                            "enum": ["problem", "difficultySpike", "suggestion"],
+                        },
+                        "severity": { "type": "string", "enum": ["low", "medium", "high"] },
+                        "message": { "type": "string" },
+                        "fix": { "type": "string" },
+                    },
+                    "required": ["bar", "endBar", "category", "severity", "message", "fix"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["summary", "comments"],
+        "additionalProperties": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart_generation::StemNote;
+
+    /// 120BPM・4/4で4小節（1拍0.5秒）
+    fn tempo_map() -> TempoMap {
+        TempoMap::new(vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 4.0,
+        }])
+    }
+
+    fn single(position: u64, lane: usize) -> ChartEvent {
+        ChartEvent::SingleNote {
+            uuid: format!("{}-{}", position, lane),
+            position,
+            lane,
+        }
+    }
+
+    #[test]
+    fn jacks_count_only_repeats_within_a_beat() {
+        let bars = tempo_map().bars();
+        let events = vec![
+            single(0, 0),
+            // 半拍後の同じレーンは縦連
+            single(250_000_000, 0),
+            // 2拍空けた同じレーンは縦連ではない
+            single(1_250_000_000, 0),
+            // 同時押しのうち直前と同じレーンだけ数える
+            single(1_500_000_000, 0),
+            single(1_500_000_000, 1),
+        ];
+
+        let features = bar_features(&bars, &events, &StemNotes::default());
+        assert_eq!(features[0].jacks, 2);
+        assert_eq!(features[0].chords, 1);
+        assert_eq!(features[0].lanes, [1, 2]);
+    }
+
+    #[test]
+    fn bars_cover_the_stems_after_the_last_note() {
+        let stem_notes = StemNotes {
+            drums: vec![StemNote {
+                pitch: 36.0,
+                velocity: 0.8,
+                time: 13.0,
+                kind: None,
+                end: None,
+            }],
+            ..Default::default()
+        };
+        let bars = chart_generation::song_bars(&tempo_map(), None, &stem_notes, &[single(0, 0)]);
+
+        let features = bar_features(&bars, &[single(0, 0)], &stem_notes);
+        assert_eq!(features.len(), 7);
+        assert_eq!(features[6].notes, 0);
+        assert_eq!(features[6].stems.drums, 1);
+    }
+}
@@ -0,0 +1,876 @@
+use crate::chart_generation::{
+    self, BarStemNotes, ChartGenerationOptions, GeneratedNote, StemNotes, MAX_ATTEMPTS,
+    STEPS_PER_BEAT,
+};
+use crate::language_model::{ChatClient, ChatOptions, ChatProvider, Message, Role, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use souon_core::sof::ChartEvent;
+use souon_core::tempo::{Bar, TempoEvent, TempoMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+// 対話による譜面の調整
+//
+// start_chart_sessionでシステムプロンプト・譜面・ステムの情報を持つセッションを作り、
+// refine_chartで「9～16小節をもっと薄くして」のような指示を送る。LLMは小節ごとのノートの
+// 追加と削除を返し、レーンと拍の規則を検証してから譜面に適用する（不正なら作り直してもらう）。
+//
+// 会話の履歴には指示と応答だけを残し、現在の譜面は最新の指示と一緒に毎回送る。
+
+/// セッション内のノート（beatは小節内の1始まりの拍位置、lengthはロングノートの拍数）
+#[derive(Debug, Clone)]
+struct SessionNote {
+    uuid: String,
+    key: usize,
+    beat: f64,
+    length: Option<f64>,
+}
+
+impl SessionNote {
+    fn step(&self) -> u64 {
+        beat_step(self.beat)
+    }
+}
+
+/// プロンプトに書く現在のノート
+#[derive(Serialize)]
+struct PromptNote {
+    key: usize,
+    beat: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<f64>,
+}
+
+/// 追加するノート（lengthが0ならタップノート）
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddedNote {
+    key: usize,
+    beat: f64,
+    length: f64,
+}
+
+/// 削除するノート（キーと拍位置で指定する）
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemovedNote {
+    key: usize,
+    beat: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BarDiff {
+    bar: usize,
+    add: Vec<AddedNote>,
+    remove: Vec<RemovedNote>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RefinementResponse {
+    changes: Vec<BarDiff>,
+    comment: String,
+}
+
+/// 1小節分の変更（フロントエンドはremovedのuuidを削除し、addedを追加する）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BarChange {
+    /// 小節番号（1始まり）
+    pub bar: usize,
+    pub added: Vec<ChartEvent>,
+    pub removed: Vec<String>,
+}
+
+/// refine_chartの戻り値
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartRefinement {
+    pub changes: Vec<BarChange>,
+    /// 変更内容についてのLLMの説明
+    pub comment: String,
+    pub usage: Usage,
+}
+
+struct ChartSession {
+    client: ChatClient,
+    options: ChartGenerationOptions,
+    tempo_map: TempoMap,
+    bars: Vec<Bar>,
+    /// 小節番号（1始まり）ごとのステムのノート（システムプロンプトを作り直すときに使う）
+    stem_bars: BTreeMap<usize, BarStemNotes>,
+    /// 小節番号（1始まり）ごとのノート
+    notes: BTreeMap<usize, Vec<SessionNote>>,
+    /// システムプロンプトと、これまでの指示と応答
+    history: Vec<Message>,
+}
+
+/// 開いているセッション（IDはuuid）
+#[derive(Default)]
+pub struct ChartSessions {
+    sessions: Mutex<HashMap<String, Arc<tokio::sync::Mutex<ChartSession>>>>,
+}
+
+/// 譜面を調整するセッションを開始し、セッションIDを返す
+///
+/// eventsは調整する譜面のイベント（SingleNoteとLongNote以外は無視する）。
+/// 小節は曲の長さ（秒）とステムのノートから曲の最後まで用意する。
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn start_chart_session(
+    app_handle: tauri::AppHandle,
+    sessions: tauri::State<'_, ChartSessions>,
+    provider: ChatProvider,
+    music_tempo_list: Vec<TempoEvent>,
+    music_length: Option<f64>,
+    stem_notes: StemNotes,
+    events: Vec<ChartEvent>,
+    options: ChartGenerationOptions,
+    chat_options: Option<ChatOptions>,
+) -> Result<String, String> {
+    chart_generation::validate_options(&options)?;
+    let tempo_map = TempoMap::new(music_tempo_list);
+    if tempo_map.is_empty() {
+        return Err("musicTempoList is empty".to_string());
+    }
+    let bars = chart_generation::song_bars(&tempo_map, music_length, &stem_notes, &events);
+    let client = ChatClient::new(&app_handle, provider, chat_options.unwrap_or_default())?;
+
+    let stem_bars = chart_generation::quantize_stem_notes(&bars, &stem_notes)
+        .into_iter()
+        .map(|data| (data.number(), data.notes))
+        .collect();
+    let session = ChartSession {
+        client,
+        notes: session_notes(&bars, &events, options.key_count),
+        history: vec![Message {
+            role: Role::System,
+            content: create_system_prompt(&bars, &stem_bars, &options),
+        }],
+        options,
+        tempo_map,
+        bars,
+        stem_bars,
+    };
+
+    let id = uuid::Uuid::new_v4().to_string();
+    log::info!(
+        "Started chart session {} ({} bars, {} notes)",
+        id,
+        session.bars.len(),
+        session.notes.values().map(Vec::len).sum::<usize>()
+    );
+    sessions
+        .sessions
+        .lock()
+        .unwrap()
+        .insert(id.clone(), Arc::new(tokio::sync::Mutex::new(session)));
+    Ok(id)
+}
+
+/// 指示に従って譜面を変更する
+///
+/// eventsを指定すると、セッションの譜面をそれで置き換えてから指示を送る
+/// （セッション開始後にエディタで編集した場合）。
+#[tauri::command]
+pub async fn refine_chart(
+    sessions: tauri::State<'_, ChartSessions>,
+    session_id: String,
+    instruction: String,
+    events: Option<Vec<ChartEvent>>,
+) -> Result<ChartRefinement, String> {
+    if instruction.trim().is_empty() {
+        return Err("Instruction is empty".to_string());
+    }
+    let session = sessions
+        .sessions
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .cloned()
+        .ok_or_else(|| format!("Chart session not found: {}", session_id))?;
+    let mut session = session.lock().await;
+    if let Some(events) = events {
+        session.replace_events(&events);
+    }
+    session.refine(&instruction).await
+}
+
+/// セッションを終了する（存在しなければfalse）
+#[tauri::command]
+pub fn end_chart_session(sessions: tauri::State<'_, ChartSessions>, session_id: String) -> bool {
+    sessions
+        .sessions
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .is_some()
+}
+
+impl ChartSession {
+    /// 譜面を置き換える（最後の小節より後にノートがあれば、そこまで小節を延ばす）
+    fn replace_events(&mut self, events: &[ChartEvent]) {
+        let bars_end_ns = self.bars.last().map_or(0, Bar::end_ns);
+        if let Some(end_ns) = events
+            .iter()
+            .map(ChartEvent::end_position)
+            .max()
+            .filter(|&end_ns| end_ns >= bars_end_ns)
+        {
+            self.bars = self.tempo_map.bars_until(end_ns + 1);
+            self.history[0] = Message {
+                role: Role::System,
+                content: create_system_prompt(&self.bars, &self.stem_bars, &self.options),
+            };
+            log::info!("Extended chart session to {} bars", self.bars.len());
+        }
+        self.notes = session_notes(&self.bars, events, self.options.key_count);
+    }
+
+    async fn refine(&mut self, instruction: &str) -> Result<ChartRefinement, String> {
+        let schema = response_schema(&self.bars, &self.options);
+        let mut messages = self.history.clone();
+        messages.push(Message::user(format!(
+            "CURRENT CHART (bar number: notes; long notes have \"length\" in beats):\n{}\n\nINSTRUCTION:\n{}",
+            self.chart_text(),
+            instruction
+        )));
+        let mut usage = Usage::default();
+        let mut last_error = String::new();
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let reply = self.client.send(&messages, Some(&schema)).await?;
+            usage.add(reply.usage);
+            let response = reply.content;
+
+            let error = match crate::language_model::parse_structured::<RefinementResponse>(
+                response.clone(),
+                &schema,
+            ) {
+                Ok(refinement) => match self.apply(&refinement.changes) {
+                    Ok((notes, changes)) => {
+                        self.notes = notes;
+                        self.history
+                            .push(Message::user(format!("INSTRUCTION:\n{}", instruction)));
+                        self.history.push(Message {
+                            role: Role::Assistant,
+                            content: response,
+                        });
+                        log::info!(
+                            "Applied chart changes to {} bars ({}ms)",
+                            changes.len(),
+                            usage.latency_ms
+                        );
+                        return Ok(ChartRefinement {
+                            changes,
+                            comment: refinement.comment,
+                            usage,
+                        });
+                    }
+                    Err(e) => e,
+                },
+                Err(e) => e.to_string(),
+            };
+
+            log::warn!(
+                "Invalid chart changes (attempt {}/{}): {}",
+                attempt,
+                MAX_ATTEMPTS,
+                error
+            );
+            // エラー内容を伝えて、変更全体を出力し直してもらう
+            messages.push(Message {
+                role: Role::Assistant,
+                content: response,
+            });
+            messages.push(Message::user(format!(
+                "Your changes were invalid: {}\nNothing was applied. Output a corrected JSON object with all of the changes.",
+                error
+            )));
+            last_error = error;
+        }
+
+        Err(format!("Failed to refine chart: {}", last_error))
+    }
+
+    /// 変更を検証し、適用後のノートと小節ごとの変更を返す（一部だけ適用することはしない）
+    #[allow(clippy::type_complexity)]
+    fn apply(
+        &self,
+        diffs: &[BarDiff],
+    ) -> Result<(BTreeMap<usize, Vec<SessionNote>>, Vec<BarChange>), String> {
+        let mut notes = self.notes.clone();
+        let mut changes = Vec::new();
+        let mut added_uuids = HashSet::new();
+        let mut seen_bars = HashSet::new();
+
+        for (i, diff) in diffs.iter().enumerate() {
+            let path = format!("/changes/{}", i);
+            let Some(bar) = diff
+                .bar
+                .checked_sub(1)
+                .and_then(|index| self.bars.get(index))
+            else {
+                return Err(format!("{}: bar {} does not exist", path, diff.bar));
+            };
+            if !seen_bars.insert(diff.bar) {
+                return Err(format!("{}: bar {} appears more than once", path, diff.bar));
+            }
+
+            let bar_notes = notes.entry(diff.bar).or_default();
+            let mut removed = Vec::new();
+            for (j, note) in diff.remove.iter().enumerate() {
+                let step = beat_step(note.beat);
+                let Some(index) = bar_notes
+                    .iter()
+                    .position(|n| n.key == note.key && n.step() == step)
+                else {
+                    return Err(format!(
+                        "{}/remove/{}: bar {} has no note on key {} at beat {}",
+                        path, j, diff.bar, note.key, note.beat
+                    ));
+                };
+                removed.push(bar_notes.remove(index).uuid);
+            }
+
+            let mut added = Vec::new();
+            for (j, note) in diff.add.iter().enumerate() {
+                let error = |message: String| format!("{}/add/{}: {}", path, j, message);
+                if !self
+                    .options
+                    .enabled_keys
+                    .get(note.key.wrapping_sub(1))
+                    .copied()
+                    .unwrap_or(false)
+                {
+                    return Err(error(format!("key {} is not enabled", note.key)));
+                }
+                if note.beat < 1.0 || note.beat > bar.beats as f64 {
+                    return Err(error(format!(
+                        "beat {} is outside bar {} (1 to {})",
+                        note.beat, diff.bar, bar.beats
+                    )));
+                }
+                let length = (note.length > 0.0).then_some(note.length);
+                let session_note = SessionNote {
+                    uuid: uuid::Uuid::new_v4().to_string(),
+                    key: note.key,
+                    beat: chart_generation::quantize_beat(note.beat),
+                    length: length.map(chart_generation::quantize_beat),
+                };
+                let event = note_event(&self.bars, bar, &session_note);
+                if event.end_position() > self.bars.last().map_or(0, Bar::end_ns) {
+                    return Err(error("long note extends past the last bar".to_string()));
+                }
+                added_uuids.insert(session_note.uuid.clone());
+                bar_notes.push(session_note);
+                added.push(event);
+            }
+
+            // 同じ鍵盤の重複と白鍵・黒鍵の同時押し
+            let generated: Vec<GeneratedNote> = bar_notes
+                .iter()
+                .map(|note| GeneratedNote {
+                    key: note.key,
+                    beat: note.beat,
+                })
+                .collect();
+            chart_generation::check_bar_notes(&path, &generated, &self.options)
+                .map_err(|e| format!("bar {} after the changes: {}", diff.bar, e))?;
+
+            bar_notes.sort_by(|a, b| a.beat.total_cmp(&b.beat).then(a.key.cmp(&b.key)));
+            changes.push(BarChange {
+                bar: diff.bar,
+                added,
+                removed,
+            });
+        }
+
+        check_long_note_overlaps(&self.bars, &notes, &added_uuids)?;
+        notes.retain(|_, bar_notes| !bar_notes.is_empty());
+        Ok((notes, changes))
+    }
+
+    /// 現在の譜面（ノートのある小節のみ）
+    fn chart_text(&self) -> String {
+        let chart: Map<String, Value> = self
+            .notes
+            .iter()
+            .map(|(number, notes)| {
+                let notes: Vec<PromptNote> = notes
+                    .iter()
+                    .map(|note| PromptNote {
+                        key: note.key,
+                        beat: note.beat,
+                        length: note.length,
+                    })
+                    .collect();
+                (number.to_string(), json!(notes))
+            })
+            .collect();
+        if chart.is_empty() {
+            "(empty)".to_string()
+        } else {
+            Value::Object(chart).to_string()
+        }
+    }
+}
+
+/// 新しく追加したノートが、同じ鍵盤のロングノートと重なっていないか検証する
+fn check_long_note_overlaps(
+    bars: &[Bar],
+    notes: &BTreeMap<usize, Vec<SessionNote>>,
+    added_uuids: &HashSet<String>,
+) -> Result<(), String> {
+    let mut by_key: BTreeMap<usize, Vec<(u64, u64, usize, &SessionNote)>> = BTreeMap::new();
+    for (&number, bar_notes) in notes {
+        let bar = &bars[number - 1];
+        for note in bar_notes {
+            let event = note_event(bars, bar, note);
+            let start = event.position();
+            by_key
+                .entry(note.key)
+                .or_default()
+                .push((start, event.end_position(), number, note));
+        }
+    }
+
+    for (key, mut spans) in by_key {
+        spans.sort_by_key(|&(start, ..)| start);
+        // それまでのロングノートのうち最も遅く終わるもの（間に別のノートがあっても比べる）
+        let mut open: Option<(u64, usize, &SessionNote)> = None;
+        for (start, end, number, note) in spans {
+            if let Some((open_end, open_bar, long_note)) = open {
+                if start <= open_end
+                    && (added_uuids.contains(&long_note.uuid) || added_uuids.contains(&note.uuid))
+                {
+                    return Err(format!(
+                        "key {}: the note at bar {} beat {} overlaps the long note at bar {} beat {}",
+                        key, number, note.beat, open_bar, long_note.beat
+                    ));
+                }
+            }
+            if note.length.is_some() && open.is_none_or(|(open_end, ..)| end > open_end) {
+                open = Some((end, number, note));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 譜面のイベントを小節ごとのノートにする（拍位置は16分音符でクオンタイズする）
+///
+/// key_countを超えるレーンのノートは扱えないため含めない。
+fn session_notes(
+    bars: &[Bar],
+    events: &[ChartEvent],
+    key_count: usize,
+) -> BTreeMap<usize, Vec<SessionNote>> {
+    let mut notes: BTreeMap<usize, Vec<SessionNote>> = BTreeMap::new();
+    for event in events {
+        let (uuid, position, lane, end_position) = match event {
+            ChartEvent::SingleNote {
+                uuid,
+                position,
+                lane,
+            } => (uuid, *position, lane, None),
+            ChartEvent::LongNote {
+                uuid,
+                position,
+                lane,
+                end_position,
+            } => (uuid, *position, lane, Some(*end_position)),
+            ChartEvent::SpeedChange { .. } => continue,
+        };
+
+        if *lane >= key_count {
+            log::warn!(
+                "Ignoring note {} on lane {} (keyCount {})",
+                uuid,
+                lane,
+                key_count
+            );
+            continue;
+        }
+
+        let mut index = bars.partition_point(|b| b.end_ns() <= position);
+        let Some(bar) = bars.get(index) else {
+            continue;
+        };
+        let beats = (position - bar.start_ns) as f64 / bar.beat_length_ns() as f64;
+        let mut beat = chart_generation::quantize_beat(1.0 + beats);
+        // クオンタイズで次の小節の頭になった場合
+        if beat > bar.beats as f64 && index + 1 < bars.len() {
+            index += 1;
+            beat = 1.0;
+        }
+        let length = end_position.map(|end| {
+            let beats = end.saturating_sub(position) as f64 / bar.beat_length_ns() as f64;
+            chart_generation::quantize_beat(beats).max(1.0 / STEPS_PER_BEAT)
+        });
+
+        notes.entry(index + 1).or_default().push(SessionNote {
+            uuid: uuid.clone(),
+            key: *lane + 1,
+            beat,
+            length,
+        });
+    }
+    for bar_notes in notes.values_mut() {
+        bar_notes.sort_by(|a, b| a.beat.total_cmp(&b.beat).then(a.key.cmp(&b.key)));
+    }
+    notes
+}
+
+/// ノートをイベントに変換する（ロングノートは小節をまたいでもよい）
+fn note_event(bars: &[Bar], bar: &Bar, note: &SessionNote) -> ChartEvent {
+    let position = chart_generation::note_position_ns(bar, note.beat);
+    let lane = note.key - 1;
+    match note.length {
+        Some(length) => ChartEvent::LongNote {
+            uuid: note.uuid.clone(),
+            position,
+            lane,
+            end_position: position_after(bars, bar.index, note.beat + length),
+        },
+        None => ChartEvent::SingleNote {
+            uuid: note.uuid.clone(),
+            position,
+            lane,
+        },
+    }
+}
+
+/// index番目の小節の拍位置（1始まり、小節の拍数を超えてもよい）をナノ秒位置にする
+fn position_after(bars: &[Bar], mut index: usize, mut beat: f64) -> u64 {
+    while beat >= bars[index].beats as f64 + 1.0 && index + 1 < bars.len() {
+        beat -= bars[index].beats as f64;
+        index += 1;
+    }
+    chart_generation::note_position_ns(&bars[index], beat)
+}
+
+fn beat_step(beat: f64) -> u64 {
+    ((beat - 1.0) * STEPS_PER_BEAT).round().max(0.0) as u64
+}
+
+fn create_system_prompt(
+    bars: &[Bar],
+    stem_bars: &BTreeMap<usize, BarStemNotes>,
+    options: &ChartGenerationOptions,
+) -> String {
+    let (enabled_key_info, enabled_numbers) = chart_generation::enabled_key_info(options);
+    let stems_text = stem_bars
+        .iter()
+        .map(|(number, notes)| {
+            let bar = &bars[number - 1];
+            format!(
+                "Bar {} ({}/4, {} BPM): {}",
+                number,
+                bar.beats,
+                bar.tempo,
+                serde_json::to_string(notes).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut rules = vec![
+        "Output ONLY a valid JSON object with the changes and a short comment.".to_string(),
+        "Format: {\"changes\": [{\"bar\": 9, \"add\": [{\"key\": 1, \"beat\": 1.0, \"length\": 0}], \"remove\": [{\"key\": 3, \"beat\": 2.5}]}], \"comment\": \"...\"}".to_string(),
+        "Only include bars you change, and only change what the instruction asks for".to_string(),
+        format!("Bar numbers range from 1 to {}", bars.len()),
+        "\"remove\" must refer to notes in the current chart by their exact key and beat".to_string(),
+        "Beat positions must be quantized to 16th notes (0.25 increments) and be within 1.0 to the bar's beat count".to_string(),
+        "\"length\" is the long-note length in beats (0.25 increments) and may extend into later bars; use 0 for a tap note".to_string(),
+        "A long note must not overlap another note on the same key".to_string(),
+        format!("Only use enabled keys: {}", enabled_numbers),
+        "A key cannot have two notes at the same beat position".to_string(),
+    ];
+    if !options.allow_simultaneous_white_black {
+        rules.push(
+            "Do not place white and black keys simultaneously at the same beat position"
+                .to_string(),
+        );
+    }
+    rules.push("\"comment\" briefly explains what you changed and why".to_string());
+    let rules_text = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| format!("{}. {}", i + 1, rule))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let custom_instructions = if options.custom_instructions.trim().is_empty() {
+        String::new()
+    } else {
+        format!("\n\nCUSTOM INSTRUCTIONS:\n{}", options.custom_instructions)
+    };
+
+    format!(
+        r#"You are a professional rhythm game chart designer editing an existing chart together with the user. Each message contains the current chart and an instruction; reply with the note additions and removals that carry out the instruction.
+
+CONFIGURATION:
+- Total keys: {key_count}
+- Available keys: {enabled_key_info}
+- White/Black key simultaneous press: {simultaneous}
+- Bars: {bar_count}
+
+STEM AUDIO DATA (bars with notes only):
+{stems_text}
+
+RULES:
+{rules_text}
+
+DESIGN PHILOSOPHY:
+- Follow the rhythm and intensity of the stem audio data
+- Long notes suit sustained vocal, bass or other notes (use their "length")
+- Keep the parts of the chart the instruction does not mention unchanged{custom_instructions}"#,
+        key_count = options.key_count,
+        simultaneous = if options.allow_simultaneous_white_black {
+            "Allowed"
+        } else {
+            "Not allowed"
+        },
+        bar_count = bars.len(),
+    )
+}
+
+/// 応答のJSON Schema
+fn response_schema(bars: &[Bar], options: &ChartGenerationOptions) -> Value {
+    let enabled_keys: Vec<usize> = (1..=options.key_count)
+        .filter(|&key| options.enabled_keys[key - 1])
+        .collect();
+    let max_beats = bars.iter().map(|bar| bar.beats).max().unwrap_or(4);
+    let key = json!({
+        "type": "integer",
+        "minimum": 1,
+        "maximum": options.key_count,
+    });
+    let beat = json!({
+        "type": "number",
+        "minimum": 1.0,
+        "maximum": max_beats,
+        "multipleOf": 1.0 / STEPS_PER_BEAT,
+    });
+
+    json!({
+        "type": "object",
+        "properties": {
+            "changes": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "bar": { "type": "integer", "minimum": 1, "maximum": bars.len() },
+                        "add": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "key": {
+                                        "type": "integer",
+                                        "minimum": 1,
+                                        "maximum": options.key_count,
+                                        "enum": enabled_keys,
+                                    },
+                                    "beat": beat,
+                                    "length": {
+                                        "type": "number",
+                                        "minimum": 0.0,
+                                        "multipleOf": 1.0 / STEPS_PER_BEAT,
+                                    },
+                                },
+                                "required": ["key", "beat", "length"],
+                                "additionalProperties": false,
+                            },
+                        },
+                        "remove": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": { "key": key, "beat": beat },
+                                "required": ["key", "beat"],
+                                "additionalProperties": false,
+                            },
+                        },
+                    },
+                    "required": ["bar", "add", "remove"],
+                    "additionalProperties": false,
+                },
+            },
+            "comment": { "type": "string" },
+        },
+        "required": ["changes", "comment"],
+        "additionalProperties": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language_model::ChatOptions;
+
+    /// 120BPM・4/4で4小節（1拍0.5秒）、4鍵のうち4番目は無効
+    fn session(events: Value) -> ChartSession {
+        let tempo_map = TempoMap::new(vec![TempoEvent {
+            uuid: "tempo".to_string(),
+            tempo: 120.0,
+            beat: 4.0,
+            length: 4.0,
+        }]);
+        let bars = tempo_map.bars();
+        let options: ChartGenerationOptions = serde_json::from_value(json!({
+            "keyCount": 4,
+            "enabledKeys": [true, true, true, false],
+            "allowSimultaneousWhiteBlack": true,
+            "keyTypes": ["white", "black", "white", "black"],
+            "barsPerBatch": 4,
+            "label": "test"
+        }))
+        .unwrap();
+        let events: Vec<ChartEvent> = serde_json::from_value(events).unwrap();
+        ChartSession {
+            client: ChatClient::without_cache(
+                ChatProvider::Mock { script: None },
+                ChatOptions::default(),
+            ),
+            notes: session_notes(&bars, &events, options.key_count),
+            history: Vec::new(),
+            options,
+            tempo_map,
+            bars,
+            stem_bars: BTreeMap::new(),
+        }
+    }
+
+    fn diffs(changes: Value) -> Vec<BarDiff> {
+        serde_json::from_value(changes).unwrap()
+    }
+
+    fn apply_error(session: &ChartSession, changes: Value) -> String {
+        session.apply(&diffs(changes)).unwrap_err()
+    }
+
+    #[test]
+    fn apply_adds_and_removes_notes() {
+        let session = session(json!([
+            { "type": "SingleNote", "uuid": "a", "position": "0", "lane": 0 }
+        ]));
+        let (notes, changes) = session
+            .apply(&diffs(json!([{
+                "bar": 1,
+                "add": [{ "key": 2, "beat": 2.0, "length": 0 }],
+                "remove": [{ "key": 1, "beat": 1.0 }]
+            }])))
+            .unwrap();
+
+        assert_eq!(changes[0].removed, ["a"]);
+        assert_eq!(changes[0].added[0].position(), 500_000_000);
+        assert_eq!(changes[0].added[0].lane(), Some(1));
+        let keys: Vec<usize> = notes[&1].iter().map(|note| note.key).collect();
+        assert_eq!(keys, [2]);
+        // 検証と適用は別なので、セッションの譜面はまだ変わらない
+        assert_eq!(session.notes[&1][0].uuid, "a");
+    }
+
+    #[test]
+    fn apply_rejects_disabled_or_unknown_key() {
+        let session = session(json!([]));
+        for key in [0, 4, 5] {
+            let error = apply_error(
+                &session,
+                json!([{ "bar": 1, "add": [{ "key": key, "beat": 1.0, "length": 0 }], "remove": [] }]),
+            );
+            assert_eq!(
+                error,
+                format!("/changes/0/add/0: key {} is not enabled", key)
+            );
+        }
+    }
+
+    #[test]
+    fn apply_rejects_beat_outside_bar() {
+        let session = session(json!([]));
+        for beat in [0.5, 5.0] {
+            let error = apply_error(
+                &session,
+                json!([{ "bar": 2, "add": [{ "key": 1, "beat": beat, "length": 0 }], "remove": [] }]),
+            );
+            assert!(error.contains("is outside bar 2 (1 to 4)"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn apply_rejects_duplicate_or_missing_bar() {
+        let session = session(json!([]));
+        let error = apply_error(
+            &session,
+            json!([
+                { "bar": 2, "add": [{ "key": 1, "beat": 1.0, "length": 0 }], "remove": [] },
+                { "bar": 2, "add": [{ "key": 2, "beat": 1.0, "length": 0 }], "remove": [] }
+            ]),
+        );
+        assert_eq!(error, "/changes/1: bar 2 appears more than once");
+
+        let error = apply_error(&session, json!([{ "bar": 5, "add": [], "remove": [] }]));
+        assert_eq!(error, "/changes/0: bar 5 does not exist");
+    }
+
+    #[test]
+    fn apply_rejects_removing_missing_note() {
+        let session = session(json!([
+            { "type": "SingleNote", "uuid": "a", "position": "0", "lane": 0 }
+        ]));
+        let error = apply_error(
+            &session,
+            json!([{ "bar": 1, "add": [], "remove": [{ "key": 2, "beat": 1.0 }] }]),
+        );
+        assert_eq!(
+            error,
+            "/changes/0/remove/0: bar 1 has no note on key 2 at beat 1"
+        );
+    }
+
+    #[test]
+    fn apply_rejects_long_note_past_last_bar() {
+        let session = session(json!([]));
+        let error = apply_error(
+            &session,
+            json!([{ "bar": 4, "add": [{ "key": 1, "beat": 4.0, "length": 2.0 }], "remove": [] }]),
+        );
+        assert_eq!(
+            error,
+            "/changes/0/add/0: long note extends past the last bar"
+        );
+    }
+
+    #[test]
+    fn apply_rejects_note_inside_long_note_behind_another_note() {
+        // 既存のロングノート（1小節目の1拍目から4拍）の中に既存のタップノートがある
+        let session = session(json!([
+            { "type": "LongNote", "uuid": "a", "position": "0", "lane": 0, "endPosition": "2000000000" },
+            { "type": "SingleNote", "uuid": "b", "position": "500000000", "lane": 0 }
+        ]));
+
+        // 既存のノート同士の重なりは問わない
+        session
+            .apply(&diffs(json!([{
+                "bar": 1,
+                "add": [{ "key": 2, "beat": 3.0, "length": 0 }],
+                "remove": []
+            }])))
+            .unwrap();
+
+        let error = apply_error(
+            &session,
+            json!([{ "bar": 1, "add": [{ "key": 1, "beat": 3.0, "length": 0 }], "remove": [] }]),
+        );
+        assert_eq!(
+            error,
+            "key 1: the note at bar 1 beat 3 overlaps the long note at bar 1 beat 1"
+        );
+    }
+}
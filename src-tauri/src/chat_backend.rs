@@ -0,0 +1,145 @@
+use crate::language_model::{ChatProvider, Message};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+
+// チャットのバックエンド
+//
+// ChatClientはこのトレイトを通してLLMを呼び出すため、HTTPのプロバイダーとモック（mock_llm）を
+// 同じように扱える。
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// サンプリングと出力長の指定（Noneの項目はバックエンドの既定値）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationParams {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// OpenAI互換サーバーでは対応しているもの（llama.cpp、vLLMなど）のみ有効
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// この文字列が出力されたら生成を止める
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// 乱数のシード（Ollama、Gemini、一部のOpenAI互換サーバーが対応）
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// 1回分のリクエスト
+#[derive(Debug, Clone, Copy)]
+pub struct ChatRequest<'a> {
+    pub messages: &'a [Message],
+    /// 指定した場合はJSON Schemaに従った出力を要求する
+    pub schema: Option<&'a Value>,
+    pub params: &'a GenerationParams,
+}
+
+/// バックエンドの応答（トークン数は報告されない場合はNone）
+#[derive(Debug, Clone, Default)]
+pub struct ChatResponse {
+    pub text: String,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+}
+
+pub trait ChatBackend: Send + Sync {
+    /// ストリーミングでチャットし、受信したトークンをon_tokenに渡す（戻り値は応答全体）
+    fn chat_stream<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<ChatResponse, String>>;
+}
+
+/// プロバイダーに対応するバックエンド
+pub fn for_provider(provider: &ChatProvider) -> Box<dyn ChatBackend> {
+    match provider.clone() {
+        ChatProvider::Ollama { model, base_url } => Box::new(OllamaBackend { model, base_url }),
+        ChatProvider::Google { model, api_key } => Box::new(GoogleBackend { model, api_key }),
+        ChatProvider::OpenAiCompatible {
+            base_url,
+            model,
+            api_key,
+        } => Box::new(OpenAiCompatibleBackend {
+            base_url,
+            model,
+            api_key,
+        }),
+        ChatProvider::Mock { script } => Box::new(crate::mock_llm::MockBackend::new(script)),
+    }
+}
+
+struct OllamaBackend {
+    model: String,
+    base_url: Option<String>,
+}
+
+impl ChatBackend for OllamaBackend {
+    fn chat_stream<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<ChatResponse, String>> {
+        Box::pin(crate::ollama::chat_stream(
+            self.base_url.as_deref(),
+            &self.model,
+            request.params,
+            request.messages,
+            request.schema,
+            on_token,
+        ))
+    }
+}
+
+struct GoogleBackend {
+    model: String,
+    api_key: String,
+}
+
+impl ChatBackend for GoogleBackend {
+    fn chat_stream<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<ChatResponse, String>> {
+        Box::pin(crate::google_ai::chat_stream(
+            &self.model,
+            &self.api_key,
+            request.params,
+            request.messages,
+            request.schema,
+            on_token,
+        ))
+    }
+}
+
+struct OpenAiCompatibleBackend {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl ChatBackend for OpenAiCompatibleBackend {
+    fn chat_stream<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<ChatResponse, String>> {
+        Box::pin(crate::openai_compatible::chat_stream(
+            &self.base_url,
+            &self.model,
+            self.api_key.as_deref(),
+            request.params,
+            request.messages,
+            request.schema,
+            on_token,
+        ))
+    }
+}
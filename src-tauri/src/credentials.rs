@@ -0,0 +1,251 @@
+use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+// AIプロバイダーのAPIキーの保存
+//
+// OSのキーリング（Windowsの資格情報マネージャー、macOSのキーチェーン、LinuxのSecret Service）に保存し、
+// 使えない環境（ヘッドレスのLinuxなど）ではAppLocalDataの暗号化ファイルに保存する。
+// 保存したキーはフロントエンドに返さない。
+
+/// キーリングのサービス名
+const KEYRING_SERVICE: &str = "com.kemoshumai.souon-editor";
+
+/// APIキーを保存できるプロバイダー（ChatProviderのtypeと同じ）
+const PROVIDER_IDS: &[&str] = &["google", "openAiCompatible"];
+
+fn validate_provider_id(provider_id: &str) -> Result<(), String> {
+    if PROVIDER_IDS.contains(&provider_id) {
+        Ok(())
+    } else {
+        Err(format!("Unknown provider: {}", provider_id))
+    }
+}
+
+/// APIキーを保存する（空なら削除する）
+#[tauri::command]
+pub async fn set_api_key(
+    app_handle: tauri::AppHandle,
+    provider_id: String,
+    api_key: String,
+) -> Result<(), String> {
+    validate_provider_id(&provider_id)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return delete(&app_handle, &provider_id);
+    }
+
+    let file = EncryptedFile::new(&app_handle)?;
+    match keyring_entry(&provider_id).and_then(|entry| entry.set_password(api_key)) {
+        Ok(()) => {
+            // 以前にファイルへ保存していた場合は消しておく
+            file.remove(&provider_id)?;
+            log::info!("Stored API key for {} in the OS keyring", provider_id);
+        }
+        Err(e) => {
+            log::warn!(
+                "OS keyring is unavailable ({}), storing API key for {} in an encrypted file",
+                e,
+                provider_id
+            );
+            file.insert(&provider_id, api_key)?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_api_key(
+    app_handle: tauri::AppHandle,
+    provider_id: String,
+) -> Result<(), String> {
+    validate_provider_id(&provider_id)?;
+    delete(&app_handle, &provider_id)
+}
+
+/// APIキーが保存されているか（キー自体は返さない）
+#[tauri::command]
+pub async fn has_api_key(
+    app_handle: tauri::AppHandle,
+    provider_id: String,
+) -> Result<bool, String> {
+    validate_provider_id(&provider_id)?;
+    Ok(api_key(&app_handle, &provider_id)?.is_some())
+}
+
+/// 保存されているAPIキーを読む
+pub(crate) fn api_key(
+    app_handle: &tauri::AppHandle,
+    provider_id: &str,
+) -> Result<Option<String>, String> {
+    match keyring_entry(provider_id).and_then(|entry| entry.get_password()) {
+        Ok(api_key) => return Ok(Some(api_key)),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => log::warn!("Failed to read API key from the OS keyring: {}", e),
+    }
+    EncryptedFile::new(app_handle)?.get(provider_id)
+}
+
+fn delete(app_handle: &tauri::AppHandle, provider_id: &str) -> Result<(), String> {
+    match keyring_entry(provider_id).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => log::warn!("Failed to delete API key from the OS keyring: {}", e),
+    }
+    EncryptedFile::new(app_handle)?.remove(provider_id)?;
+    log::info!("Deleted API key for {}", provider_id);
+    Ok(())
+}
+
+fn keyring_entry(provider_id: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, provider_id)
+}
+
+/// キーリングが使えない場合の保存先
+///
+/// 鍵（credentials.key）は所有者のみ読み書きできるファイルとして別に保存する。
+/// 設定ファイルやバックアップからキーが平文で漏れるのを防ぐためのもので、
+/// 同じユーザー権限で動くプログラムからは保護できない。
+struct EncryptedFile {
+    dir: PathBuf,
+}
+
+/// 暗号化した1件分
+#[derive(Serialize, Deserialize)]
+struct EncryptedEntry {
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFile {
+    fn new(app_handle: &tauri::AppHandle) -> Result<Self, String> {
+        let dir = app_handle
+            .path()
+            .app_local_data_dir()
+            .map_err(|e| format!("Failed to resolve AppLocalData directory: {}", e))?
+            .join("credentials");
+        Ok(Self { dir })
+    }
+
+    fn entries_path(&self) -> PathBuf {
+        self.dir.join("credentials.json")
+    }
+
+    fn get(&self, provider_id: &str) -> Result<Option<String>, String> {
+        let entries = self.read_entries()?;
+        let Some(entry) = entries.get(provider_id) else {
+            return Ok(None);
+        };
+        let cipher = ChaCha20Poly1305::new(&self.key(false)?);
+
+        let decode = |text: &str| {
+            general_purpose::STANDARD
+                .decode(text)
+                .map_err(|e| format!("Failed to decode stored API key: {}", e))
+        };
+        let nonce = decode(&entry.nonce)?;
+        if nonce.len() != 12 {
+            return Err("Stored API key is corrupted".to_string());
+        }
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode(&entry.ciphertext)?,
+                    aad: provider_id.as_bytes(),
+                },
+            )
+            .map_err(|_| format!("Failed to decrypt stored API key for {}", provider_id))?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| format!("Stored API key is corrupted: {}", e))
+    }
+
+    fn insert(&self, provider_id: &str, api_key: &str) -> Result<(), String> {
+        let cipher = ChaCha20Poly1305::new(&self.key(true)?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        // プロバイダーIDを関連データにして、別のプロバイダーの項目と入れ替えられないようにする
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: api_key.as_bytes(),
+                    aad: provider_id.as_bytes(),
+                },
+            )
+            .map_err(|e| format!("Failed to encrypt API key: {}", e))?;
+
+        let mut entries = self.read_entries()?;
+        entries.insert(
+            provider_id.to_string(),
+            EncryptedEntry {
+                nonce: general_purpose::STANDARD.encode(nonce),
+                ciphertext: general_purpose::STANDARD.encode(ciphertext),
+            },
+        );
+        self.write_entries(&entries)
+    }
+
+    fn remove(&self, provider_id: &str) -> Result<(), String> {
+        let mut entries = self.read_entries()?;
+        if entries.remove(provider_id).is_some() {
+            self.write_entries(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn read_entries(&self) -> Result<BTreeMap<String, EncryptedEntry>, String> {
+        match std::fs::read(self.entries_path()) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| format!("Failed to parse stored credentials: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(format!("Failed to read stored credentials: {}", e)),
+        }
+    }
+
+    fn write_entries(&self, entries: &BTreeMap<String, EncryptedEntry>) -> Result<(), String> {
+        let content = serde_json::to_vec_pretty(entries)
+            .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+        write_private(&self.entries_path(), &content)
+    }
+
+    /// 暗号化の鍵を読む（createなら無いときに作る）
+    fn key(&self, create: bool) -> Result<Key, String> {
+        let path = self.dir.join("credentials.key");
+        match std::fs::read(&path) {
+            Ok(key) if key.len() == 32 => return Ok(Key::clone_from_slice(&key)),
+            Ok(_) => return Err("Credential key file is corrupted".to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {}
+            Err(e) => return Err(format!("Failed to read credential key: {}", e)),
+        }
+
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        write_private(&path, &key)?;
+        Ok(key)
+    }
+}
+
+/// 所有者のみ読み書きできるファイルとして書き込む
+fn write_private(path: &Path, content: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create credentials directory: {}", e))?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    use std::io::Write;
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,138 @@
+use crate::runtime_settings::RuntimeSettings;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+#[cfg(target_os = "windows")]
+const FFMPEG_EXECUTABLE: &str = "ffmpeg.exe";
+#[cfg(not(target_os = "windows"))]
+const FFMPEG_EXECUTABLE: &str = "ffmpeg";
+
+/// FFmpegの入手元
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FfmpegSource {
+    /// runtime_settings.jsonのffmpegPath
+    Configured,
+    /// PATH上のffmpeg
+    System,
+    /// python_env/Scriptsにダウンロードしたもの
+    Bundled,
+}
+
+/// オーディオコーデック（ffmpeg -codecsの1行）
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegCodec {
+    pub name: String,
+    pub description: String,
+    pub decode: bool,
+    pub encode: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegInfo {
+    pub path: String,
+    pub source: FfmpegSource,
+    /// "ffmpeg version ..."の行
+    pub version: String,
+    pub codecs: Vec<FfmpegCodec>,
+}
+
+/// 使用するFFmpegを探す（設定→PATH→python_envの順）
+pub fn find_ffmpeg(
+    python_env_dir: &Path,
+    settings: &RuntimeSettings,
+) -> Option<(PathBuf, FfmpegSource)> {
+    if let Some(path) = settings.ffmpeg_path() {
+        let path = PathBuf::from(path);
+        if path.is_file() {
+            return Some((path, FfmpegSource::Configured));
+        }
+        log::warn!("Configured FFmpeg not found: {}", path.display());
+    }
+
+    if let Some(path) = std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(FFMPEG_EXECUTABLE))
+            .find(|path| path.is_file())
+    }) {
+        return Some((path, FfmpegSource::System));
+    }
+
+    let bundled = python_env_dir.join("Scripts").join(FFMPEG_EXECUTABLE);
+    bundled
+        .is_file()
+        .then_some((bundled, FfmpegSource::Bundled))
+}
+
+/// 使用するFFmpegのバージョンと対応コーデックを返す（見つからない場合はnull）
+#[tauri::command]
+pub async fn ffmpeg_info(app_handle: tauri::AppHandle) -> Result<Option<FfmpegInfo>, String> {
+    let settings = RuntimeSettings::load(&app_handle)?;
+    let python_env_dir = app_handle
+        .path()
+        .resolve("python_env", tauri::path::BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve python_env path: {}", e))?;
+
+    let Some((path, source)) = find_ffmpeg(&python_env_dir, &settings) else {
+        return Ok(None);
+    };
+
+    let version = run_ffmpeg(&path, &["-version"]).await?;
+    let codecs = run_ffmpeg(&path, &["-hide_banner", "-codecs"]).await?;
+
+    Ok(Some(FfmpegInfo {
+        path: path.to_string_lossy().to_string(),
+        source,
+        version: version.lines().next().unwrap_or_default().to_string(),
+        codecs: parse_audio_codecs(&codecs),
+    }))
+}
+
+async fn run_ffmpeg(path: &Path, args: &[&str]) -> Result<String, String> {
+    let mut command = tokio::process::Command::new(path);
+    command.args(args);
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute {}: {}", path.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to run ffmpeg: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// ffmpeg -codecsの出力からオーディオコーデックを取り出す
+///
+/// 各行は" DEA.L. aac                  AAC (Advanced Audio Coding)"の形式。
+fn parse_audio_codecs(output: &str) -> Vec<FfmpegCodec> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("-------"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?.as_bytes();
+            let name = parts.next()?;
+            if flags.len() < 3 || flags[2] != b'A' {
+                return None;
+            }
+            Some(FfmpegCodec {
+                name: name.to_string(),
+                description: parts.collect::<Vec<_>>().join(" "),
+                decode: flags[0] == b'D',
+                encode: flags[1] == b'E',
+            })
+        })
+        .collect()
+}
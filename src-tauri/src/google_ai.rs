@@ -0,0 +1,171 @@
+use crate::chat_backend::{ChatResponse, GenerationParams};
+use crate::json_schema;
+use crate::language_model::{self, Message, Role};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Google AI Studio（Gemini API）のストリーミング呼び出し
+
+pub const API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub const TEMPERATURE: f32 = 1.2;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentRequest<'a> {
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    generation_config: GenerationConfig<'a>,
+}
+
+#[derive(Serialize)]
+struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig<'a> {
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop_sequences: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    /// それまでの累計
+    #[serde(default)]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: Option<u32>,
+    #[serde(default)]
+    candidates_token_count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    #[serde(default)]
+    content: Option<CandidateContent>,
+}
+
+#[derive(Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+/// streamGenerateContentを呼び出す
+pub async fn chat_stream(
+    model: &str,
+    api_key: &str,
+    params: &GenerationParams,
+    messages: &[Message],
+    schema: Option<&Value>,
+    on_token: &mut (dyn FnMut(&str) + Send),
+) -> Result<ChatResponse, String> {
+    let text_content = |role, text: &str| Content {
+        role,
+        parts: vec![Part {
+            text: text.to_string(),
+        }],
+    };
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == Role::System)
+        .map(|m| m.content.as_str())
+        .collect();
+    let request = GenerateContentRequest {
+        contents: messages
+            .iter()
+            .filter_map(|m| match m.role {
+                Role::System => None,
+                Role::User => Some(text_content(Some("user"), &m.content)),
+                Role::Assistant => Some(text_content(Some("model"), &m.content)),
+            })
+            .collect(),
+        system_instruction: (!system.is_empty()).then(|| text_content(None, &system.join("\n\n"))),
+        generation_config: GenerationConfig {
+            temperature: params.temperature.unwrap_or(TEMPERATURE),
+            top_p: params.top_p,
+            top_k: params.top_k,
+            max_output_tokens: params.max_tokens,
+            stop_sequences: &params.stop,
+            seed: params.seed,
+            response_mime_type: schema.map(|_| "application/json"),
+            response_schema: schema.map(json_schema::to_gemini_schema),
+        },
+    };
+    let body =
+        serde_json::to_vec(&request).map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+    let url = format!(
+        "{}/models/{}:streamGenerateContent?alt=sse",
+        API_BASE_URL, model
+    );
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("x-goog-api-key", api_key)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Google AI Studio request failed: {}", e))?;
+    let response = language_model::error_for_status(response, "Google AI Studio").await?;
+
+    let mut result = ChatResponse::default();
+    language_model::read_lines(response, |line| {
+        let Some(data) = language_model::sse_data(line) else {
+            return Ok(true);
+        };
+        let chunk: GenerateContentResponse = serde_json::from_str(data)
+            .map_err(|e| format!("Failed to parse Google AI Studio response: {}", e))?;
+        if let Some(usage) = chunk.usage_metadata {
+            result.prompt_tokens = usage.prompt_token_count;
+            result.completion_tokens = usage.candidates_token_count;
+        }
+        let parts = chunk
+            .candidates
+            .into_iter()
+            .next()
+            .and_then(|candidate| candidate.content)
+            .map(|content| content.parts)
+            .unwrap_or_default();
+        for part in parts.iter().filter(|part| !part.text.is_empty()) {
+            on_token(&part.text);
+            result.text.push_str(&part.text);
+        }
+        Ok(true)
+    })
+    .await?;
+
+    Ok(result)
+}
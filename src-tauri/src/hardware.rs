@@ -0,0 +1,798 @@
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+// ハードウェアの調査と、それに合ったローカルLLMの推奨
+
+/// GPUのメーカー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Apple,
+    Unknown,
+}
+
+impl GpuVendor {
+    /// PCIのベンダーID
+    fn from_pci_id(id: u32) -> Self {
+        match id {
+            0x10de => GpuVendor::Nvidia,
+            0x1002 => GpuVendor::Amd,
+            0x8086 => GpuVendor::Intel,
+            0x106b => GpuVendor::Apple,
+            _ => GpuVendor::Unknown,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "NVIDIA",
+            GpuVendor::Amd => "AMD",
+            GpuVendor::Intel => "Intel",
+            GpuVendor::Apple => "Apple",
+            GpuVendor::Unknown => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuInfo {
+    pub vendor: GpuVendor,
+    pub name: String,
+    /// 専用のビデオメモリ（内蔵GPUなど、わからない場合はNone）
+    pub vram_gb: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardwareInfo {
+    pub gpus: Vec<GpuInfo>,
+    /// システムメモリ（Appleシリコンではユニファイドメモリ）
+    pub ram_gb: f32,
+    pub cpu_cores: usize,
+    /// GPUがシステムメモリを共有する（Appleシリコン）
+    pub unified_memory: bool,
+}
+
+impl HardwareInfo {
+    /// 最も大きい専用ビデオメモリ
+    pub fn max_vram_gb(&self) -> Option<f32> {
+        self.gpus
+            .iter()
+            .filter_map(|gpu| gpu.vram_gb)
+            .reduce(f32::max)
+    }
+}
+
+/// このPCのハードウェアを調べる
+#[tauri::command]
+pub async fn probe_hardware() -> Result<HardwareInfo, String> {
+    // 外部コマンドの実行を待つため別スレッドで実行
+    tokio::task::spawn_blocking(probe)
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+fn probe() -> HardwareInfo {
+    let cpu_cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    #[cfg(target_os = "macos")]
+    {
+        let ram_gb = sysctl("hw.memsize")
+            .and_then(|bytes| bytes.parse::<u64>().ok())
+            .map(bytes_to_gb)
+            .unwrap_or(0.0);
+        let brand = sysctl("machdep.cpu.brand_string").unwrap_or_default();
+        // AppleシリコンではGPUがシステムメモリを共有する
+        let unified_memory = brand.starts_with("Apple");
+        let gpus = if unified_memory {
+            vec![GpuInfo {
+                vendor: GpuVendor::Apple,
+                name: brand,
+                vram_gb: None,
+            }]
+        } else {
+            Vec::new()
+        };
+        HardwareInfo {
+            gpus,
+            ram_gb,
+            cpu_cores,
+            unified_memory,
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let mut gpus = nvidia_gpus();
+
+        #[cfg(target_os = "linux")]
+        let ram_gb = {
+            let has_nvidia = !gpus.is_empty();
+            // nvidia-smiで取れたNVIDIAのGPUは重複するので除く
+            gpus.extend(
+                probe_drm(Path::new("/sys"))
+                    .into_iter()
+                    .filter(|gpu| !(has_nvidia && gpu.vendor == GpuVendor::Nvidia)),
+            );
+            read_meminfo_gb(Path::new("/proc")).unwrap_or(0.0)
+        };
+
+        #[cfg(target_os = "windows")]
+        let ram_gb = {
+            if gpus.is_empty() {
+                if let Ok(vram_gb) = get_vram_from_dxdiag() {
+                    gpus.push(GpuInfo {
+                        vendor: GpuVendor::Unknown,
+                        name: "GPU".to_string(),
+                        vram_gb: Some(vram_gb),
+                    });
+                }
+            }
+            windows_ram_gb().unwrap_or(0.0)
+        };
+
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let ram_gb = 0.0;
+
+        HardwareInfo {
+            gpus,
+            ram_gb,
+            cpu_cores,
+            unified_memory: false,
+        }
+    }
+}
+
+/// sysfs（通常は/sys）のclass/drmからGPUを列挙する
+///
+/// AMDはmem_info_vram_totalからビデオメモリを読む。IntelなどVRAMの情報がないものはNone。
+pub fn probe_drm(sysfs_root: &Path) -> Vec<GpuInfo> {
+    let Ok(entries) = std::fs::read_dir(sysfs_root.join("class/drm")) else {
+        return Vec::new();
+    };
+
+    let mut cards: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        // card0-HDMI-A-1などのコネクタは除く
+        .filter(|name| {
+            name.strip_prefix("card").is_some_and(|number| {
+                !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+            })
+        })
+        .collect();
+    cards.sort();
+
+    cards
+        .into_iter()
+        .filter_map(|card| {
+            let device = sysfs_root.join("class/drm").join(&card).join("device");
+            let vendor_id = read_hex(&device.join("vendor"))?;
+            let device_id = read_hex(&device.join("device")).unwrap_or(0);
+            let vendor = GpuVendor::from_pci_id(vendor_id);
+            let vram_gb = std::fs::read_to_string(device.join("mem_info_vram_total"))
+                .ok()
+                .and_then(|bytes| bytes.trim().parse::<u64>().ok())
+                .map(bytes_to_gb)
+                // APUのBIOSで確保された小さな領域は専用メモリとみなさない
+                .filter(|&gb| gb >= 1.0);
+            Some(GpuInfo {
+                vendor,
+                name: format!(
+                    "{} GPU ({:04x}:{:04x})",
+                    vendor.name(),
+                    vendor_id,
+                    device_id
+                ),
+                vram_gb,
+            })
+        })
+        .collect()
+}
+
+/// procfs（通常は/proc）のmeminfoからMemTotalを読む
+pub fn read_meminfo_gb(procfs_root: &Path) -> Option<f32> {
+    let meminfo = std::fs::read_to_string(procfs_root.join("meminfo")).ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb: u64 = line
+        .trim_start_matches("MemTotal:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(bytes_to_gb(kb * 1024))
+}
+
+fn read_hex(path: &Path) -> Option<u32> {
+    let text = std::fs::read_to_string(path).ok()?;
+    u32::from_str_radix(text.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn bytes_to_gb(bytes: u64) -> f32 {
+    bytes as f32 / (1024.0 * 1024.0 * 1024.0)
+}
+
+/// nvidia-smiでNVIDIAのGPUを列挙する（使えない場合は空）
+fn nvidia_gpus() -> Vec<GpuInfo> {
+    let mut command = Command::new("nvidia-smi");
+    command
+        .arg("--query-gpu=name,memory.total")
+        .arg("--format=csv,noheader,nounits");
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let Ok(output) = command.output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    // 複数行の出力を処理（複数GPUの場合）
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, memory) = line.rsplit_once(',')?;
+            let vram_mb: f32 = memory.trim().parse().ok()?;
+            Some(GpuInfo {
+                vendor: GpuVendor::Nvidia,
+                name: name.trim().to_string(),
+                vram_gb: Some(vram_mb / 1024.0), // MBからGBに変換
+            })
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn sysctl(name: &str) -> Option<String> {
+    let output = Command::new("sysctl").arg("-n").arg(name).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn windows_ram_gb() -> Option<f32> {
+    let mut command = Command::new("powershell");
+    command
+        .arg("-Command")
+        .arg("(Get-CimInstance Win32_ComputerSystem).TotalPhysicalMemory");
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    command.creation_flags(CREATE_NO_WINDOW);
+
+    let output = command.output().ok()?;
+    let bytes: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(bytes_to_gb(bytes))
+}
+
+/// 推奨候補のモデル（sizeはOllamaの既定の量子化でのダウンロードサイズ）
+struct ModelSpec {
+    name: &'static str,
+    label: &'static str,
+    description: &'static str,
+    /// 10億パラメータ単位
+    parameters_b: f32,
+    size_gb: f32,
+}
+
+const MODEL_CATALOG: &[ModelSpec] = &[
+    ModelSpec {
+        name: "qwen2.5:32b",
+        label: "Qwen 2.5 32B",
+        description: "高性能だが大量のメモリが必要",
+        parameters_b: 32.8,
+        size_gb: 20.0,
+    },
+    ModelSpec {
+        name: "phi4:14b",
+        label: "Phi-4 14B",
+        description: "バランスの良い高性能モデル",
+        parameters_b: 14.7,
+        size_gb: 9.1,
+    },
+    ModelSpec {
+        name: "qwen2.5:14b",
+        label: "Qwen 2.5 14B",
+        description: "JSON出力が安定した中～大型モデル",
+        parameters_b: 14.8,
+        size_gb: 9.0,
+    },
+    ModelSpec {
+        name: "gemma3:12b",
+        label: "Gemma 3 12B",
+        description: "中～大型の汎用モデル",
+        parameters_b: 12.2,
+        size_gb: 8.1,
+    },
+    ModelSpec {
+        name: "qwen2.5:7b",
+        label: "Qwen 2.5 7B",
+        description: "中程度の性能",
+        parameters_b: 7.6,
+        size_gb: 4.7,
+    },
+    ModelSpec {
+        name: "codellama:7b",
+        label: "CodeLlama 7B",
+        description: "コード生成特化",
+        parameters_b: 7.0,
+        size_gb: 3.8,
+    },
+    ModelSpec {
+        name: "gemma3:4b",
+        label: "Gemma 3 4B",
+        description: "軽量な汎用モデル",
+        parameters_b: 4.3,
+        size_gb: 3.3,
+    },
+    ModelSpec {
+        name: "llama3.2:3b",
+        label: "Llama 3.2 3B",
+        description: "軽量で高速",
+        parameters_b: 3.2,
+        size_gb: 2.0,
+    },
+    ModelSpec {
+        name: "llama3.2:1b",
+        label: "Llama 3.2 1B",
+        description: "最軽量",
+        parameters_b: 1.2,
+        size_gb: 1.3,
+    },
+];
+
+/// モデル以外に必要なメモリ（コンテキスト、ランタイム）
+const OVERHEAD_GB: f32 = 1.5;
+
+/// 推論を実行する場所
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    Gpu,
+    /// GPUとCPUに分けて実行する
+    Partial,
+    Cpu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Speed {
+    Fast,
+    Moderate,
+    Slow,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRecommendation {
+    pub name: String,
+    pub label: String,
+    pub description: String,
+    pub size_gb: f32,
+    pub device: Device,
+    /// 生成速度の目安
+    pub tokens_per_second: f32,
+    pub speed: Speed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRecommendations {
+    pub hardware: HardwareInfo,
+    /// おすすめ順（メモリに収まらないモデルは含めない）
+    pub models: Vec<ModelRecommendation>,
+}
+
+/// ハードウェアを調べ、実行できるモデルをおすすめ順に返す
+#[tauri::command]
+pub async fn recommend_models() -> Result<ModelRecommendations, String> {
+    let hardware = probe_hardware().await?;
+    let models = recommend(&hardware);
+    Ok(ModelRecommendations { hardware, models })
+}
+
+/// 実用的な速度（Moderate以上）で動くモデルを大きい順に、続いてそれ以外を速い順に並べる
+pub fn recommend(hardware: &HardwareInfo) -> Vec<ModelRecommendation> {
+    let mut models: Vec<(f32, ModelRecommendation)> = MODEL_CATALOG
+        .iter()
+        .filter_map(|spec| {
+            let (device, tokens_per_second) = estimate(hardware, spec.size_gb)?;
+            let speed = if tokens_per_second >= 20.0 {
+                Speed::Fast
+            } else if tokens_per_second >= 8.0 {
+                Speed::Moderate
+            } else {
+                Speed::Slow
+            };
+            let recommendation = ModelRecommendation {
+                name: spec.name.to_string(),
+                label: spec.label.to_string(),
+                description: spec.description.to_string(),
+                size_gb: spec.size_gb,
+                device,
+                tokens_per_second,
+                speed,
+            };
+            Some((spec.parameters_b, recommendation))
+        })
+        .collect();
+
+    models.sort_by(|(a_params, a), (b_params, b)| {
+        let a_usable = a.speed != Speed::Slow;
+        let b_usable = b.speed != Speed::Slow;
+        b_usable.cmp(&a_usable).then_with(|| {
+            if a_usable {
+                b_params.total_cmp(a_params)
+            } else {
+                b.tokens_per_second.total_cmp(&a.tokens_per_second)
+            }
+        })
+    });
+    models.into_iter().map(|(_, model)| model).collect()
+}
+
+/// 実行場所と生成速度を見積もる（メモリに収まらなければNone）
+///
+/// 生成はメモリ帯域律速なので、1トークンごとにモデル全体を読むものとして計算する。
+fn estimate(hardware: &HardwareInfo, size_gb: f32) -> Option<(Device, f32)> {
+    // メモリ帯域の目安（GB/s）と実効効率
+    const CPU_BANDWIDTH: f32 = 40.0;
+    const UNIFIED_BANDWIDTH: f32 = 120.0;
+    const EFFICIENCY: f32 = 0.6;
+
+    let required_gb = size_gb + OVERHEAD_GB;
+    // OSや他のアプリの分を残す
+    let available_ram_gb = hardware.ram_gb * 0.6;
+
+    if hardware.unified_memory {
+        return (required_gb <= hardware.ram_gb * 0.7)
+            .then(|| (Device::Gpu, UNIFIED_BANDWIDTH / size_gb * EFFICIENCY));
+    }
+
+    let gpu = hardware
+        .gpus
+        .iter()
+        .filter_map(|gpu| gpu.vram_gb.map(|vram| (gpu.vendor, vram)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((vendor, vram_gb)) = gpu {
+        let gpu_bandwidth = match vendor {
+            GpuVendor::Nvidia => 400.0,
+            GpuVendor::Amd => 350.0,
+            _ => 250.0,
+        };
+        if required_gb <= vram_gb {
+            return Some((Device::Gpu, gpu_bandwidth / size_gb * EFFICIENCY));
+        }
+        if required_gb <= vram_gb + available_ram_gb {
+            // GPUに載らない分はCPUで処理する
+            let gpu_part = (vram_gb - OVERHEAD_GB).max(0.0).min(size_gb);
+            let seconds = gpu_part / gpu_bandwidth + (size_gb - gpu_part) / CPU_BANDWIDTH;
+            return Some((Device::Partial, EFFICIENCY / seconds));
+        }
+        return None;
+    }
+
+    (required_gb <= available_ram_gb).then(|| (Device::Cpu, CPU_BANDWIDTH / size_gb * EFFICIENCY))
+}
+
+/// 最も大きいビデオメモリ（GB）
+#[tauri::command]
+pub async fn get_vram() -> Result<f32, String> {
+    probe_hardware()
+        .await?
+        .max_vram_gb()
+        .ok_or_else(|| "No VRAM detected".to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn get_vram_from_dxdiag() -> Result<f32, String> {
+    let mut command = std::process::Command::new("powershell");
+    command.arg("-Command").arg(r#"
+        $xmlPath = Join-Path $env:TEMP "dxdiag_temp.xml"
+        try {
+            Start-Process dxdiag -ArgumentList "/x","$xmlPath" -Wait -NoNewWindow
+            Start-Sleep -Seconds 2
+            if (Test-Path $xmlPath) {
+                $content = Get-Content $xmlPath -Raw
+                $maxVramMB = 0
+                
+                # 複数のDedicatedMemoryエントリを検索
+                $matches = [regex]::Matches($content, '<DedicatedMemory>(\d+)\s*MB</DedicatedMemory>')
+                foreach ($match in $matches) {
+                    $vramMB = [int]$match.Groups[1].Value
+                    if ($vramMB -gt $maxVramMB) {
+                        $maxVramMB = $vramMB
+                    }
+                }
+                
+                if ($maxVramMB -gt 0) {
+                    [math]::Round($maxVramMB / 1024, 2)
+                } else {
+                    "0"
+                }
+            } else {
+                "0"
+            }
+        } catch {
+            "0"
+        } finally {
+            if (Test-Path $xmlPath) {
+                Remove-Item $xmlPath -ErrorAction SilentlyContinue
+            }
+        }
+    "#);
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    match command.output() {
+        Ok(output) => {
+            if output.status.success() {
+                let vram_str = String::from_utf8_lossy(&output.stdout);
+                if let Ok(vram) = vram_str.trim().parse::<f32>() {
+                    if vram > 0.0 {
+                        Ok(vram)
+                    } else {
+                        Err("No VRAM detected".into())
+                    }
+                } else {
+                    Err("Failed to parse VRAM value from dxdiag".into())
+                }
+            } else {
+                Err(format!(
+                    "dxdiag error: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ))
+            }
+        }
+        Err(e) => Err(format!("Failed to execute dxdiag: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// テスト用のsysfs・procfsを置く一時ディレクトリ（終了時に削除する）
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "souon-hardware-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, relative: &str, content: &str) {
+            let path = self.0.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn probe_drm_reads_amd_and_intel_cards() {
+        let sysfs = TempTree::new("drm");
+        // AMDの専用GPU（16GB）
+        sysfs.write("class/drm/card1/device/vendor", "0x1002\n");
+        sysfs.write("class/drm/card1/device/device", "0x73bf\n");
+        sysfs.write(
+            "class/drm/card1/device/mem_info_vram_total",
+            &format!("{}\n", 16u64 << 30),
+        );
+        // Intelの内蔵GPU（VRAMの情報なし）
+        sysfs.write("class/drm/card0/device/vendor", "0x8086\n");
+        sysfs.write("class/drm/card0/device/device", "0x9a49\n");
+        // コネクタとベンダーが読めないカードは除く
+        sysfs.write("class/drm/card0-HDMI-A-1/device/vendor", "0x8086\n");
+        std::fs::create_dir_all(sysfs.0.join("class/drm/card2/device")).unwrap();
+        // 番号のないエントリも除く
+        sysfs.write("class/drm/card/device/vendor", "0x10de\n");
+
+        let gpus = probe_drm(&sysfs.0);
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].vendor, GpuVendor::Intel);
+        assert_eq!(gpus[0].name, "Intel GPU (8086:9a49)");
+        assert_eq!(gpus[0].vram_gb, None);
+        assert_eq!(gpus[1].vendor, GpuVendor::Amd);
+        assert_eq!(gpus[1].name, "AMD GPU (1002:73bf)");
+        assert_eq!(gpus[1].vram_gb, Some(16.0));
+    }
+
+    #[test]
+    fn probe_drm_ignores_small_apu_carveout() {
+        let sysfs = TempTree::new("apu");
+        sysfs.write("class/drm/card0/device/vendor", "0x1002\n");
+        sysfs.write("class/drm/card0/device/device", "0x15bf\n");
+        sysfs.write(
+            "class/drm/card0/device/mem_info_vram_total",
+            &format!("{}\n", 512u64 << 20),
+        );
+
+        let gpus = probe_drm(&sysfs.0);
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].vendor, GpuVendor::Amd);
+        assert_eq!(gpus[0].vram_gb, None);
+    }
+
+    #[test]
+    fn probe_drm_without_drm_class_is_empty() {
+        let sysfs = TempTree::new("empty");
+        assert!(probe_drm(&sysfs.0).is_empty());
+    }
+
+    #[test]
+    fn read_meminfo_gb_reads_mem_total() {
+        let procfs = TempTree::new("meminfo");
+        procfs.write(
+            "meminfo",
+            "MemTotal:       32780412 kB\nMemFree:         1234567 kB\nMemAvailable:   20000000 kB\n",
+        );
+
+        let gb = read_meminfo_gb(&procfs.0).unwrap();
+        assert!((gb - 31.26).abs() < 0.01, "{}", gb);
+    }
+
+    #[test]
+    fn read_meminfo_gb_without_mem_total_is_none() {
+        let procfs = TempTree::new("no-meminfo");
+        assert_eq!(read_meminfo_gb(&procfs.0), None);
+        procfs.write("meminfo", "MemFree:         1234567 kB\n");
+        assert_eq!(read_meminfo_gb(&procfs.0), None);
+    }
+
+    fn hardware(gpus: Vec<GpuInfo>, ram_gb: f32, unified_memory: bool) -> HardwareInfo {
+        HardwareInfo {
+            gpus,
+            ram_gb,
+            cpu_cores: 8,
+            unified_memory,
+        }
+    }
+
+    fn nvidia(vram_gb: f32) -> GpuInfo {
+        GpuInfo {
+            vendor: GpuVendor::Nvidia,
+            name: "NVIDIA GeForce RTX".to_string(),
+            vram_gb: Some(vram_gb),
+        }
+    }
+
+    fn names(models: &[ModelRecommendation]) -> Vec<&str> {
+        models.iter().map(|model| model.name.as_str()).collect()
+    }
+
+    /// 実用的な速度のモデルが先、その中ではパラメータ数の大きい順
+    fn assert_ordered(models: &[ModelRecommendation]) {
+        let parameters = |name: &str| {
+            MODEL_CATALOG
+                .iter()
+                .find(|spec| spec.name == name)
+                .unwrap()
+                .parameters_b
+        };
+        for pair in models.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            match (a.speed == Speed::Slow, b.speed == Speed::Slow) {
+                (false, false) => assert!(parameters(&a.name) >= parameters(&b.name)),
+                (true, true) => assert!(a.tokens_per_second >= b.tokens_per_second),
+                (usable_after_slow, _) => assert!(!usable_after_slow, "{:?}", names(models)),
+            }
+        }
+    }
+
+    #[test]
+    fn recommend_uses_unified_memory_as_gpu_memory() {
+        let apple = hardware(Vec::new(), 16.0, true);
+        let models = recommend(&apple);
+        assert_ordered(&models);
+
+        // 16GBの7割（11.2GB）まで使える
+        assert!(!names(&models).contains(&"qwen2.5:32b"));
+        assert!(names(&models).contains(&"qwen2.5:14b"));
+        assert!(models.iter().all(|model| model.device == Device::Gpu));
+        assert_ne!(models[0].speed, Speed::Slow);
+        // 帯域はユニファイドメモリの値（120GB/s）で見積もる
+        assert_eq!(estimate(&apple, 6.0), Some((Device::Gpu, 12.0)));
+
+        // 同じ容量でもユニファイドメモリでなければ14Bは載らない
+        let cpu = recommend(&hardware(Vec::new(), 16.0, false));
+        assert!(!names(&cpu).contains(&"qwen2.5:14b"));
+    }
+
+    #[test]
+    fn recommend_splits_between_gpu_and_cpu() {
+        let desktop = hardware(vec![nvidia(24.0)], 32.0, false);
+        let models = recommend(&desktop);
+        assert_ordered(&models);
+
+        // 24GBならすべてGPUに載り、32Bが最初に来る
+        assert_eq!(models.len(), MODEL_CATALOG.len());
+        assert!(models.iter().all(|model| model.device == Device::Gpu));
+        assert_eq!(models[0].name, "qwen2.5:32b");
+        assert_eq!(models[0].speed, Speed::Moderate);
+        assert_eq!(estimate(&desktop, 20.0), Some((Device::Gpu, 12.0)));
+
+        // 16GBでは32BがGPUとシステムメモリに分かれ、遅くなる
+        let smaller = hardware(vec![nvidia(16.0)], 32.0, false);
+        let (device, tokens_per_second) = estimate(&smaller, 20.0).unwrap();
+        assert_eq!(device, Device::Partial);
+        assert!(tokens_per_second < 8.0, "{}", tokens_per_second);
+        let models = recommend(&smaller);
+        assert_ordered(&models);
+        let large = models.iter().find(|m| m.name == "qwen2.5:32b").unwrap();
+        assert_eq!(large.device, Device::Partial);
+        assert_eq!(large.speed, Speed::Slow);
+        assert_eq!(models.last().unwrap().name, "qwen2.5:32b");
+
+        // GPUとシステムメモリを合わせても足りなければ除く
+        let tiny = hardware(vec![nvidia(4.0)], 8.0, false);
+        assert_eq!(estimate(&tiny, 20.0), None);
+    }
+
+    #[test]
+    fn recommend_cpu_only_excludes_large_models() {
+        let laptop = hardware(Vec::new(), 8.0, false);
+        let models = recommend(&laptop);
+        assert_ordered(&models);
+
+        // 8GBの6割（4.8GB）に収まる小型モデルだけ
+        for name in [
+            "qwen2.5:32b",
+            "phi4:14b",
+            "qwen2.5:14b",
+            "gemma3:12b",
+            "qwen2.5:7b",
+        ] {
+            assert!(!names(&models).contains(&name), "{}", name);
+        }
+        assert!(names(&models).contains(&"llama3.2:3b"));
+        assert!(names(&models).contains(&"llama3.2:1b"));
+        assert!(models.iter().all(|model| model.device == Device::Cpu));
+
+        // VRAMのわからないGPU（内蔵GPU）はCPUとして扱う
+        let integrated = GpuInfo {
+            vendor: GpuVendor::Intel,
+            name: "Intel GPU".to_string(),
+            vram_gb: None,
+        };
+        assert_eq!(
+            names(&recommend(&hardware(vec![integrated], 8.0, false))),
+            names(&models)
+        );
+    }
+}
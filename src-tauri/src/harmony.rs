@@ -0,0 +1,17 @@
+use souon_core::harmony::{self, HarmonyAnalysis};
+use souon_core::tempo::TempoEvent;
+
+/// otherとbassのステムから調と小節ごとのコードを推定する
+///
+/// bassが空の場合はotherのみで推定する。
+#[tauri::command]
+pub async fn detect_harmony(
+    music_tempo_list: Vec<TempoEvent>,
+    other: String,
+    bass: String,
+) -> Result<HarmonyAnalysis, String> {
+    // 重い処理を別スレッドで実行
+    tokio::task::spawn_blocking(move || harmony::detect_harmony(music_tempo_list, &other, &bass))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
@@ -0,0 +1,37 @@
+use souon_core::hpss;
+use souon_core::stem::{self, StemCodec, StemJobQueue};
+
+/// Python/Demucsを使わずにHPSSでステムを生成する
+///
+/// 戻り値はDemucsと同じくbass, drums, other, vocalsのData URLを"\n"で結合したもの。
+/// ボーカル推定を行わない場合（またはモノラル入力の場合）、vocalsは空文字列になる。
+#[tauri::command]
+pub async fn hpss(
+    app_handle: tauri::AppHandle,
+    queue: tauri::State<'_, StemJobQueue>,
+    input_base64: String,
+    mime_type: String,
+    codec: Option<StemCodec>,
+    vocals: Option<bool>,
+    job_id: Option<String>,
+) -> Result<String, String> {
+    let codec = codec.unwrap_or_default();
+    let vocals = vocals.unwrap_or(true);
+    let (job, cancel_rx) =
+        stem::start_stem_job(&crate::stem::jobs_dir(&app_handle)?, &queue, job_id)?;
+
+    let result = hpss::run_hpss_job(
+        &job,
+        input_base64,
+        mime_type,
+        codec,
+        vocals,
+        cancel_rx.clone(),
+    )
+    .await;
+    if *cancel_rx.borrow() {
+        log::info!("Stem job {} cancelled", job.id);
+        return Err(format!("Stem job {} was cancelled", job.id));
+    }
+    result
+}
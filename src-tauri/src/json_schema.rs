@@ -0,0 +1,372 @@
+use serde_json::{Map, Value};
+
+// LLMの構造化出力を検証するためのJSON Schemaのサブセット
+//
+// 対応するキーワード: type, enum, properties, required, additionalProperties, items,
+// minItems, maxItems, minimum, maximum, multipleOf, minLength, maxLength
+// それ以外のキーワードは無視する。
+
+/// スキーマに一致しない箇所
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    /// JSON Pointer（例: "/3/0/key"、ルートは""）
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// valueがschemaに一致するか検証する（最初に見つかった不一致を返す）
+pub fn validate(schema: &Value, value: &Value) -> Result<(), SchemaError> {
+    validate_at(schema, value, &mut String::new())
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &mut String) -> Result<(), SchemaError> {
+    let Some(schema) = schema.as_object() else {
+        // trueや{}以外のスキーマは想定しない
+        return Ok(());
+    };
+    let error = |path: &str, message: String| SchemaError {
+        path: path.to_string(),
+        message,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            return Err(error(
+                path,
+                format!("expected {}, got {}", types.join(" or "), type_name(value)),
+            ));
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.iter().any(|a| json_equal(a, value)) {
+            return Err(error(
+                path,
+                format!("{} is not one of {}", value, Value::Array(allowed.clone())),
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(schema, object, path)?,
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    return Err(error(
+                        path,
+                        format!("expected at least {} items, got {}", min, items.len()),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    return Err(error(
+                        path,
+                        format!("expected at most {} items, got {}", max, items.len()),
+                    ));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    let len = path.len();
+                    path.push_str(&format!("/{}", i));
+                    validate_at(item_schema, item, path)?;
+                    path.truncate(len);
+                }
+            }
+        }
+        Value::Number(number) => {
+            let n = number.as_f64().unwrap_or(f64::NAN);
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    return Err(error(path, format!("{} is less than minimum {}", n, min)));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    return Err(error(
+                        path,
+                        format!("{} is greater than maximum {}", n, max),
+                    ));
+                }
+            }
+            if let Some(step) = schema.get("multipleOf").and_then(Value::as_f64) {
+                let ratio = n / step;
+                if step > 0.0 && (ratio - ratio.round()).abs() > 1e-9 {
+                    return Err(error(path, format!("{} is not a multiple of {}", n, step)));
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    return Err(error(
+                        path,
+                        format!("expected at least {} characters, got {}", min, len),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    return Err(error(
+                        path,
+                        format!("expected at most {} characters, got {}", max, len),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &mut String,
+) -> Result<(), SchemaError> {
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                return Err(SchemaError {
+                    path: path.clone(),
+                    message: format!("missing required property \"{}\"", name),
+                });
+            }
+        }
+    }
+
+    for (name, property) in object {
+        let len = path.len();
+        path.push('/');
+        path.push_str(&name.replace('~', "~0").replace('/', "~1"));
+
+        match properties.and_then(|p| p.get(name)) {
+            Some(property_schema) => validate_at(property_schema, property, path)?,
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return Err(SchemaError {
+                        path: path.clone(),
+                        message: "unexpected property".to_string(),
+                    });
+                }
+                Some(additional @ Value::Object(_)) => validate_at(additional, property, path)?,
+                _ => {}
+            },
+        }
+
+        path.truncate(len);
+    }
+
+    Ok(())
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// 数値は1と1.0を等しいものとして比較する
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// Google AI Studio（responseSchema）が受け付けないキーワードを取り除く
+///
+/// 取り除いた制約はvalidateで検証する。
+pub fn to_gemini_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(object) => {
+            let is_string = object.get("type").and_then(Value::as_str) == Some("string");
+            let mut result = Map::new();
+            for (key, value) in object {
+                match key.as_str() {
+                    "additionalProperties" | "multipleOf" | "$schema" => {}
+                    // enumは文字列型でのみ使える
+                    "enum" if !is_string => {}
+                    "properties" => {
+                        let properties = value
+                            .as_object()
+                            .map(|p| {
+                                p.iter()
+                                    .map(|(name, s)| (name.clone(), to_gemini_schema(s)))
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        result.insert(key.clone(), Value::Object(properties));
+                    }
+                    "items" => {
+                        result.insert(key.clone(), to_gemini_schema(value));
+                    }
+                    _ => {
+                        result.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Value::Object(result)
+        }
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn note_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "integer", "minimum": 1, "maximum": 7 },
+                "beat": { "type": "number", "minimum": 0, "multipleOf": 0.25 },
+                "kind": { "type": "string", "enum": ["tap", "hold"] }
+            },
+            "required": ["key", "beat"],
+            "additionalProperties": false
+        })
+    }
+
+    fn error_of(schema: &Value, value: Value) -> SchemaError {
+        validate(schema, &value).unwrap_err()
+    }
+
+    #[test]
+    fn accepts_matching_value() {
+        let schema = note_schema();
+        assert_eq!(
+            validate(&schema, &json!({ "key": 7, "beat": 2.75, "kind": "hold" })),
+            Ok(())
+        );
+        // 整数の1は数値の1.0として扱う
+        assert_eq!(validate(&json!({ "enum": [1.0] }), &json!(1)), Ok(()));
+    }
+
+    #[test]
+    fn rejects_value_outside_enum() {
+        let error = error_of(
+            &note_schema(),
+            json!({ "key": 1, "beat": 0, "kind": "slide" }),
+        );
+        assert_eq!(error.path, "/kind");
+        assert_eq!(error.message, "\"slide\" is not one of [\"tap\",\"hold\"]");
+    }
+
+    #[test]
+    fn checks_minimum_and_maximum() {
+        let schema = note_schema();
+        let error = error_of(&schema, json!({ "key": 0, "beat": 0 }));
+        assert_eq!(error.path, "/key");
+        assert_eq!(error.message, "0 is less than minimum 1");
+
+        let error = error_of(&schema, json!({ "key": 8, "beat": 0 }));
+        assert_eq!(error.message, "8 is greater than maximum 7");
+
+        // 境界値は含む
+        assert_eq!(validate(&schema, &json!({ "key": 1, "beat": 0 })), Ok(()));
+    }
+
+    #[test]
+    fn checks_multiple_of_quarter_beats() {
+        let schema = note_schema();
+        for beat in [0.25, 1.5, 3.75, 12.0] {
+            assert_eq!(
+                validate(&schema, &json!({ "key": 1, "beat": beat })),
+                Ok(())
+            );
+        }
+        let error = error_of(&schema, json!({ "key": 1, "beat": 1.1 }));
+        assert_eq!(error.path, "/beat");
+        assert_eq!(error.message, "1.1 is not a multiple of 0.25");
+    }
+
+    #[test]
+    fn rejects_additional_properties() {
+        let error = error_of(&note_schema(), json!({ "key": 1, "beat": 0, "a/b": true }));
+        assert_eq!(error.path, "/a~1b");
+        assert_eq!(error.message, "unexpected property");
+
+        // スキーマで指定された追加プロパティは検証する
+        let schema = json!({ "type": "object", "additionalProperties": { "type": "string" } });
+        assert_eq!(validate(&schema, &json!({ "a": "x" })), Ok(()));
+        assert_eq!(error_of(&schema, json!({ "a": 1 })).path, "/a");
+    }
+
+    #[test]
+    fn reports_missing_required_property_on_parent() {
+        let error = error_of(&note_schema(), json!({ "key": 1 }));
+        assert_eq!(error.path, "");
+        assert_eq!(error.message, "missing required property \"beat\"");
+        assert_eq!(error.to_string(), "/: missing required property \"beat\"");
+    }
+
+    #[test]
+    fn reports_path_inside_nested_arrays() {
+        let schema = json!({
+            "type": "array",
+            "items": {
+                "type": "array",
+                "minItems": 1,
+                "items": note_schema()
+            }
+        });
+        let value = json!([
+            [{ "key": 1, "beat": 0 }],
+            [{ "key": 2, "beat": 1 }, { "key": 3, "beat": -1 }]
+        ]);
+        let error = error_of(&schema, value);
+        assert_eq!(error.path, "/1/1/beat");
+        assert_eq!(error.to_string(), "/1/1/beat: -1 is less than minimum 0");
+
+        let error = error_of(&schema, json!([[{ "key": 1, "beat": 0 }], []]));
+        assert_eq!(error.path, "/1");
+        assert_eq!(error.message, "expected at least 1 items, got 0");
+
+        let error = error_of(&schema, json!([["tap"]]));
+        assert_eq!(error.path, "/0/0");
+        assert_eq!(error.message, "expected object, got string");
+    }
+}
@@ -0,0 +1,182 @@
+/// ストリーミング中のJSONオブジェクトから、値が閉じたトップレベルのメンバーを順に取り出す
+///
+/// 最初の'{'より前（コードブロックの開始など）は読み飛ばす。値の中身は検証しない。
+#[derive(Debug, Default)]
+pub struct ObjectMemberStream {
+    buffer: String,
+    /// 次に走査するバイト位置
+    position: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    done: bool,
+    key_start: Option<usize>,
+    key: Option<String>,
+    value_start: Option<usize>,
+}
+
+impl ObjectMemberStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 受信したテキストを追加し、新たに閉じたメンバー（キー、値のJSON）を返す
+    pub fn push(&mut self, text: &str) -> Vec<(String, String)> {
+        self.buffer.push_str(text);
+        let mut members = Vec::new();
+
+        // 構造を表す文字はすべてASCIIなので、バイト単位で走査してよい
+        while !self.done && self.position < self.buffer.len() {
+            let i = self.position;
+            let b = self.buffer.as_bytes()[i];
+            self.position += 1;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                    if self.depth == 1 {
+                        if let Some(start) = self.key_start.take() {
+                            self.key = serde_json::from_str(&self.buffer[start..=i]).ok();
+                        } else if let Some(start) = self.value_start {
+                            members.push(self.finish(start, i + 1));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            match b {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 {
+                        if self.key.is_none() {
+                            self.key_start = Some(i);
+                        } else if self.value_start.is_none() {
+                            self.value_start = Some(i);
+                        }
+                    }
+                }
+                b'{' if self.depth == 0 => self.depth = 1,
+                b'{' | b'[' if self.depth > 0 => {
+                    if self.depth == 1 && self.key.is_some() && self.value_start.is_none() {
+                        self.value_start = Some(i);
+                    }
+                    self.depth += 1;
+                }
+                b'}' | b']' if self.depth == 1 => {
+                    // オブジェクトの終わり（直前が数値などの値ならそれも閉じる）
+                    if let Some(start) = self.value_start {
+                        members.push(self.finish(start, i));
+                    }
+                    self.done = true;
+                }
+                b'}' | b']' if self.depth > 1 => {
+                    self.depth -= 1;
+                    if self.depth == 1 {
+                        if let Some(start) = self.value_start {
+                            members.push(self.finish(start, i + 1));
+                        }
+                    }
+                }
+                b',' if self.depth == 1 => {
+                    if let Some(start) = self.value_start {
+                        members.push(self.finish(start, i));
+                    }
+                }
+                b':' => {}
+                _ if self.depth == 1
+                    && !b.is_ascii_whitespace()
+                    && self.key.is_some()
+                    && self.value_start.is_none() =>
+                {
+                    self.value_start = Some(i);
+                }
+                _ => {}
+            }
+        }
+
+        members
+    }
+
+    fn finish(&mut self, start: usize, end: usize) -> (String, String) {
+        self.value_start = None;
+        (
+            self.key.take().unwrap_or_default(),
+            self.buffer[start..end].trim().to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "```json\n{\"title\": \"say \\\"hi\\\" {not} [a] \\\\\", \"bars\": [{\"notes\": [{\"key\": 1}]}, {}], \"meta\": {\"inner\": {\"x\": \"}\"}}, \"bpm\": 120.5, \"ok\": true, \"歌\": null}\n```";
+
+    fn expected() -> Vec<(String, String)> {
+        [
+            ("title", r#""say \"hi\" {not} [a] \\""#),
+            ("bars", r#"[{"notes": [{"key": 1}]}, {}]"#),
+            ("meta", r#"{"inner": {"x": "}"}}"#),
+            ("bpm", "120.5"),
+            ("ok", "true"),
+            ("歌", "null"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+    }
+
+    fn push_chunks<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Vec<(String, String)> {
+        let mut stream = ObjectMemberStream::new();
+        chunks
+            .into_iter()
+            .flat_map(|chunk| stream.push(chunk))
+            .collect()
+    }
+
+    #[test]
+    fn emits_each_member_once_at_any_split() {
+        assert_eq!(push_chunks([INPUT]), expected());
+
+        // キー、エスケープされた引用符、文字列中の括弧、入れ子のオブジェクトの途中で分割する
+        for (split, _) in INPUT.char_indices().skip(1) {
+            let (head, tail) = INPUT.split_at(split);
+            assert_eq!(push_chunks([head, tail]), expected(), "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn emits_each_member_once_char_by_char() {
+        let chars: Vec<String> = INPUT.chars().map(String::from).collect();
+        assert_eq!(push_chunks(chars.iter().map(String::as_str)), expected());
+    }
+
+    #[test]
+    fn emits_member_only_when_its_value_closes() {
+        let mut stream = ObjectMemberStream::new();
+        assert!(stream.push("{\"ti").is_empty());
+        assert!(stream.push("tle\": \"a\\\"").is_empty());
+        assert_eq!(
+            stream.push("b\", \"n\": {\"x\": [1,"),
+            vec![("title".to_string(), r#""a\"b""#.to_string())]
+        );
+        assert!(stream.push(" 2]").is_empty());
+        assert_eq!(
+            stream.push("}, \"bpm\": 1"),
+            vec![("n".to_string(), r#"{"x": [1, 2]}"#.to_string())]
+        );
+        // 数値は区切りが来るまで閉じない
+        assert!(stream.push("20").is_empty());
+        assert_eq!(
+            stream.push("}{\"late\": 1}"),
+            vec![("bpm".to_string(), "120".to_string())]
+        );
+        // オブジェクトが閉じた後の入力は無視する
+        assert!(stream.push(", \"after\": 2}").is_empty());
+    }
+}
@@ -1,216 +1,518 @@
-use llm::{builder::LLMBuilder, chat::ChatMessage};
+use crate::chat_backend::{self, ChatBackend, ChatRequest, GenerationParams};
+use crate::json_schema::{self, SchemaError};
+use crate::llm_cache::{CacheMode, LlmCache};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tauri::Emitter;
 
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 
-#[tauri::command]
-pub async fn call_llm(model_name: &str, query: &str) -> Result<String, String> {
-    // ヘルパー関数を定義してSendの問題を回避
-    async fn make_llm_request(model_name: &str, query: &str) -> Result<String, String> {
-        let base_url = std::env::var("OLLAMA_URL").unwrap_or("http://127.0.0.1:11434".into());
-
-        let llm = LLMBuilder::new()
-            .backend(llm::builder::LLMBackend::Ollama)
-            .base_url(base_url)
-            .model(model_name)
-            .max_tokens(4000) // 譜面生成に十分な長さに設定
-            .temperature(0.7)
-            .stream(false)
-            .build()
-            .expect("Failed to build LLM (Ollama)");
-
-        let messages = vec![ChatMessage::user().content(query).build()];
-
-        match llm.chat(&messages).await {
-            Ok(response) => {
-                let text = response.text().unwrap_or_default().to_string();
-                Ok(text)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// 会話の1メッセージ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+}
+
+/// チャットに使うバックエンド
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ChatProvider {
+    /// base_urlを省略した場合はOLLAMA_URLまたは127.0.0.1:11434
+    Ollama {
+        model: String,
+        base_url: Option<String>,
+    },
+    /// APIキーはフロントエンドから受け取らず、with_api_keyで保存済みのものを設定する
+    Google {
+        model: String,
+        #[serde(skip)]
+        api_key: String,
+    },
+    /// llama.cpp server、vLLM、LM StudioなどのOpenAI互換API
+    OpenAiCompatible {
+        base_url: String,
+        model: String,
+        #[serde(skip)]
+        api_key: Option<String>,
+    },
+    /// 決まった応答を返すモック（scriptはルールを書いたJSONのパス、詳細はmock_llm）
+    Mock { script: Option<String> },
+}
+
+impl ChatProvider {
+    /// 保存されているAPIキーを設定する（IDはtypeと同じ）
+    pub fn with_api_key(mut self, app_handle: &tauri::AppHandle) -> Result<Self, String> {
+        let id = self.id();
+        match &mut self {
+            ChatProvider::Ollama { .. } | ChatProvider::Mock { .. } => {}
+            ChatProvider::Google { api_key, .. } => {
+                *api_key = crate::credentials::api_key(app_handle, id)?
+                    .ok_or("No API key is stored for Google AI Studio")?;
+            }
+            ChatProvider::OpenAiCompatible { api_key, .. } => {
+                *api_key = crate::credentials::api_key(app_handle, id)?;
             }
-            Err(err) => Err(format!("LLM request failed: {}", err)),
         }
+        Ok(self)
     }
 
-    // 最初のリクエストを試行
-    match make_llm_request(model_name, query).await {
-        Ok(text) => Ok(text),
-        Err(err_msg) => {
-            // 404エラーの場合はモデルをダウンロード
-            if err_msg.contains("404") {
-                // ollama pull <model_name>を実行してモデルを取得する
-                let mut command = std::process::Command::new("ollama");
-                command.arg("pull").arg(model_name);
-
-                #[cfg(target_os = "windows")]
-                {
-                    const CREATE_NO_WINDOW: u32 = 0x08000000;
-                    command.creation_flags(CREATE_NO_WINDOW);
-                }
+    /// typeの値
+    pub fn id(&self) -> &'static str {
+        match self {
+            ChatProvider::Ollama { .. } => "ollama",
+            ChatProvider::Google { .. } => "google",
+            ChatProvider::OpenAiCompatible { .. } => "openAiCompatible",
+            ChatProvider::Mock { .. } => "mock",
+        }
+    }
 
-                let output = command.output().expect("Failed to execute ollama pull");
+    /// リクエスト先（Ollamaは環境変数による切り替えを反映したもの）
+    pub fn base_url(&self) -> String {
+        match self {
+            ChatProvider::Ollama { base_url, .. } => crate::ollama::base_url(base_url.as_deref()),
+            ChatProvider::Google { .. } => crate::google_ai::API_BASE_URL.to_string(),
+            ChatProvider::OpenAiCompatible { base_url, .. } => {
+                base_url.trim_end_matches('/').to_string()
+            }
+            ChatProvider::Mock { script } => script.clone().unwrap_or("builtin".into()),
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        match self {
+            ChatProvider::Ollama { model, .. }
+            | ChatProvider::Google { model, .. }
+            | ChatProvider::OpenAiCompatible { model, .. } => model,
+            ChatProvider::Mock { .. } => "mock",
+        }
+    }
 
+    pub fn temperature(&self) -> f32 {
+        match self {
+            ChatProvider::Ollama { .. } => crate::ollama::TEMPERATURE,
+            ChatProvider::Google { .. } => crate::google_ai::TEMPERATURE,
+            ChatProvider::OpenAiCompatible { .. } => crate::openai_compatible::TEMPERATURE,
+            ChatProvider::Mock { .. } => 0.0,
+        }
+    }
+}
+
+/// チャットコマンド共通のオプション
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatOptions {
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    /// 指定した場合はメッセージの先頭にシステムメッセージとして加える
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub cache: CacheMode,
+}
+
+/// トークン数と所要時間（トークン数はプロバイダーが報告しない場合はNone）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    /// キャッシュから応答した場合はその読み込みにかかった時間
+    pub latency_ms: u64,
+}
+
+impl Usage {
+    /// 複数回のリクエストの合計にする
+    pub fn add(&mut self, other: Usage) {
+        let sum = |a: Option<u32>, b: Option<u32>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        self.prompt_tokens = sum(self.prompt_tokens, other.prompt_tokens);
+        self.completion_tokens = sum(self.completion_tokens, other.completion_tokens);
+        self.latency_ms += other.latency_ms;
+    }
+}
+
+/// 応答と使用量
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatReply<T> {
+    pub content: T,
+    pub usage: Usage,
+}
+
+/// プロバイダーとオプションをまとめたもの（応答のキャッシュもここで扱う）
+pub struct ChatClient {
+    provider: ChatProvider,
+    backend: Box<dyn ChatBackend>,
+    params: GenerationParams,
+    system_prompt: Option<String>,
+    cache: LlmCache,
+}
+
+impl ChatClient {
+    /// 保存されているAPIキーを設定して作る（LLM_MOCKが設定されていればモックにする）
+    pub fn new(
+        app_handle: &tauri::AppHandle,
+        provider: ChatProvider,
+        options: ChatOptions,
+    ) -> Result<Self, String> {
+        let provider = match crate::mock_llm::provider_override() {
+            Some(mock) => {
                 log::info!(
-                    "Ollama pull output: {}",
-                    String::from_utf8_lossy(&output.stdout)
+                    "LLM_MOCK is set, using the mock instead of {}",
+                    provider.id()
                 );
+                mock
+            }
+            None => provider.with_api_key(app_handle)?,
+        };
+        let cache = LlmCache::new(app_handle, options.cache)?;
+        Ok(Self {
+            cache,
+            ..Self::without_cache(provider, options)
+        })
+    }
 
-                if !output.status.success() {
-                    log::error!(
-                        "Ollama pull failed: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                    return Err("Failed to pull model from Ollama".to_string());
-                }
+    /// キャッシュを使わずに作る（APIキーは設定しないので、モックやテストで使う）
+    pub(crate) fn without_cache(provider: ChatProvider, options: ChatOptions) -> Self {
+        // キャッシュのキーに実際の値が入るよう、温度の既定値をここで決める
+        let mut params = options.params;
+        params.temperature.get_or_insert(provider.temperature());
+        Self {
+            backend: chat_backend::for_provider(&provider),
+            provider,
+            params,
+            system_prompt: options
+                .system_prompt
+                .filter(|prompt| !prompt.trim().is_empty()),
+            cache: LlmCache::disabled(),
+        }
+    }
 
-                // モデルのダウンロード後に再度リクエストを試行
-                match make_llm_request(model_name, query).await {
-                    Ok(text) => Ok(text),
-                    Err(e) => Err(format!("Failed to get response from LLM after pull: {}", e)),
+    pub async fn send(
+        &self,
+        messages: &[Message],
+        schema: Option<&Value>,
+    ) -> Result<ChatReply<String>, String> {
+        self.send_stream(messages, schema, &mut |_| {}).await
+    }
+
+    /// ストリーミングでチャットし、受信したトークンをon_tokenに渡す（戻り値は応答全体）
+    ///
+    /// キャッシュから応答する場合は、応答全体を1つのトークンとして渡す。
+    pub async fn send_stream(
+        &self,
+        messages: &[Message],
+        schema: Option<&Value>,
+        on_token: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<ChatReply<String>, String> {
+        let messages: Vec<Message> = self
+            .system_prompt
+            .iter()
+            .map(|prompt| Message {
+                role: Role::System,
+                content: prompt.clone(),
+            })
+            .chain(messages.iter().cloned())
+            .collect();
+
+        let started = std::time::Instant::now();
+        let key = LlmCache::request(&self.provider, &self.params, &messages, schema);
+        let response = match self.cache.get(&key)? {
+            Some(response) => {
+                on_token(&response.text);
+                response
+            }
+            None => {
+                let request = ChatRequest {
+                    messages: &messages,
+                    schema,
+                    params: &self.params,
+                };
+                let response = self.backend.chat_stream(request, on_token).await?;
+                // 保存に失敗しても応答は使えるので、警告だけにする
+                if let Err(e) = self.cache.put(key, &response) {
+                    log::warn!("Failed to cache LLM response: {}", e);
                 }
-            } else {
-                Err(err_msg)
+                response
             }
+        };
+
+        let usage = Usage {
+            prompt_tokens: response.prompt_tokens,
+            completion_tokens: response.completion_tokens,
+            latency_ms: started.elapsed().as_millis() as u64,
+        };
+        log::info!(
+            "LLM usage ({} {}): prompt {:?}, completion {:?} tokens, {}ms",
+            self.provider.id(),
+            self.provider.model(),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.latency_ms
+        );
+        Ok(ChatReply {
+            content: response.text,
+            usage,
+        })
+    }
+
+    /// JSON Schemaに従った応答を要求し、検証してTに変換する
+    ///
+    /// 各プロバイダーにスキーマを渡した上で、応答もこちらで検証する
+    /// （OpenAI互換サーバーにはresponse_formatを無視するものがあるため）。
+    pub async fn chat_structured<T: DeserializeOwned>(
+        &self,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<ChatReply<T>, StructuredOutputError> {
+        let reply = self
+            .send(messages, Some(schema))
+            .await
+            .map_err(StructuredOutputError::Request)?;
+        Ok(ChatReply {
+            content: parse_structured(reply.content, schema)?,
+            usage: reply.usage,
+        })
+    }
+}
+
+/// 構造化出力の失敗
+#[derive(Debug, Clone)]
+pub enum StructuredOutputError {
+    /// リクエスト自体が失敗した
+    Request(String),
+    /// 応答がJSONとして読めない
+    InvalidJson { response: String, message: String },
+    /// 応答がスキーマに一致しない
+    Schema {
+        response: String,
+        error: SchemaError,
+    },
+}
+
+impl StructuredOutputError {
+    /// 不正だった応答（リクエストの失敗ならNone）
+    pub fn response(&self) -> Option<&str> {
+        match self {
+            Self::Request(_) => None,
+            Self::InvalidJson { response, .. } | Self::Schema { response, .. } => Some(response),
         }
     }
 }
 
-#[tauri::command]
-pub async fn call_google_ai(
-    model_name: &str,
-    query: &str,
-    api_key: &str,
-) -> Result<String, String> {
-    // ヘルパー関数を定義してSendの問題を回避
-    async fn make_google_ai_request(
-        model_name: &str,
-        query: &str,
-        api_key: &str,
-    ) -> Result<String, String> {
-        let llm = LLMBuilder::new()
-            .backend(llm::builder::LLMBackend::Google)
-            .model(model_name)
-            .api_key(api_key)
-            .temperature(1.2)
-            .stream(false)
-            .build()
-            .map_err(|e| format!("Failed to build LLM (Google): {}", e))?;
-
-        let messages = vec![ChatMessage::user().content(query).build()];
-
-        match llm.chat(&messages).await {
-            Ok(response) => {
-                let text = response.text().unwrap_or_default().to_string();
-                Ok(text)
+impl std::fmt::Display for StructuredOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "{}", e),
+            Self::InvalidJson { message, .. } => {
+                write!(f, "Response is not valid JSON: {}", message)
             }
-            Err(err) => Err(format!("Google AI Studio request failed: {}", err)),
+            Self::Schema { error, .. } => write!(f, "Response does not match schema at {}", error),
         }
     }
-
-    make_google_ai_request(model_name, query, api_key).await
 }
 
+/// プロバイダーを指定してチャットする
 #[tauri::command]
-pub async fn is_ollama_installed() -> Result<bool, String> {
-    let mut command = std::process::Command::new("ollama");
-    command.arg("--version");
+pub async fn chat(
+    app_handle: tauri::AppHandle,
+    provider: ChatProvider,
+    messages: Vec<Message>,
+    options: Option<ChatOptions>,
+) -> Result<ChatReply<String>, String> {
+    let client = ChatClient::new(&app_handle, provider, options.unwrap_or_default())?;
+    client.send(&messages, None).await
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        const CREATE_NO_WINDOW: u32 = 0x08000000;
-        command.creation_flags(CREATE_NO_WINDOW);
-    }
+/// JSON Schemaに従った応答を要求し、検証済みのJSONを返す
+#[tauri::command]
+pub async fn chat_json(
+    app_handle: tauri::AppHandle,
+    provider: ChatProvider,
+    messages: Vec<Message>,
+    schema: Value,
+    options: Option<ChatOptions>,
+) -> Result<ChatReply<Value>, String> {
+    let client = ChatClient::new(&app_handle, provider, options.unwrap_or_default())?;
+    client
+        .chat_structured(&messages, &schema)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    match command.output() {
-        Ok(output) => Ok(output.status.success()),
-        Err(_) => {
-            // コマンドが見つからない場合はfalseを返す
-            Ok(false)
+/// 応答をJSONとして読み、スキーマで検証してTに変換する
+pub fn parse_structured<T: DeserializeOwned>(
+    response: String,
+    schema: &Value,
+) -> Result<T, StructuredOutputError> {
+    let value: Value = match serde_json::from_str(strip_code_fence(&response)) {
+        Ok(value) => value,
+        Err(e) => {
+            return Err(StructuredOutputError::InvalidJson {
+                response,
+                message: e.to_string(),
+            })
         }
+    };
+    if let Err(error) = json_schema::validate(schema, &value) {
+        return Err(StructuredOutputError::Schema { response, error });
     }
+    serde_json::from_value(value).map_err(|e| StructuredOutputError::InvalidJson {
+        response,
+        message: e.to_string(),
+    })
 }
 
+/// トークン（応答の断片）ごとにchat_tokenイベントを送りながらチャットする
 #[tauri::command]
-pub async fn get_vram() -> Result<f32, String> {
-    // まずnvidia-smiを試す
-    if let Ok(vram) = get_vram_from_nvidia_smi().await {
-        return Ok(vram);
-    }
+pub async fn chat_stream(
+    app_handle: tauri::AppHandle,
+    provider: ChatProvider,
+    messages: Vec<Message>,
+    stream_id: String,
+    options: Option<ChatOptions>,
+) -> Result<ChatReply<String>, String> {
+    let client = ChatClient::new(&app_handle, provider, options.unwrap_or_default())?;
+    client
+        .send_stream(&messages, None, &mut |text| {
+            let token = ChatToken {
+                stream_id: &stream_id,
+                text,
+            };
+            if let Err(e) = app_handle.emit("chat_token", token) {
+                log::warn!("Failed to emit chat token: {}", e);
+            }
+        })
+        .await
+}
 
-    // 次にdxdiagを試す
-    get_vram_from_dxdiag().await
+/// chat_tokenイベントの内容
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ChatToken<'a> {
+    stream_id: &'a str,
+    text: &'a str,
 }
 
-async fn get_vram_from_nvidia_smi() -> Result<f32, String> {
-    let mut nvidia_command = std::process::Command::new("nvidia-smi");
-    nvidia_command
-        .arg("--query-gpu=memory.total")
-        .arg("--format=csv,noheader,nounits");
+/// HTTPエラーの場合は本文を含めたエラーにする
+pub(crate) async fn error_for_status(
+    response: reqwest::Response,
+    service: &str,
+) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(format!(
+        "{} request failed: HTTP {}: {}",
+        service, status, body
+    ))
+}
 
-    #[cfg(target_os = "windows")]
+/// レスポンスを1行ずつon_lineに渡す（NDJSONとServer-Sent Events用）
+///
+/// on_lineがfalseを返したら読み込みをやめる。
+pub(crate) async fn read_lines(
+    mut response: reqwest::Response,
+    mut on_line: impl FnMut(&str) -> Result<bool, String>,
+) -> Result<(), String> {
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?
     {
-        const CREATE_NO_WINDOW: u32 = 0x08000000;
-        nvidia_command.creation_flags(CREATE_NO_WINDOW);
+        pending.extend_from_slice(&chunk);
+        // UTF-8の文字が分割されないよう、改行までそろってからデコードする
+        while let Some(newline) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=newline).collect();
+            if !on_line(String::from_utf8_lossy(&line).trim_end())? {
+                return Ok(());
+            }
+        }
     }
+    if !pending.is_empty() {
+        on_line(String::from_utf8_lossy(&pending).trim_end())?;
+    }
+    Ok(())
+}
 
-    if let Ok(nvidia_output) = nvidia_command.output() {
-        if nvidia_output.status.success() {
-            let vram_str = String::from_utf8_lossy(&nvidia_output.stdout);
-            let mut max_vram = 0.0f32;
+/// Server-Sent Eventsのdata行の中身
+pub(crate) fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim_start)
+}
 
-            // 複数行の出力を処理（複数GPUの場合）
-            for line in vram_str.lines() {
-                if let Ok(vram_mb) = line.trim().parse::<f32>() {
-                    let vram_gb = vram_mb / 1024.0; // MBからGBに変換
-                    if vram_gb > max_vram {
-                        max_vram = vram_gb;
-                    }
-                }
-            }
+#[tauri::command]
+pub async fn call_llm(
+    app_handle: tauri::AppHandle,
+    model_name: &str,
+    query: &str,
+    options: Option<ChatOptions>,
+) -> Result<ChatReply<String>, String> {
+    let provider = ChatProvider::Ollama {
+        model: model_name.to_string(),
+        base_url: None,
+    };
+    let client = ChatClient::new(&app_handle, provider, options.unwrap_or_default())?;
+    client.send(&[Message::user(query)], None).await
+}
 
-            if max_vram > 0.0 {
-                return Ok(max_vram);
-            }
-        }
-    }
+#[tauri::command]
+pub async fn call_google_ai(
+    app_handle: tauri::AppHandle,
+    model_name: &str,
+    query: &str,
+    options: Option<ChatOptions>,
+) -> Result<ChatReply<String>, String> {
+    let provider = ChatProvider::Google {
+        model: model_name.to_string(),
+        api_key: String::new(),
+    };
+    let client = ChatClient::new(&app_handle, provider, options.unwrap_or_default())?;
+    client.send(&[Message::user(query)], None).await
+}
 
-    Err("Failed to get VRAM from nvidia-smi".to_string())
-}
-
-async fn get_vram_from_dxdiag() -> Result<f32, String> {
-    let mut command = std::process::Command::new("powershell");
-    command.arg("-Command").arg(r#"
-        $xmlPath = Join-Path $env:TEMP "dxdiag_temp.xml"
-        try {
-            Start-Process dxdiag -ArgumentList "/x","$xmlPath" -Wait -NoNewWindow
-            Start-Sleep -Seconds 2
-            if (Test-Path $xmlPath) {
-                $content = Get-Content $xmlPath -Raw
-                $maxVramMB = 0
-                
-                # 複数のDedicatedMemoryエントリを検索
-                $matches = [regex]::Matches($content, '<DedicatedMemory>(\d+)\s*MB</DedicatedMemory>')
-                foreach ($match in $matches) {
-                    $vramMB = [int]$match.Groups[1].Value
-                    if ($vramMB -gt $maxVramMB) {
-                        $maxVramMB = $vramMB
-                    }
-                }
-                
-                if ($maxVramMB -gt 0) {
-                    [math]::Round($maxVramMB / 1024, 2)
-                } else {
-                    "0"
-                }
-            } else {
-                "0"
-            }
-        } catch {
-            "0"
-        } finally {
-            if (Test-Path $xmlPath) {
-                Remove-Item $xmlPath -ErrorAction SilentlyContinue
-            }
-        }
-    "#);
+/// 応答全体を囲むMarkdownのコードブロックを外す
+fn strip_code_fence(response: &str) -> &str {
+    let trimmed = response.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // ```jsonなどの言語指定の行を飛ばす
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+#[tauri::command]
+pub async fn is_ollama_installed() -> Result<bool, String> {
+    let mut command = std::process::Command::new("ollama");
+    command.arg("--version");
 
     #[cfg(target_os = "windows")]
     {
@@ -219,25 +521,10 @@ async fn get_vram_from_dxdiag() -> Result<f32, String> {
     }
 
     match command.output() {
-        Ok(output) => {
-            if output.status.success() {
-                let vram_str = String::from_utf8_lossy(&output.stdout);
-                if let Ok(vram) = vram_str.trim().parse::<f32>() {
-                    if vram > 0.0 {
-                        Ok(vram)
-                    } else {
-                        Err("No VRAM detected".into())
-                    }
-                } else {
-                    Err("Failed to parse VRAM value from dxdiag".into())
-                }
-            } else {
-                Err(format!(
-                    "dxdiag error: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ))
-            }
+        Ok(output) => Ok(output.status.success()),
+        Err(_) => {
+            // コマンドが見つからない場合はfalseを返す
+            Ok(false)
         }
-        Err(e) => Err(format!("Failed to execute dxdiag: {}", e)),
     }
 }
@@ -4,10 +4,32 @@ use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_fs::FsExt;
 
 mod audio_labeling;
+mod chart_generation;
+mod chart_review;
+mod chart_session;
+mod chat_backend;
+mod credentials;
 mod export_meta;
+mod ffmpeg;
+mod google_ai;
+mod hardware;
+mod harmony;
+mod hpss;
+mod json_schema;
+mod json_stream;
 mod language_model;
+mod llm_cache;
+mod mock_llm;
+mod ollama;
+mod openai_compatible;
+mod pitch_tracking;
 mod python_env;
+mod runtime_bundle;
+mod runtime_doctor;
+mod runtime_manifest;
+mod runtime_settings;
 mod stem;
+mod stem_activity;
 
 #[tauri::command]
 async fn set_title(window: tauri::Window, title: &str) -> Result<(), tauri::Error> {
@@ -79,6 +101,10 @@ pub fn run() {
             saved: false,
             preserved_open_action: OpenAction::None,
         }))
+        .manage(souon_core::stem::StemJobQueue::default())
+        .manage(chart_generation::ChartGeneration::default())
+        .manage(chart_session::ChartSessions::default())
+        .manage(ollama::OllamaPulls::default())
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
                 let state = window.try_state::<Mutex<AppState>>().unwrap();
@@ -120,12 +146,48 @@ pub fn run() {
             python_env::check_python,
             python_env::check_demucs,
             python_env::check_ffmpeg,
+            ffmpeg::ffmpeg_info,
+            runtime_bundle::install_runtime_bundle,
+            runtime_bundle::export_runtime_bundle,
+            runtime_doctor::diagnose_environment,
+            runtime_doctor::repair_environment,
+            runtime_doctor::uninstall_environment,
+            runtime_settings::get_runtime_settings,
+            runtime_settings::set_runtime_settings,
+            stem::demucs_available,
             stem::demucs,
+            stem::cancel_stem_job,
+            stem::set_stem_job_concurrency,
+            hpss::hpss,
             audio_labeling::onset,
+            audio_labeling::drum_hits,
+            pitch_tracking::track_pitch,
+            stem_activity::stem_activity,
+            harmony::detect_harmony,
+            credentials::set_api_key,
+            credentials::delete_api_key,
+            credentials::has_api_key,
+            language_model::chat,
+            language_model::chat_json,
+            language_model::chat_stream,
+            chart_generation::generate_chart,
+            chart_generation::cancel_chart_generation,
+            chart_review::review_chart,
+            chart_session::start_chart_session,
+            chart_session::refine_chart,
+            chart_session::end_chart_session,
             language_model::call_llm,
             language_model::call_google_ai,
             language_model::is_ollama_installed,
-            language_model::get_vram,
+            llm_cache::clear_llm_cache,
+            llm_cache::llm_cache_size,
+            ollama::pull_ollama_model,
+            ollama::cancel_ollama_pull,
+            ollama::list_ollama_models,
+            ollama::delete_ollama_model,
+            hardware::get_vram,
+            hardware::probe_hardware,
+            hardware::recommend_models,
             get_preserved_opened_file,
         ])
         .setup(|app| {
@@ -0,0 +1,203 @@
+use crate::chat_backend::{ChatResponse, GenerationParams};
+use crate::language_model::{ChatProvider, Message};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+// LLMのリクエストと応答のキャッシュ
+//
+// AppLocalData/llm_cache/<キー>.jsonに1リクエストずつ保存する。キーはプロバイダー、モデル、
+// 温度やシードなどの生成パラメーター、メッセージとスキーマから求めたSHA-256で、
+// 同じ入力なら同じ応答を再生できる。
+
+/// キャッシュの使い方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CacheMode {
+    /// 使わない（保存し続けると際限なく増えるので、明示的に指定した場合だけ使う）
+    #[default]
+    Off,
+    /// 常にLLMを呼び出し、応答を保存する
+    Record,
+    /// キャッシュにあればそれを使い、なければLLMを呼び出して保存する
+    Cached,
+    /// キャッシュからのみ応答する（なければエラー）
+    Replay,
+}
+
+/// 保存する1件分（デバッグしやすいよう入力も残す）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    request: Value,
+    response: String,
+    #[serde(default)]
+    prompt_tokens: Option<u32>,
+    #[serde(default)]
+    completion_tokens: Option<u32>,
+    /// UNIX時間（秒）
+    created_at: u64,
+}
+
+pub struct LlmCache {
+    dir: Option<PathBuf>,
+    mode: CacheMode,
+}
+
+impl LlmCache {
+    pub fn new(app_handle: &tauri::AppHandle, mode: CacheMode) -> Result<Self, String> {
+        if mode == CacheMode::Off {
+            return Ok(Self::disabled());
+        }
+        Ok(Self {
+            dir: Some(cache_dir(app_handle)?),
+            mode,
+        })
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            mode: CacheMode::Off,
+        }
+    }
+
+    /// キャッシュのキーになるリクエスト内容
+    pub fn request(
+        provider: &ChatProvider,
+        params: &GenerationParams,
+        messages: &[Message],
+        schema: Option<&Value>,
+    ) -> Value {
+        json!({
+            "provider": provider.id(),
+            "baseUrl": provider.base_url(),
+            "model": provider.model(),
+            "params": params,
+            "messages": messages,
+            "schema": schema,
+        })
+    }
+
+    /// キャッシュにある応答（Replayでなければ、なければNone）
+    pub fn get(&self, request: &Value) -> Result<Option<ChatResponse>, String> {
+        let Some(path) = self.path(request) else {
+            return Ok(None);
+        };
+        if matches!(self.mode, CacheMode::Cached | CacheMode::Replay) {
+            match std::fs::read(&path) {
+                Ok(content) => {
+                    let entry: CacheEntry = serde_json::from_slice(&content)
+                        .map_err(|e| format!("Failed to parse cached response: {}", e))?;
+                    log::info!("Using cached LLM response {}", path.display());
+                    return Ok(Some(ChatResponse {
+                        text: entry.response,
+                        prompt_tokens: entry.prompt_tokens,
+                        completion_tokens: entry.completion_tokens,
+                    }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to read cached response: {}", e)),
+            }
+        }
+        if self.mode == CacheMode::Replay {
+            return Err(format!(
+                "No cached response for this request (replay mode): {}",
+                path.display()
+            ));
+        }
+        Ok(None)
+    }
+
+    pub fn put(&self, request: Value, response: &ChatResponse) -> Result<(), String> {
+        let Some(path) = self.path(&request) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+        }
+        let entry = CacheEntry {
+            request,
+            response: response.text.clone(),
+            prompt_tokens: response.prompt_tokens,
+            completion_tokens: response.completion_tokens,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        let content = serde_json::to_vec_pretty(&entry)
+            .map_err(|e| format!("Failed to serialize cached response: {}", e))?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write cached response: {}", e))
+    }
+
+    fn path(&self, request: &Value) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        // serde_jsonのMapはキー順に並ぶので、同じ内容なら同じバイト列になる
+        let hash = Sha256::digest(request.to_string().as_bytes());
+        let key: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(dir.join(format!("{}.json", key)))
+    }
+}
+
+fn cache_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|e| format!("Failed to resolve AppLocalData directory: {}", e))?
+        .join("llm_cache"))
+}
+
+/// 保存されている応答のファイル（ディレクトリがなければ空）
+fn cache_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read cache directory: {}", e)),
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect())
+}
+
+/// llm_cache_sizeの戻り値
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmCacheSize {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// 保存されている応答の件数と合計サイズ
+#[tauri::command]
+pub async fn llm_cache_size(app_handle: tauri::AppHandle) -> Result<LlmCacheSize, String> {
+    let files = cache_files(&cache_dir(&app_handle)?)?;
+    let bytes = files
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    Ok(LlmCacheSize {
+        entries: files.len(),
+        bytes,
+    })
+}
+
+/// キャッシュをすべて削除し、削除した件数を返す
+#[tauri::command]
+pub async fn clear_llm_cache(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    let files = cache_files(&cache_dir(&app_handle)?)?;
+    for path in &files {
+        std::fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    let count = files.len();
+    log::info!("Cleared {} cached LLM responses", count);
+    Ok(count)
+}
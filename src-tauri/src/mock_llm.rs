@@ -0,0 +1,214 @@
+use crate::chat_backend::{BoxFuture, ChatBackend, ChatRequest, ChatResponse};
+use crate::language_model::{ChatProvider, Role};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// LLMのモック（CIやネットワークのない環境で譜面生成などを動かすためのもの）
+//
+// プロバイダーにtype: "mock"を指定するか、環境変数LLM_MOCKを設定すると、どのプロバイダーを
+// 指定してもモックが応答する。LLM_MOCKにはスクリプト（JSON）のパスを指定する
+// （空文字列か"builtin"ならスクリプトを使わない）。
+//
+// スクリプトの例:
+// {
+//   "rules": [
+//     { "contains": "bars 1-4", "schema": true, "reply": { "1": [{ "key": 1, "beat": 1 }] } },
+//     { "contains": "hello", "reply": "Hello!" }
+//   ],
+//   "default": "OK"
+// }
+//
+// 最後のユーザーメッセージに対して上から順にルールを調べ、最初に一致したものを返す。
+// 一致しない場合、スキーマがあればそれを満たす最小限のJSON、なければdefaultを返す。
+
+const ENV_VAR: &str = "LLM_MOCK";
+
+/// 1トークンとして送る文字数（ストリーミングの処理を通すため分割して送る）
+const CHUNK_CHARS: usize = 16;
+
+const DEFAULT_REPLY: &str = "This is a mock reply.";
+
+/// LLM_MOCKが設定されていれば、代わりに使うプロバイダー
+pub fn provider_override() -> Option<ChatProvider> {
+    let value = std::env::var(ENV_VAR).ok()?;
+    let script = match value.trim() {
+        "" | "builtin" => None,
+        path => Some(path.to_string()),
+    };
+    Some(ChatProvider::Mock { script })
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Script {
+    #[serde(default)]
+    rules: Vec<Rule>,
+    #[serde(default)]
+    default: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    /// 最後のユーザーメッセージに含まれる文字列（省略するとすべてに一致）
+    #[serde(default)]
+    contains: Option<String>,
+    /// trueならスキーマ付き、falseならスキーマなしのリクエストにだけ一致する
+    #[serde(default)]
+    schema: Option<bool>,
+    /// 文字列以外はJSONとして返す
+    reply: Value,
+}
+
+impl Rule {
+    fn matches(&self, message: &str, has_schema: bool) -> bool {
+        self.contains
+            .as_ref()
+            .is_none_or(|needle| message.contains(needle.as_str()))
+            && self.schema.is_none_or(|schema| schema == has_schema)
+    }
+}
+
+pub struct MockBackend {
+    script: Option<String>,
+}
+
+impl MockBackend {
+    pub fn new(script: Option<String>) -> Self {
+        Self { script }
+    }
+
+    fn load_script(&self) -> Result<Script, String> {
+        let Some(path) = &self.script else {
+            return Ok(Script::default());
+        };
+        let content = std::fs::read(path)
+            .map_err(|e| format!("Failed to read mock script {}: {}", path, e))?;
+        serde_json::from_slice(&content)
+            .map_err(|e| format!("Failed to parse mock script {}: {}", path, e))
+    }
+
+    fn reply(&self, request: &ChatRequest) -> Result<String, String> {
+        let script = self.load_script()?;
+        let message = request
+            .messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::User)
+            .map_or("", |m| m.content.as_str());
+
+        if let Some(rule) = script
+            .rules
+            .iter()
+            .find(|rule| rule.matches(message, request.schema.is_some()))
+        {
+            return Ok(match &rule.reply {
+                Value::String(text) => text.clone(),
+                value => value.to_string(),
+            });
+        }
+        if let Some(schema) = request.schema {
+            return Ok(example_value(schema).to_string());
+        }
+        Ok(script.default.unwrap_or_else(|| DEFAULT_REPLY.to_string()))
+    }
+}
+
+impl ChatBackend for MockBackend {
+    fn chat_stream<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<ChatResponse, String>> {
+        Box::pin(async move {
+            let reply = self.reply(&request)?;
+            let chars: Vec<char> = reply.chars().collect();
+            let chunks: Vec<String> = chars
+                .chunks(CHUNK_CHARS)
+                .map(|chunk| chunk.iter().collect())
+                .collect();
+            for chunk in &chunks {
+                on_token(chunk);
+            }
+            // トークン数の代わりにチャンク数を報告する
+            let prompt_chars: usize = request
+                .messages
+                .iter()
+                .map(|m| m.content.chars().count())
+                .sum();
+            Ok(ChatResponse {
+                text: reply,
+                prompt_tokens: Some(prompt_chars.div_ceil(CHUNK_CHARS) as u32),
+                completion_tokens: Some(chunks.len() as u32),
+            })
+        })
+    }
+}
+
+/// スキーマを満たす最小限の値（配列は要素を1つ以上入れる）
+fn example_value(schema: &Value) -> Value {
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(value) = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|values| values.first())
+    {
+        return value.clone();
+    }
+
+    let schema_type = match schema.get("type") {
+        Some(Value::String(schema_type)) => Some(schema_type.as_str()),
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|&schema_type| schema_type != "null"),
+        _ => None,
+    };
+    let schema_type =
+        schema_type.or_else(|| schema.get("properties").is_some().then_some("object"));
+
+    let number = |key: &str| schema.get(key).and_then(Value::as_f64);
+    match schema_type {
+        Some("object") => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let object: Map<String, Value> = schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|key| {
+                    let property = properties.and_then(|p| p.get(key));
+                    let value = property.map_or(Value::Null, example_value);
+                    (key.to_string(), value)
+                })
+                .collect();
+            Value::Object(object)
+        }
+        Some("array") => {
+            let min_items = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+            let max_items = schema.get("maxItems").and_then(Value::as_u64);
+            let count = min_items.max(1).min(max_items.unwrap_or(u64::MAX));
+            let item = schema.get("items").map_or(Value::Null, example_value);
+            Value::Array(vec![item; count as usize])
+        }
+        Some("integer") => {
+            let minimum = number("minimum")
+                .map(f64::ceil)
+                .or_else(|| number("exclusiveMinimum").map(|min| min.floor() + 1.0))
+                .unwrap_or(0.0);
+            let value = number("maximum").map_or(minimum, |max| minimum.min(max.floor()));
+            Value::from(value as i64)
+        }
+        Some("number") => {
+            let value = number("minimum").unwrap_or(0.0);
+            serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+        }
+        Some("string") => {
+            let min_length = schema.get("minLength").and_then(Value::as_u64).unwrap_or(0);
+            Value::String("a".repeat(min_length as usize))
+        }
+        Some("boolean") => Value::Bool(false),
+        _ => Value::Null,
+    }
+}
@@ -0,0 +1,624 @@
+use crate::chat_backend::{ChatResponse, GenerationParams};
+use crate::language_model::{self, Message};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use souon_core::stem::wait_cancelled;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::Emitter;
+use tokio::sync::watch;
+
+// OllamaのHTTP API（チャット、モデルの取得・一覧・削除）
+//
+// URLはすべてbase_urlで切り替えられるため、ローカルのモックサーバーに向けて動かせる。
+
+/// OllamaのURL（未指定ならOLLAMA_URLまたは127.0.0.1:11434）
+pub fn base_url(base_url: Option<&str>) -> String {
+    base_url
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| std::env::var("OLLAMA_URL").unwrap_or("http://127.0.0.1:11434".into()))
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    stream: bool,
+    /// JSON Schemaを渡すとそれに従った出力になる
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a Value>,
+    options: ModelOptions<'a>,
+}
+
+#[derive(Serialize)]
+struct ModelOptions<'a> {
+    temperature: f32,
+    num_predict: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+pub const TEMPERATURE: f32 = 0.7;
+
+/// 譜面生成に十分な長さに設定
+const MAX_TOKENS: u32 = 4000;
+
+/// /api/chatのストリーミング応答の1行
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<ChatChunkMessage>,
+    #[serde(default)]
+    error: Option<String>,
+    /// 最後の行にだけ含まれる
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ChatChunkMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// モデルがない場合のエラーの先頭（フロントエンドはこれを見てpull_ollama_modelを実行する）
+pub const MODEL_NOT_FOUND: &str = "Ollama model not found";
+
+/// /api/chatをストリーミングで呼び出す
+///
+/// モデルがない場合は取得せずにMODEL_NOT_FOUNDで始まるエラーを返す。
+pub async fn chat_stream(
+    base_url: Option<&str>,
+    model: &str,
+    params: &GenerationParams,
+    messages: &[Message],
+    schema: Option<&Value>,
+    on_token: &mut (dyn FnMut(&str) + Send),
+) -> Result<ChatResponse, String> {
+    let url = format!("{}/api/chat", self::base_url(base_url));
+    let body = serde_json::to_vec(&ChatRequest {
+        model,
+        messages,
+        stream: true,
+        format: schema,
+        options: ModelOptions {
+            temperature: params.temperature.unwrap_or(TEMPERATURE),
+            num_predict: params.max_tokens.unwrap_or(MAX_TOKENS),
+            top_p: params.top_p,
+            top_k: params.top_k,
+            stop: &params.stop,
+            seed: params.seed,
+        },
+    })
+    .map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if is_model_not_found(&body) {
+            return Err(format!("{}: {}", MODEL_NOT_FOUND, model));
+        }
+        return Err(format!("Ollama request failed: HTTP {}: {}", status, body));
+    }
+    let response = language_model::error_for_status(response, "Ollama").await?;
+
+    let mut result = ChatResponse::default();
+    language_model::read_lines(response, |line| {
+        if line.is_empty() {
+            return Ok(true);
+        }
+        let chunk: ChatChunk = serde_json::from_str(line)
+            .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
+        if let Some(error) = chunk.error {
+            return Err(format!("Ollama request failed: {}", error));
+        }
+        if let Some(message) = chunk.message.filter(|m| !m.content.is_empty()) {
+            on_token(&message.content);
+            result.text.push_str(&message.content);
+        }
+        result.prompt_tokens = chunk.prompt_eval_count.or(result.prompt_tokens);
+        result.completion_tokens = chunk.eval_count.or(result.completion_tokens);
+        Ok(true)
+    })
+    .await?;
+
+    Ok(result)
+}
+
+/// 404の本文がモデルがないことを示すか（{"error":"model \"...\" not found, try pulling it first"}）
+fn is_model_not_found(body: &str) -> bool {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        error: String,
+    }
+    serde_json::from_str::<ErrorBody>(body)
+        .is_ok_and(|body| body.error.starts_with("model ") && body.error.contains("not found"))
+}
+
+/// /api/pullの進行状況（1行分）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    /// ダウンロード中のレイヤー
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// バイト数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PullChunk {
+    #[serde(flatten)]
+    progress: PullProgress,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// /api/pullでモデルを取得する（進行状況は1行ごとにon_progressに渡す）
+pub async fn pull_model(
+    base_url: Option<&str>,
+    model: &str,
+    on_progress: &mut (dyn FnMut(&PullProgress) + Send),
+) -> Result<(), String> {
+    let url = format!("{}/api/pull", self::base_url(base_url));
+    let body = serde_json::to_vec(&json!({ "model": model, "stream": true }))
+        .map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Ollama pull failed: {}", e))?;
+    let response = language_model::error_for_status(response, "Ollama pull").await?;
+
+    let mut succeeded = false;
+    language_model::read_lines(response, |line| {
+        if line.is_empty() {
+            return Ok(true);
+        }
+        let chunk: PullChunk = serde_json::from_str(line)
+            .map_err(|e| format!("Failed to parse Ollama pull response: {}", e))?;
+        if let Some(error) = chunk.error {
+            return Err(format!("Ollama pull failed: {}", error));
+        }
+        on_progress(&chunk.progress);
+        succeeded = chunk.progress.status == "success";
+        Ok(!succeeded)
+    })
+    .await?;
+
+    if !succeeded {
+        return Err(format!(
+            "Ollama pull of {} ended before it completed",
+            model
+        ));
+    }
+    log::info!("Pulled Ollama model {}", model);
+    Ok(())
+}
+
+/// 実行中のモデル取得（モデル名ごとに中止できるようにする）
+#[derive(Default)]
+pub struct OllamaPulls {
+    pulls: Mutex<HashMap<String, watch::Sender<bool>>>,
+}
+
+impl OllamaPulls {
+    fn register(&self, model: &str) -> Result<(OllamaPull<'_>, watch::Receiver<bool>), String> {
+        let mut pulls = self.pulls.lock().unwrap();
+        if pulls.contains_key(model) {
+            return Err(format!("{} is already being pulled", model));
+        }
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        pulls.insert(model.to_string(), cancel_tx);
+        Ok((
+            OllamaPull {
+                pulls: self,
+                model: model.to_string(),
+            },
+            cancel_rx,
+        ))
+    }
+
+    /// 取得を中止する（取得中でなければfalse）
+    pub fn cancel(&self, model: &str) -> bool {
+        match self.pulls.lock().unwrap().get(model) {
+            Some(cancel_tx) => {
+                let _ = cancel_tx.send(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 取得が終わったら（中止・エラーを含む）登録を解除する
+struct OllamaPull<'a> {
+    pulls: &'a OllamaPulls,
+    model: String,
+}
+
+impl Drop for OllamaPull<'_> {
+    fn drop(&mut self) {
+        self.pulls.pulls.lock().unwrap().remove(&self.model);
+    }
+}
+
+/// ollama_pull_progressイベントの内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OllamaPullProgress<'a> {
+    model: &'a str,
+    #[serde(flatten)]
+    progress: &'a PullProgress,
+}
+
+/// モデルを取得する（進行状況はollama_pull_progressイベントで通知する）
+#[tauri::command]
+pub async fn pull_ollama_model(
+    app_handle: tauri::AppHandle,
+    pulls: tauri::State<'_, OllamaPulls>,
+    base_url: Option<String>,
+    model: String,
+) -> Result<(), String> {
+    let (_pull, mut cancel_rx) = pulls.register(&model)?;
+
+    let mut on_progress = |progress: &PullProgress| {
+        let payload = OllamaPullProgress {
+            model: &model,
+            progress,
+        };
+        if let Err(e) = app_handle.emit("ollama_pull_progress", payload) {
+            log::warn!("Failed to emit pull progress: {}", e);
+        }
+    };
+
+    // 中止した場合はリクエストを破棄して接続を閉じる（Ollama側の取得も止まる）
+    tokio::select! {
+        result = pull_model(base_url.as_deref(), &model, &mut on_progress) => result,
+        _ = wait_cancelled(&mut cancel_rx) => {
+            log::info!("Ollama pull of {} cancelled", model);
+            Err(format!("Ollama pull of {} was cancelled", model))
+        }
+    }
+}
+
+#[tauri::command]
+pub fn cancel_ollama_pull(pulls: tauri::State<'_, OllamaPulls>, model: String) -> bool {
+    pulls.cancel(&model)
+}
+
+/// ローカルにあるモデル
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaModel {
+    pub name: String,
+    /// バイト数
+    pub size: u64,
+    pub modified_at: String,
+    pub family: String,
+    /// 例: "14.7B"
+    pub parameter_size: String,
+    /// 例: "Q4_K_M"
+    pub quantization_level: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<TagsModel>,
+}
+
+#[derive(Deserialize)]
+struct TagsModel {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    modified_at: String,
+    #[serde(default)]
+    details: TagsModelDetails,
+}
+
+#[derive(Default, Deserialize)]
+struct TagsModelDetails {
+    #[serde(default)]
+    family: String,
+    #[serde(default)]
+    parameter_size: String,
+    #[serde(default)]
+    quantization_level: String,
+}
+
+/// /api/tagsでローカルのモデルを一覧する
+#[tauri::command]
+pub async fn list_ollama_models(base_url: Option<String>) -> Result<Vec<OllamaModel>, String> {
+    let url = format!("{}/api/tags", self::base_url(base_url.as_deref()));
+    let response = reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    let response = language_model::error_for_status(response, "Ollama").await?;
+    let content = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    let tags: TagsResponse = serde_json::from_slice(&content)
+        .map_err(|e| format!("Failed to parse Ollama model list: {}", e))?;
+
+    Ok(tags
+        .models
+        .into_iter()
+        .map(|model| OllamaModel {
+            name: model.name,
+            size: model.size,
+            modified_at: model.modified_at,
+            family: model.details.family,
+            parameter_size: model.details.parameter_size,
+            quantization_level: model.details.quantization_level,
+        })
+        .collect())
+}
+
+/// /api/deleteでモデルを削除する
+#[tauri::command]
+pub async fn delete_ollama_model(base_url: Option<String>, model: String) -> Result<(), String> {
+    let url = format!("{}/api/delete", self::base_url(base_url.as_deref()));
+    let body = serde_json::to_vec(&json!({ "model": model }))
+        .map_err(|e| format!("Failed to serialize request: {}", e))?;
+    let response = reqwest::Client::new()
+        .delete(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    language_model::error_for_status(response, "Ollama delete").await?;
+
+    log::info!("Deleted Ollama model {}", model);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// サーバーが受け取ったリクエスト
+    struct Request {
+        method: String,
+        path: String,
+        body: Value,
+    }
+
+    /// 1回だけ応答するモックのOllamaを立て、base_urlと受け取ったリクエストを返す
+    fn serve_once(status: &'static str, body: &'static str) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (request_tx, request_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut request_body = vec![0u8; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            let mut parts = request_line.split_whitespace();
+            request_tx
+                .send(Request {
+                    method: parts.next().unwrap().to_string(),
+                    path: parts.next().unwrap().to_string(),
+                    body: serde_json::from_slice(&request_body).unwrap_or(Value::Null),
+                })
+                .unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/x-ndjson\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        (base_url, request_rx)
+    }
+
+    async fn pull(base_url: &str) -> (Result<(), String>, Vec<String>) {
+        let mut statuses = Vec::new();
+        let result = pull_model(Some(base_url), "phi4:14b", &mut |progress| {
+            statuses.push(progress.status.clone())
+        })
+        .await;
+        (result, statuses)
+    }
+
+    #[tokio::test]
+    async fn pull_reports_progress_until_success() {
+        let (base_url, requests) = serve_once(
+            "200 OK",
+            concat!(
+                "{\"status\":\"pulling manifest\"}\n",
+                "{\"status\":\"pulling abc\",\"digest\":\"sha256:abc\",\"total\":100,\"completed\":50}\n",
+                "{\"status\":\"success\"}\n",
+            ),
+        );
+
+        let (result, statuses) = pull(&base_url).await;
+        result.unwrap();
+        assert_eq!(statuses, ["pulling manifest", "pulling abc", "success"]);
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/pull");
+        assert_eq!(request.body, json!({ "model": "phi4:14b", "stream": true }));
+    }
+
+    #[tokio::test]
+    async fn pull_fails_on_error_line() {
+        let (base_url, _requests) = serve_once(
+            "200 OK",
+            concat!(
+                "{\"status\":\"pulling manifest\"}\n",
+                "{\"error\":\"pull model manifest: file does not exist\"}\n",
+            ),
+        );
+
+        let (result, statuses) = pull(&base_url).await;
+        assert_eq!(
+            result.unwrap_err(),
+            "Ollama pull failed: pull model manifest: file does not exist"
+        );
+        assert_eq!(statuses, ["pulling manifest"]);
+    }
+
+    #[tokio::test]
+    async fn pull_fails_when_stream_ends_without_success() {
+        let (base_url, _requests) = serve_once(
+            "200 OK",
+            "{\"status\":\"pulling manifest\"}\n{\"status\":\"verifying sha256 digest\"}\n",
+        );
+
+        let (result, _) = pull(&base_url).await;
+        assert_eq!(
+            result.unwrap_err(),
+            "Ollama pull of phi4:14b ended before it completed"
+        );
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_tags() {
+        let (base_url, requests) = serve_once(
+            "200 OK",
+            r#"{"models":[{"name":"phi4:14b","size":9053116391,"modified_at":"2025-01-10T00:00:00Z","details":{"family":"phi3","parameter_size":"14.7B","quantization_level":"Q4_K_M"}},{"name":"llama3.2:1b"}]}"#,
+        );
+
+        let models = list_ollama_models(Some(base_url)).await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].name, "phi4:14b");
+        assert_eq!(models[0].size, 9053116391);
+        assert_eq!(models[0].parameter_size, "14.7B");
+        assert_eq!(models[0].quantization_level, "Q4_K_M");
+        assert_eq!(models[1].name, "llama3.2:1b");
+        assert_eq!(models[1].family, "");
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/api/tags");
+    }
+
+    #[tokio::test]
+    async fn delete_model_sends_model_name() {
+        let (base_url, requests) = serve_once("200 OK", "");
+
+        delete_ollama_model(Some(base_url), "phi4:14b".to_string())
+            .await
+            .unwrap();
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(request.path, "/api/delete");
+        assert_eq!(request.body, json!({ "model": "phi4:14b" }));
+    }
+
+    #[tokio::test]
+    async fn delete_missing_model_fails() {
+        let (base_url, _requests) =
+            serve_once("404 Not Found", r#"{"error":"model 'phi4:14b' not found"}"#);
+
+        let error = delete_ollama_model(Some(base_url), "phi4:14b".to_string())
+            .await
+            .unwrap_err();
+        assert!(error.contains("HTTP 404"), "{}", error);
+    }
+
+    async fn chat(base_url: &str) -> Result<ChatResponse, String> {
+        chat_stream(
+            Some(base_url),
+            "phi4:14b",
+            &GenerationParams::default(),
+            &[Message::user("hello")],
+            None,
+            &mut |_| {},
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn chat_reports_missing_model_without_pulling() {
+        let (base_url, requests) = serve_once(
+            "404 Not Found",
+            r#"{"error":"model \"phi4:14b\" not found, try pulling it first"}"#,
+        );
+
+        let error = chat(&base_url).await.unwrap_err();
+        assert_eq!(error, format!("{}: phi4:14b", MODEL_NOT_FOUND));
+        assert_eq!(requests.recv().unwrap().path, "/api/chat");
+        // 取得のリクエストは送らない
+        assert!(requests.recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn chat_reports_other_404_as_http_error() {
+        let (base_url, _requests) = serve_once("404 Not Found", "404 page not found");
+
+        let error = chat(&base_url).await.unwrap_err();
+        assert!(!error.starts_with(MODEL_NOT_FOUND), "{}", error);
+        assert!(error.contains("HTTP 404"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn chat_collects_streamed_tokens() {
+        let (base_url, _requests) = serve_once(
+            "200 OK",
+            concat!(
+                "{\"message\":{\"content\":\"{\\\"1\\\"\"}}\n",
+                "{\"message\":{\"content\":\":[]}\"},\"prompt_eval_count\":12,\"eval_count\":5}\n",
+            ),
+        );
+
+        let response = chat(&base_url).await.unwrap();
+        assert_eq!(response.text, "{\"1\":[]}");
+        assert_eq!(response.prompt_tokens, Some(12));
+        assert_eq!(response.completion_tokens, Some(5));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::chat_backend::{ChatResponse, GenerationParams};
+use crate::language_model::{self, Message};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// OpenAI互換のchat completions API（llama.cpp server、vLLM、LM Studioなど）
+
+pub const TEMPERATURE: f32 = 0.7;
+
+/// 譜面生成に十分な長さに設定
+const MAX_TOKENS: u32 = 4000;
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// OpenAIの仕様にはないが、llama.cpp serverやvLLMは対応している
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    /// 対応しているサーバーでのみ有効
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    stream: bool,
+    /// 最後のチャンクでトークン数を受け取る
+    stream_options: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+}
+
+/// stream: trueのときに届く差分
+#[derive(Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChatCompletionChunkChoice>,
+    #[serde(default)]
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize)]
+struct CompletionUsage {
+    #[serde(default)]
+    prompt_tokens: Option<u32>,
+    #[serde(default)]
+    completion_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionChunkChoice {
+    delta: ChatCompletionMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// ストリーミング（Server-Sent Events）で呼び出し、受信したテキストをon_tokenに渡す
+///
+/// base_urlは"/v1"まで含めたもの（例: "http://127.0.0.1:8080/v1"）
+pub async fn chat_stream(
+    base_url: &str,
+    model: &str,
+    api_key: Option<&str>,
+    params: &GenerationParams,
+    messages: &[Message],
+    schema: Option<&Value>,
+    on_token: &mut (dyn FnMut(&str) + Send),
+) -> Result<ChatResponse, String> {
+    let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
+    let body = serde_json::to_vec(&ChatCompletionRequest {
+        model,
+        messages,
+        max_tokens: params.max_tokens.unwrap_or(MAX_TOKENS),
+        temperature: params.temperature.unwrap_or(TEMPERATURE),
+        top_p: params.top_p,
+        top_k: params.top_k,
+        stop: &params.stop,
+        seed: params.seed,
+        stream: true,
+        stream_options: json!({ "include_usage": true }),
+        response_format: schema.map(|schema| {
+            json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": schema, "strict": true }
+            })
+        }),
+    })
+    .map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+    let mut request = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    if let Some(api_key) = api_key.filter(|key| !key.is_empty()) {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("OpenAI-compatible request failed: {}", e))?;
+    let response = language_model::error_for_status(response, "OpenAI-compatible").await?;
+
+    let mut result = ChatResponse::default();
+    language_model::read_lines(response, |line| {
+        let Some(data) = language_model::sse_data(line) else {
+            return Ok(true);
+        };
+        if data == "[DONE]" {
+            return Ok(false);
+        }
+        let chunk: ChatCompletionChunk =
+            serde_json::from_str(data).map_err(|e| format!("Failed to parse response: {}", e))?;
+        if let Some(usage) = chunk.usage {
+            result.prompt_tokens = usage.prompt_tokens;
+            result.completion_tokens = usage.completion_tokens;
+        }
+        if let Some(content) = chunk
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.delta.content)
+            .filter(|content| !content.is_empty())
+        {
+            on_token(&content);
+            result.text.push_str(&content);
+        }
+        Ok(true)
+    })
+    .await?;
+
+    Ok(result)
+}
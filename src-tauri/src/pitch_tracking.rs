@@ -0,0 +1,14 @@
+use souon_core::pitch_tracking::{self, PitchInstrument, PitchSegment};
+
+#[tauri::command]
+pub async fn track_pitch(
+    input_base64_audio: String,
+    instrument: PitchInstrument,
+) -> Result<Vec<PitchSegment>, String> {
+    // 重い処理を別スレッドで実行
+    tokio::task::spawn_blocking(move || {
+        pitch_tracking::track_pitch(&input_base64_audio, instrument)
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
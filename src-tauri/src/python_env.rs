@@ -1,13 +1,15 @@
+use crate::runtime_manifest::{self, REQUIREMENTS_LOCK};
+use crate::runtime_settings::RuntimeSettings;
 use std::path::PathBuf;
 use tauri::Manager;
 
 // Python環境をダウンロードして展開する関数
-pub async fn download_and_extract_python(local_python_dir: &std::path::Path) -> Result<(), String> {
+pub async fn download_and_extract_python(
+    local_python_dir: &std::path::Path,
+    settings: &RuntimeSettings,
+) -> Result<(), String> {
     log::info!("Downloading Python environment from URL...");
 
-    const PYTHON_URL: &str =
-        "https://www.python.org/ftp/python/3.13.6/python-3.13.6-embed-amd64.zip";
-
     // 一時ファイルのパスを生成
     let temp_zip_path = local_python_dir
         .parent()
@@ -21,23 +23,10 @@ pub async fn download_and_extract_python(local_python_dir: &std::path::Path) ->
             .map_err(|e| format!("Failed to create AppLocalData directory: {}", e))?;
     }
 
-    // Pythonのzipファイルをダウンロード
-    log::info!("Downloading Python from: {}", PYTHON_URL);
-    let response = reqwest::get(PYTHON_URL)
-        .await
-        .map_err(|e| format!("Failed to download Python: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!(
-            "Failed to download Python: HTTP {}",
-            response.status()
-        ));
-    }
-
-    let content = response
-        .bytes()
-        .await
-        .map_err(|e| format!("Failed to read download content: {}", e))?;
+    // Pythonのzipファイルをダウンロード（展開前にハッシュを検証）
+    let client = settings.http_client()?;
+    let content =
+        runtime_manifest::download_artifact(&client, "Python", &settings.python()).await?;
 
     // 一時ファイルに保存
     log::info!("Saving downloaded file to: {}", temp_zip_path.display());
@@ -50,44 +39,9 @@ pub async fn download_and_extract_python(local_python_dir: &std::path::Path) ->
     let temp_zip_path_clone = temp_zip_path.clone();
     let local_python_dir_clone = local_python_dir.to_path_buf();
 
-    tokio::task::spawn_blocking(move || {
-        let file = std::fs::File::open(&temp_zip_path_clone)
-            .map_err(|e| format!("Failed to open zip file: {}", e))?;
-        let mut archive =
-            zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
-
-        std::fs::create_dir_all(&local_python_dir_clone)
-            .map_err(|e| format!("Failed to create Python directory: {}", e))?;
-
-        for i in 0..archive.len() {
-            let mut file = archive
-                .by_index(i)
-                .map_err(|e| format!("Failed to read file from archive: {}", e))?;
-            let outpath = match file.enclosed_name() {
-                Some(path) => local_python_dir_clone.join(path),
-                None => continue,
-            };
-
-            if file.name().ends_with('/') {
-                std::fs::create_dir_all(&outpath)
-                    .map_err(|e| format!("Failed to create directory: {}", e))?;
-            } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)
-                            .map_err(|e| format!("Failed to create parent directory: {}", e))?;
-                    }
-                }
-                let mut outfile = std::fs::File::create(&outpath)
-                    .map_err(|e| format!("Failed to create output file: {}", e))?;
-                std::io::copy(&mut file, &mut outfile)
-                    .map_err(|e| format!("Failed to extract file: {}", e))?;
-            }
-        }
-        Ok::<(), String>(())
-    })
-    .await
-    .map_err(|e| format!("Task join error: {}", e))??;
+    tokio::task::spawn_blocking(move || extract_zip(&temp_zip_path_clone, &local_python_dir_clone))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))??;
 
     // 一時ファイルを削除
     let _ = tokio::fs::remove_file(&temp_zip_path).await;
@@ -96,10 +50,56 @@ pub async fn download_and_extract_python(local_python_dir: &std::path::Path) ->
     Ok(())
 }
 
+/// python.exeのあるディレクトリ用のpython313._pth（site-packagesを有効化する）
+pub(crate) const PTH_CONTENT: &str = "python313.zip\n.\nimport site";
+
+/// zipファイルをディレクトリに展開する（ブロッキング）
+pub(crate) fn extract_zip(
+    zip_path: &std::path::Path,
+    dest_dir: &std::path::Path,
+) -> Result<(), String> {
+    let file =
+        std::fs::File::open(zip_path).map_err(|e| format!("Failed to open zip file: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    std::fs::create_dir_all(dest_dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dest_dir.display(), e))?;
+
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read file from archive: {}", e))?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => dest_dir.join(path),
+            None => continue,
+        };
+
+        if file.name().ends_with('/') {
+            std::fs::create_dir_all(&outpath)
+                .map_err(|e| format!("Failed to create directory: {}", e))?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    std::fs::create_dir_all(p)
+                        .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+                }
+            }
+            let mut outfile = std::fs::File::create(&outpath)
+                .map_err(|e| format!("Failed to create output file: {}", e))?;
+            std::io::copy(&mut file, &mut outfile)
+                .map_err(|e| format!("Failed to extract file: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String> {
     log::info!("Checking Python environment...");
 
+    let settings = RuntimeSettings::load(&app_handle)?;
+
     // AppLocalDataのPython環境パスを取得
     let local_python_path = app_handle
         .path()
@@ -119,7 +119,7 @@ pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String
             .expect("Failed to resolve local Python directory path");
 
         // Python環境をダウンロードして展開
-        download_and_extract_python(&local_python_dir)
+        download_and_extract_python(&local_python_dir, &settings)
             .await
             .map_err(|e| format!("Failed to download and extract Python environment: {}", e))?;
 
@@ -160,23 +160,11 @@ pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String
         // get-pip.pyが存在しない場合はダウンロード
         if !python_script.exists() {
             log::info!("get-pip.py not found, downloading...");
-            const GET_PIP_URL: &str = "https://bootstrap.pypa.io/get-pip.py";
-
-            let response = reqwest::get(GET_PIP_URL)
-                .await
-                .map_err(|e| format!("Failed to download get-pip.py: {}", e))?;
-
-            if !response.status().is_success() {
-                return Err(format!(
-                    "Failed to download get-pip.py: HTTP {}",
-                    response.status()
-                ));
-            }
 
-            let content = response
-                .text()
-                .await
-                .map_err(|e| format!("Failed to read get-pip.py content: {}", e))?;
+            let client = settings.http_client()?;
+            let content =
+                runtime_manifest::download_artifact(&client, "get-pip.py", &settings.get_pip())
+                    .await?;
 
             tokio::fs::write(&python_script, content)
                 .await
@@ -201,10 +189,8 @@ pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String
 
         if pth_file_path.exists() {
             // python313._pthの内容を標準的な形式に設定
-            let correct_pth_content = "python313.zip\n.\nimport site";
-
             log::info!("Setting python313._pth to standard format");
-            tokio::fs::write(&pth_file_path, correct_pth_content)
+            tokio::fs::write(&pth_file_path, PTH_CONTENT)
                 .await
                 .map_err(|e| format!("Failed to update python313._pth: {}", e))?;
             log::info!("Successfully updated python313._pth with standard content");
@@ -235,7 +221,7 @@ pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String
 
         // Pythonを実行してpipをインストール
         let mut command = tokio::process::Command::new(&local_python_path);
-        let command = command.arg(&python_script);
+        let command = command.arg(&python_script).args(settings.pip_args());
 
         #[cfg(target_os = "windows")]
         {
@@ -285,6 +271,8 @@ pub async fn check_python(app_handle: tauri::AppHandle) -> Result<String, String
 pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String> {
     log::info!("Checking Demucs environment...");
 
+    let settings = RuntimeSettings::load(&app_handle)?;
+
     // demucsのパスを取得（AppLocalDataのpython_env/Scripts/demucs.exe）
     let demucs_path = app_handle
         .path()
@@ -297,8 +285,28 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
     log::info!("Demucs executable path: {}", demucs_path.to_string_lossy());
     log::info!("Demucs executable exists: {}", demucs_path.exists());
 
-    // demucsが存在しない場合インストール
-    if !demucs_path.exists() {
+    // インストール済みのバージョン固定ファイル（同梱のものと違えば再インストール）
+    let installed_lock_path = app_handle
+        .path()
+        .resolve(
+            "python_env/requirements.lock",
+            tauri::path::BaseDirectory::AppLocalData,
+        )
+        .expect("Failed to resolve requirements.lock path");
+    let installed_lock = tokio::fs::read_to_string(&installed_lock_path)
+        .await
+        .unwrap_or_default();
+    let mut lock_outdated = installed_lock != REQUIREMENTS_LOCK;
+    // ハッシュが固定されていなければ入れ直せないため、入っているものをそのまま使う
+    if lock_outdated && demucs_path.exists() {
+        if let Err(e) = runtime_manifest::check_lock_hashes(REQUIREMENTS_LOCK) {
+            log::warn!("Keeping installed demucs: {}", e);
+            lock_outdated = false;
+        }
+    }
+
+    // demucsが存在しないか、固定バージョンが変わった場合インストール
+    if !demucs_path.exists() || lock_outdated {
         // Pythonの同梱先を取得（AppLocalData）
         let local_python_path = app_handle
             .path()
@@ -308,7 +316,11 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
             )
             .expect("Failed to resolve Python executable path");
 
-        log::info!("Demucs not found, installing...");
+        log::info!(
+            "Installing Demucs (installed: {}, lock outdated: {})",
+            demucs_path.exists(),
+            lock_outdated
+        );
         log::info!("Using Python at: {}", local_python_path.to_string_lossy());
 
         // Pythonの実行可能性をチェック
@@ -319,48 +331,16 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
             ));
         }
 
-        // pipの直接パスを取得
-        // pipを実行し依存関係をインストール（python.exe -m pip install --upgrade setuptools wheel）
-        let mut command = tokio::process::Command::new(&local_python_path);
-        command
-            .arg("-m")
-            .arg("pip")
-            .arg("install")
-            .arg("--isolated")
-            .arg("--ignore-installed")
-            .arg("--upgrade")
-            .arg("setuptools")
-            .arg("wheel")
-            .arg("soundfile");
-
-        #[cfg(target_os = "windows")]
-        {
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            command.creation_flags(CREATE_NO_WINDOW);
-        }
-
-        let output = command
-            .output().await
-            .map_err(|e| format!("Failed to execute pip for dependencies: {} (command: {} -m pip install --upgrade setuptools wheel soundfile)", 
-                e, local_python_path.to_string_lossy()))?;
-
-        log::info!(
-            "Dependencies install stdout: {}",
-            String::from_utf8_lossy(&output.stdout)
-        );
-        log::info!(
-            "Dependencies install stderr: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+        // ハッシュのない行があるとpipが途中で止まるため、始める前に確かめる
+        runtime_manifest::check_lock_hashes(REQUIREMENTS_LOCK)?;
 
-        if !output.status.success() {
-            return Err(format!(
-                "Failed to install dependencies: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
+        // 固定バージョンを一時ファイルに書き出し、成功してから正式な場所に移す
+        let pending_lock_path = installed_lock_path.with_extension("lock.pending");
+        tokio::fs::write(&pending_lock_path, REQUIREMENTS_LOCK)
+            .await
+            .map_err(|e| format!("Failed to write requirements.lock: {}", e))?;
 
-        // demucsをインストール
+        // demucsと依存関係を固定バージョンでインストール
         let mut command = tokio::process::Command::new(&local_python_path);
 
         command
@@ -369,8 +349,10 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
             .arg("install")
             .arg("--isolated")
             .arg("--ignore-installed")
-            .arg("--upgrade")
-            .arg("demucs")
+            .arg("--require-hashes")
+            .arg("-r")
+            .arg(&pending_lock_path)
+            .args(settings.pip_args())
             .env("PYTHONUSERBASE", "") // ユーザーサイトパッケージを無効化
             .env("PYTHONPATH", ""); // PYTHONPATH をクリア
 
@@ -382,9 +364,10 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
 
         let output = command.output().await.map_err(|e| {
             format!(
-                "Failed to execute pip for demucs: {} (command: {} -m pip install --upgrade demucs)",
+                "Failed to execute pip for demucs: {} (command: {} -m pip install -r {})",
                 e,
-                local_python_path.to_string_lossy()
+                local_python_path.to_string_lossy(),
+                pending_lock_path.to_string_lossy()
             )
         })?;
 
@@ -401,12 +384,17 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
         }
 
         if !output.status.success() {
+            let _ = tokio::fs::remove_file(&pending_lock_path).await;
             return Err(format!(
                 "Failed to install demucs: exit code {}. Error: {}",
                 output.status.code().unwrap_or(-1),
                 stderr
             ));
         }
+
+        tokio::fs::rename(&pending_lock_path, &installed_lock_path)
+            .await
+            .map_err(|e| format!("Failed to save requirements.lock: {}", e))?;
     }
 
     log::info!("Demucs is ready at: {}", demucs_path.to_string_lossy());
@@ -415,10 +403,32 @@ pub async fn check_demucs(app_handle: tauri::AppHandle) -> Result<String, String
     Ok(demucs_path.to_string_lossy().to_string())
 }
 
+/// 使用するFFmpegのパスを返す
+///
+/// 設定済みのパスやPATH上のffmpegを優先し、downloadがtrueの場合のみダウンロードする。
+/// 見つからない場合は空文字を返す（デコードはSymphoniaで行う）。
 #[tauri::command]
-pub async fn check_ffmpeg(app_handle: tauri::AppHandle) -> Result<String, String> {
+pub async fn check_ffmpeg(
+    app_handle: tauri::AppHandle,
+    download: Option<bool>,
+) -> Result<String, String> {
     log::info!("Checking FFmpeg environment...");
 
+    let settings = RuntimeSettings::load(&app_handle)?;
+
+    let local_python_dir = app_handle
+        .path()
+        .resolve("python_env", tauri::path::BaseDirectory::AppLocalData)
+        .expect("Failed to resolve local Python directory path");
+    if let Some((path, source)) = crate::ffmpeg::find_ffmpeg(&local_python_dir, &settings) {
+        log::info!("Using {:?} FFmpeg at: {}", source, path.display());
+        return Ok(path.to_string_lossy().to_string());
+    }
+    if !download.unwrap_or(false) {
+        log::info!("FFmpeg not found, audio will be decoded with Symphonia");
+        return Ok(String::new());
+    }
+
     // ffmpegのパスを取得（AppLocalDataのpython_env/Scripts/ffmpeg.exe）
     let ffmpeg_path = app_handle
         .path()
@@ -435,36 +445,16 @@ pub async fn check_ffmpeg(app_handle: tauri::AppHandle) -> Result<String, String
     if !ffmpeg_path.exists() {
         log::info!("FFmpeg not found, downloading...");
 
-        const FFMPEG_URL: &str = "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip";
-
         // 一時ファイルのパスを生成
-        let local_python_dir = app_handle
-            .path()
-            .resolve("python_env", tauri::path::BaseDirectory::AppLocalData)
-            .expect("Failed to resolve local Python directory path");
-
         let temp_zip_path = local_python_dir
             .parent()
             .ok_or("Failed to get parent directory")?
             .join("ffmpeg_temp.zip");
 
-        // FFmpegのzipファイルをダウンロード
-        log::info!("Downloading FFmpeg from: {}", FFMPEG_URL);
-        let response = reqwest::get(FFMPEG_URL)
-            .await
-            .map_err(|e| format!("Failed to download FFmpeg: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!(
-                "Failed to download FFmpeg: HTTP {}",
-                response.status()
-            ));
-        }
-
-        let content = response
-            .bytes()
-            .await
-            .map_err(|e| format!("Failed to read download content: {}", e))?;
+        // FFmpegのzipファイルをダウンロード（展開前にハッシュを検証）
+        let client = settings.http_client()?;
+        let content =
+            runtime_manifest::download_artifact(&client, "FFmpeg", &settings.ffmpeg()).await?;
 
         // 一時ファイルに保存
         log::info!("Saving downloaded file to: {}", temp_zip_path.display());
@@ -0,0 +1,776 @@
+use crate::python_env::{extract_zip, PTH_CONTENT};
+use crate::runtime_manifest::{self, REQUIREMENTS_LOCK};
+use crate::runtime_settings::RuntimeSettings;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+// バンドル内のファイル配置
+const BUNDLE_INFO_FILE: &str = "bundle.json";
+const BUNDLE_PYTHON_DIR: &str = "python"; // 埋め込み版Python（site-packagesなし）
+const BUNDLE_GET_PIP: &str = "get-pip.py";
+const BUNDLE_WHEELHOUSE_DIR: &str = "wheelhouse";
+const BUNDLE_REQUIREMENTS: &str = "requirements.lock";
+const BUNDLE_FFMPEG: &str = "ffmpeg.exe"; // 任意
+const BUNDLE_MODELS_DIR: &str = "models"; // 任意（torch hubのcheckpoints）
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+// インストール中の作業ディレクトリと、差し替え中に退避する既存のpython_env
+const STAGING_DIR_NAME: &str = "python_env.staging";
+const BACKUP_DIR_NAME: &str = "python_env.old";
+
+// python_envのうちPython本体以外のもの（エクスポート時に除外する）
+const NON_PYTHON_ENTRIES: [&str; 5] =
+    ["Lib", "Scripts", "torch", "requirements.lock", "get-pip.py"];
+
+/// Demucsのモデルの保存先（python_envからの相対パス、TORCH_HOME配下）
+pub(crate) const TORCH_HOME_DIR: &str = "torch";
+const TORCH_CHECKPOINTS_DIR: &str = "torch/hub/checkpoints";
+
+/// bundle.jsonの内容
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleInfo {
+    format_version: u32,
+    /// requirements.lockのSHA-256
+    requirements_sha256: String,
+}
+
+/// ローカルのバンドル（ディレクトリまたはzip）からランタイムをインストールする
+///
+/// 別ディレクトリに組み立てて確認できてから既存のpython_envと入れ替える。
+/// バンドルにFFmpegやモデルがなければ既存のものを引き継ぐ。
+#[tauri::command]
+pub async fn install_runtime_bundle(
+    app_handle: tauri::AppHandle,
+    bundle_path: String,
+) -> Result<(), String> {
+    let bundle_path = PathBuf::from(bundle_path);
+    log::info!("Installing runtime from bundle: {}", bundle_path.display());
+
+    let local_data_dir = app_handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|e| format!("Failed to resolve AppLocalData directory: {}", e))?;
+    let python_env_dir = local_data_dir.join("python_env");
+
+    // zipの場合は一時ディレクトリに展開する
+    let extract_dir = local_data_dir.join("runtime_bundle");
+    let bundle_dir = if bundle_path.is_file() {
+        let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+        let zip_path = bundle_path.clone();
+        let dest_dir = extract_dir.clone();
+        tokio::task::spawn_blocking(move || extract_zip(&zip_path, &dest_dir))
+            .await
+            .map_err(|e| format!("Task join error: {}", e))??;
+        find_bundle_root(&extract_dir)?
+    } else {
+        find_bundle_root(&bundle_path)?
+    };
+
+    let result = install_from_dir(&bundle_dir, &python_env_dir).await;
+    let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+    result?;
+
+    log::info!("Runtime installed from bundle");
+    Ok(())
+}
+
+/// 動作しているランタイムからバンドルを作成する
+///
+/// output_pathが.zipで終わる場合はzipに、それ以外はディレクトリに書き出す。
+/// wheelの取得にはネットワークが必要。
+#[tauri::command]
+pub async fn export_runtime_bundle(
+    app_handle: tauri::AppHandle,
+    output_path: String,
+) -> Result<String, String> {
+    let output_path = PathBuf::from(output_path);
+    log::info!("Exporting runtime bundle to: {}", output_path.display());
+
+    let local_data_dir = app_handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|e| format!("Failed to resolve AppLocalData directory: {}", e))?;
+    let python_env_dir = local_data_dir.join("python_env");
+    let python_path = python_env_dir.join("python.exe");
+    if !python_path.exists() || !python_env_dir.join("Scripts/demucs.exe").exists() {
+        return Err("Runtime is not installed; run the setup first".to_string());
+    }
+
+    let as_zip = output_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    let staging_dir = if as_zip {
+        let staging_dir = local_data_dir.join("runtime_bundle_export");
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+        staging_dir
+    } else {
+        if output_path.exists()
+            && output_path
+                .read_dir()
+                .map_or(true, |mut d| d.next().is_some())
+        {
+            return Err(format!(
+                "Output directory is not empty: {}",
+                output_path.display()
+            ));
+        }
+        output_path.clone()
+    };
+
+    let settings = RuntimeSettings::load(&app_handle)?;
+    let result = export_to_dir(&python_env_dir, &staging_dir, &settings).await;
+    let result = match result {
+        Ok(()) if as_zip => {
+            let source = staging_dir.clone();
+            let dest = output_path.clone();
+            tokio::task::spawn_blocking(move || write_zip(&source, &dest))
+                .await
+                .map_err(|e| format!("Task join error: {}", e))?
+        }
+        other => other,
+    };
+    if as_zip {
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+    }
+    result?;
+
+    log::info!("Runtime bundle exported to: {}", output_path.display());
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+async fn install_from_dir(bundle_dir: &Path, python_env_dir: &Path) -> Result<(), String> {
+    // バンドルの形式とバージョン固定が一致しているか確認
+    let info = tokio::fs::read_to_string(bundle_dir.join(BUNDLE_INFO_FILE))
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", BUNDLE_INFO_FILE, e))?;
+    let info: BundleInfo = serde_json::from_str(&info)
+        .map_err(|e| format!("Failed to parse {}: {}", BUNDLE_INFO_FILE, e))?;
+    if info.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported bundle format version: {}",
+            info.format_version
+        ));
+    }
+    let requirements = tokio::fs::read_to_string(bundle_dir.join(BUNDLE_REQUIREMENTS))
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", BUNDLE_REQUIREMENTS, e))?;
+    if runtime_manifest::sha256_hex(requirements.as_bytes()) != info.requirements_sha256 {
+        return Err(format!(
+            "{} does not match {}",
+            BUNDLE_REQUIREMENTS, BUNDLE_INFO_FILE
+        ));
+    }
+    if requirements != REQUIREMENTS_LOCK {
+        return Err(
+            "Bundle was exported with different pinned versions than this app requires".to_string(),
+        );
+    }
+    runtime_manifest::check_lock_hashes(&requirements)?;
+
+    // 隣のディレクトリに組み立てて確認してから差し替える（途中で失敗しても既存の環境は残る）
+    let staging_dir = python_env_dir.with_file_name(STAGING_DIR_NAME);
+    let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+    let result = match build_env(bundle_dir, &staging_dir, python_env_dir, &requirements).await {
+        Ok(()) => swap_env(bundle_dir, &staging_dir, python_env_dir, &requirements).await,
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+    result
+}
+
+/// バンドルからenv_dirに環境を組み立て、requirements.lockどおりに入ったか確認する
+async fn build_env(
+    bundle_dir: &Path,
+    env_dir: &Path,
+    current_env_dir: &Path,
+    requirements: &str,
+) -> Result<(), String> {
+    // Python本体をコピー
+    log::info!("Copying Python to: {}", env_dir.display());
+    copy_dir_blocking(
+        bundle_dir.join(BUNDLE_PYTHON_DIR),
+        env_dir.to_path_buf(),
+        |_| true,
+    )
+    .await?;
+    tokio::fs::write(env_dir.join("python313._pth"), PTH_CONTENT)
+        .await
+        .map_err(|e| format!("Failed to update python313._pth: {}", e))?;
+
+    let python_path = env_dir.join("python.exe");
+    let wheelhouse = bundle_dir.join(BUNDLE_WHEELHOUSE_DIR);
+    let get_pip_path = env_dir.join(BUNDLE_GET_PIP);
+    tokio::fs::copy(bundle_dir.join(BUNDLE_GET_PIP), &get_pip_path)
+        .await
+        .map_err(|e| format!("Failed to copy get-pip.py: {}", e))?;
+
+    // pipをwheelhouseからインストール
+    log::info!("Installing pip from wheelhouse...");
+    run_python(
+        &python_path,
+        &[
+            get_pip_path.as_os_str(),
+            "--no-index".as_ref(),
+            "--find-links".as_ref(),
+            wheelhouse.as_os_str(),
+        ],
+    )
+    .await
+    .map_err(|e| format!("Failed to install pip: {}", e))?;
+
+    // demucsと依存関係をwheelhouseからインストール
+    log::info!("Installing Demucs from wheelhouse...");
+    let lock_path = env_dir.join(BUNDLE_REQUIREMENTS);
+    let pending_lock_path = lock_path.with_extension("lock.pending");
+    tokio::fs::write(&pending_lock_path, requirements)
+        .await
+        .map_err(|e| format!("Failed to write requirements.lock: {}", e))?;
+    run_python(
+        &python_path,
+        &[
+            "-m".as_ref(),
+            "pip".as_ref(),
+            "install".as_ref(),
+            "--isolated".as_ref(),
+            "--no-index".as_ref(),
+            "--find-links".as_ref(),
+            wheelhouse.as_os_str(),
+            "--require-hashes".as_ref(),
+            "-r".as_ref(),
+            pending_lock_path.as_os_str(),
+        ],
+    )
+    .await
+    .map_err(|e| format!("Failed to install demucs: {}", e))?;
+    tokio::fs::rename(&pending_lock_path, &lock_path)
+        .await
+        .map_err(|e| format!("Failed to save requirements.lock: {}", e))?;
+
+    // FFmpegとモデルはバンドルになければ今の環境のものを引き継ぐ
+    let ffmpeg_path = [
+        bundle_dir.join(BUNDLE_FFMPEG),
+        current_env_dir.join("Scripts/ffmpeg.exe"),
+    ]
+    .into_iter()
+    .find(|path| path.exists());
+    if let Some(ffmpeg_path) = ffmpeg_path {
+        log::info!("Copying FFmpeg from: {}", ffmpeg_path.display());
+        tokio::fs::create_dir_all(env_dir.join("Scripts"))
+            .await
+            .map_err(|e| format!("Failed to create Scripts directory: {}", e))?;
+        tokio::fs::copy(&ffmpeg_path, env_dir.join("Scripts/ffmpeg.exe"))
+            .await
+            .map_err(|e| format!("Failed to copy ffmpeg.exe: {}", e))?;
+    }
+    let models_dir = [
+        bundle_dir.join(BUNDLE_MODELS_DIR),
+        current_env_dir.join(TORCH_CHECKPOINTS_DIR),
+    ]
+    .into_iter()
+    .find(|path| path.exists());
+    if let Some(models_dir) = models_dir {
+        log::info!("Copying Demucs models from: {}", models_dir.display());
+        copy_dir_blocking(models_dir, env_dir.join(TORCH_CHECKPOINTS_DIR), |_| true).await?;
+    }
+
+    // 差し替える前に固定どおりに入ったか確認する
+    let installed_lock = tokio::fs::read_to_string(&lock_path)
+        .await
+        .map_err(|e| format!("Failed to read installed requirements.lock: {}", e))?;
+    if installed_lock != REQUIREMENTS_LOCK {
+        return Err("Installed requirements.lock does not match this app".to_string());
+    }
+    run_python(
+        &python_path,
+        &["-m".as_ref(), "pip".as_ref(), "check".as_ref()],
+    )
+    .await
+    .map_err(|e| format!("Installed packages are inconsistent: {}", e))?;
+    run_python(&python_path, &["-c".as_ref(), "import demucs".as_ref()])
+        .await
+        .map_err(|e| format!("Failed to import demucs: {}", e))?;
+
+    Ok(())
+}
+
+/// 組み立てた環境をpython_envと入れ替える（成功するまで既存の環境は退避して残す）
+async fn swap_env(
+    bundle_dir: &Path,
+    staging_dir: &Path,
+    python_env_dir: &Path,
+    requirements: &str,
+) -> Result<(), String> {
+    let backup_dir = python_env_dir.with_file_name(BACKUP_DIR_NAME);
+    let _ = tokio::fs::remove_dir_all(&backup_dir).await;
+    let had_env = python_env_dir.exists();
+    if had_env {
+        tokio::fs::rename(python_env_dir, &backup_dir)
+            .await
+            .map_err(|e| format!("Failed to move the current runtime aside: {}", e))?;
+    }
+
+    let result = match tokio::fs::rename(staging_dir, python_env_dir).await {
+        Ok(()) => regenerate_launchers(bundle_dir, python_env_dir, requirements).await,
+        Err(e) => Err(format!("Failed to move the new runtime into place: {}", e)),
+    };
+    match result {
+        Ok(()) => {
+            let _ = tokio::fs::remove_dir_all(&backup_dir).await;
+            Ok(())
+        }
+        Err(e) if had_env => {
+            // 新しい環境を捨てて元に戻す
+            if python_env_dir.exists() {
+                let _ = tokio::fs::rename(python_env_dir, staging_dir).await;
+            }
+            tokio::fs::rename(&backup_dir, python_env_dir)
+                .await
+                .map_err(|restore_error| {
+                    format!(
+                        "{}; also failed to restore the previous runtime ({}), it is kept at {}",
+                        e,
+                        restore_error,
+                        backup_dir.display()
+                    )
+                })?;
+            Err(e)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Scripts/demucs.exeは作成時のpython.exeの絶対パスを埋め込むので、移動後に作り直す
+async fn regenerate_launchers(
+    bundle_dir: &Path,
+    python_env_dir: &Path,
+    requirements: &str,
+) -> Result<(), String> {
+    let entry = lock_entry(requirements, "demucs")
+        .ok_or_else(|| "demucs is not pinned in requirements.lock".to_string())?;
+    let entry_path = python_env_dir.join("demucs.lock.pending");
+    tokio::fs::write(&entry_path, entry)
+        .await
+        .map_err(|e| format!("Failed to write demucs.lock.pending: {}", e))?;
+    let wheelhouse = bundle_dir.join(BUNDLE_WHEELHOUSE_DIR);
+    let result = run_python(
+        &python_env_dir.join("python.exe"),
+        &[
+            "-m".as_ref(),
+            "pip".as_ref(),
+            "install".as_ref(),
+            "--isolated".as_ref(),
+            "--no-index".as_ref(),
+            "--find-links".as_ref(),
+            wheelhouse.as_os_str(),
+            "--no-deps".as_ref(),
+            "--force-reinstall".as_ref(),
+            "--require-hashes".as_ref(),
+            "-r".as_ref(),
+            entry_path.as_os_str(),
+        ],
+    )
+    .await
+    .map_err(|e| format!("Failed to regenerate demucs.exe: {}", e));
+    let _ = tokio::fs::remove_file(&entry_path).await;
+    result
+}
+
+/// requirements.lockから1パッケージ分の記述（--hashの継続行を含む）を取り出す
+fn lock_entry(requirements: &str, package: &str) -> Option<String> {
+    let prefix = format!("{}==", package);
+    let mut entry = String::new();
+    for line in requirements
+        .lines()
+        .skip_while(|line| !line.starts_with(&prefix))
+    {
+        entry.push_str(line);
+        entry.push('\n');
+        if !line.trim_end().ends_with('\\') {
+            break;
+        }
+    }
+    (!entry.is_empty()).then_some(entry)
+}
+
+async fn export_to_dir(
+    python_env_dir: &Path,
+    bundle_dir: &Path,
+    settings: &RuntimeSettings,
+) -> Result<(), String> {
+    // pip downloadの--require-hashesはハッシュのない行で止まるため、コピーを始める前に確かめる
+    runtime_manifest::check_lock_hashes(REQUIREMENTS_LOCK)?;
+    tokio::fs::create_dir_all(bundle_dir)
+        .await
+        .map_err(|e| format!("Failed to create bundle directory: {}", e))?;
+
+    // Python本体（pipで追加したものを除く）
+    log::info!("Copying Python...");
+    let root = python_env_dir.to_path_buf();
+    copy_dir_blocking(
+        python_env_dir.to_path_buf(),
+        bundle_dir.join(BUNDLE_PYTHON_DIR),
+        move |path| {
+            let relative = path.strip_prefix(&root).unwrap_or(path);
+            !NON_PYTHON_ENTRIES
+                .iter()
+                .any(|entry| relative == Path::new(entry))
+                && path.extension().is_none_or(|ext| ext != "pending")
+        },
+    )
+    .await?;
+
+    // get-pip.py（残っていなければマニフェストから取得）
+    let get_pip_path = python_env_dir.join(BUNDLE_GET_PIP);
+    if get_pip_path.exists() {
+        tokio::fs::copy(&get_pip_path, bundle_dir.join(BUNDLE_GET_PIP))
+            .await
+            .map_err(|e| format!("Failed to copy get-pip.py: {}", e))?;
+    } else {
+        let client = settings.http_client()?;
+        let content =
+            runtime_manifest::download_artifact(&client, "get-pip.py", &settings.get_pip()).await?;
+        tokio::fs::write(bundle_dir.join(BUNDLE_GET_PIP), content)
+            .await
+            .map_err(|e| format!("Failed to save get-pip.py: {}", e))?;
+    }
+
+    let requirements_path = bundle_dir.join(BUNDLE_REQUIREMENTS);
+    tokio::fs::write(&requirements_path, REQUIREMENTS_LOCK)
+        .await
+        .map_err(|e| format!("Failed to write requirements.lock: {}", e))?;
+
+    // 固定バージョンのwheel（pip自身を含む）をハッシュを確認してダウンロード
+    log::info!("Downloading wheels...");
+    let wheelhouse = bundle_dir.join(BUNDLE_WHEELHOUSE_DIR);
+    let pip_args = settings.pip_args();
+    let mut args: Vec<&std::ffi::OsStr> = vec![
+        "-m".as_ref(),
+        "pip".as_ref(),
+        "download".as_ref(),
+        "--isolated".as_ref(),
+        "-d".as_ref(),
+        wheelhouse.as_os_str(),
+        "--require-hashes".as_ref(),
+        "-r".as_ref(),
+        requirements_path.as_os_str(),
+    ];
+    args.extend(pip_args.iter().map(std::ffi::OsStr::new));
+    run_python(&python_env_dir.join("python.exe"), &args)
+        .await
+        .map_err(|e| format!("Failed to download wheels: {}", e))?;
+
+    let ffmpeg_path = python_env_dir.join("Scripts/ffmpeg.exe");
+    if ffmpeg_path.exists() {
+        tokio::fs::copy(&ffmpeg_path, bundle_dir.join(BUNDLE_FFMPEG))
+            .await
+            .map_err(|e| format!("Failed to copy ffmpeg.exe: {}", e))?;
+    }
+    let checkpoints_dir = python_env_dir.join(TORCH_CHECKPOINTS_DIR);
+    if checkpoints_dir.exists() {
+        copy_dir_blocking(checkpoints_dir, bundle_dir.join(BUNDLE_MODELS_DIR), |_| {
+            true
+        })
+        .await?;
+    }
+
+    let info = BundleInfo {
+        format_version: BUNDLE_FORMAT_VERSION,
+        requirements_sha256: runtime_manifest::sha256_hex(REQUIREMENTS_LOCK.as_bytes()),
+    };
+    let info = serde_json::to_string_pretty(&info)
+        .map_err(|e| format!("Failed to serialize {}: {}", BUNDLE_INFO_FILE, e))?;
+    tokio::fs::write(bundle_dir.join(BUNDLE_INFO_FILE), info)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", BUNDLE_INFO_FILE, e))?;
+
+    Ok(())
+}
+
+/// bundle.jsonのあるディレクトリを探す（zipの中で1階層下にある場合も許容）
+fn find_bundle_root(dir: &Path) -> Result<PathBuf, String> {
+    if dir.join(BUNDLE_INFO_FILE).exists() {
+        return Ok(dir.to_path_buf());
+    }
+    let entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read bundle directory {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    match entries.as_slice() {
+        [only] if only.join(BUNDLE_INFO_FILE).exists() => Ok(only.clone()),
+        _ => Err(format!(
+            "{} not found in bundle: {}",
+            BUNDLE_INFO_FILE,
+            dir.display()
+        )),
+    }
+}
+
+/// python.exeを実行し、失敗した場合はstderrを返す
+async fn run_python(python_path: &Path, args: &[&std::ffi::OsStr]) -> Result<(), String> {
+    let mut command = tokio::process::Command::new(python_path);
+    command
+        .args(args)
+        .env("PYTHONUSERBASE", "") // ユーザーサイトパッケージを無効化
+        .env("PYTHONPATH", ""); // PYTHONPATH をクリア
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute {}: {}", python_path.display(), e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.is_empty() {
+        log::info!("[PYTHON STDOUT] {}", stdout);
+    }
+    if !stderr.is_empty() {
+        log::info!("[PYTHON STDERR] {}", stderr);
+    }
+
+    if !output.status.success() {
+        return Err(format!(
+            "exit code {}. Error: {}",
+            output.status.code().unwrap_or(-1),
+            stderr
+        ));
+    }
+    Ok(())
+}
+
+async fn copy_dir_blocking<F>(source: PathBuf, dest: PathBuf, filter: F) -> Result<(), String>
+where
+    F: Fn(&Path) -> bool + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || copy_dir(&source, &dest, &filter))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// ディレクトリを再帰的にコピーする（filterがfalseを返したものは除外）
+fn copy_dir(source: &Path, dest: &Path, filter: &dyn Fn(&Path) -> bool) -> Result<(), String> {
+    std::fs::create_dir_all(dest)
+        .map_err(|e| format!("Failed to create directory {}: {}", dest.display(), e))?;
+    let entries = std::fs::read_dir(source)
+        .map_err(|e| format!("Failed to read directory {}: {}", source.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let path = entry.path();
+        if !filter(&path) {
+            continue;
+        }
+        let target = dest.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &target, filter)?;
+        } else {
+            std::fs::copy(&path, &target)
+                .map_err(|e| format!("Failed to copy {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// ディレクトリの中身をzipファイルに書き出す
+fn write_zip(source: &Path, zip_path: &Path) -> Result<(), String> {
+    let file =
+        std::fs::File::create(zip_path).map_err(|e| format!("Failed to create zip file: {}", e))?;
+    let mut writer = zip::ZipWriter::new(file);
+    // wheelは圧縮済みなので無圧縮で格納する
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+
+    let mut stack = vec![source.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read directory entry: {}", e))?
+                .path();
+            let name = path
+                .strip_prefix(source)
+                .map_err(|e| format!("Failed to build zip entry name: {}", e))?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if path.is_dir() {
+                writer
+                    .add_directory(name, options)
+                    .map_err(|e| format!("Failed to add directory to zip: {}", e))?;
+                stack.push(path);
+            } else {
+                writer
+                    .start_file(name, options)
+                    .map_err(|e| format!("Failed to add file to zip: {}", e))?;
+                let mut input = std::fs::File::open(&path)
+                    .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+                std::io::copy(&mut input, &mut writer)
+                    .map_err(|e| format!("Failed to write zip entry: {}", e))?;
+            }
+        }
+    }
+
+    writer
+        .finish()
+        .map_err(|e| format!("Failed to finalize zip: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用のバンドルを置く一時ディレクトリ（終了時に削除する）
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "souon-runtime-bundle-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, relative: &str, content: &str) {
+            let path = self.0.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn bundle_info(requirements: &str) -> String {
+        serde_json::to_string(&BundleInfo {
+            format_version: BUNDLE_FORMAT_VERSION,
+            requirements_sha256: runtime_manifest::sha256_hex(requirements.as_bytes()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn lock_entry_includes_hash_continuation_lines() {
+        let requirements = "# header\n\
+            demucs==4.0.1 \\\n    --hash=sha256:aaaa \\\n    --hash=sha256:bbbb\n\
+            dora-search==0.1.12 \\\n    --hash=sha256:cccc\n";
+
+        assert_eq!(
+            lock_entry(requirements, "demucs").unwrap(),
+            "demucs==4.0.1 \\\n    --hash=sha256:aaaa \\\n    --hash=sha256:bbbb\n"
+        );
+        assert_eq!(
+            lock_entry(requirements, "dora-search").unwrap(),
+            "dora-search==0.1.12 \\\n    --hash=sha256:cccc\n"
+        );
+    }
+
+    #[test]
+    fn lock_entry_is_none_for_missing_package() {
+        let requirements = "demucs-extra==1.0 \\\n    --hash=sha256:aaaa\nsix==1.17.0\n";
+
+        assert_eq!(lock_entry(requirements, "demucs"), None);
+        assert_eq!(lock_entry("", "demucs"), None);
+    }
+
+    #[test]
+    fn find_bundle_root_accepts_nested_directory() {
+        let tree = TempTree::new("root");
+        tree.write("top/bundle.json", "{}");
+        assert_eq!(
+            find_bundle_root(&tree.0.join("top")).unwrap(),
+            tree.0.join("top")
+        );
+
+        // zipを展開すると1階層下に入っていることがある
+        tree.write("nested/runtime/bundle.json", "{}");
+        assert_eq!(
+            find_bundle_root(&tree.0.join("nested")).unwrap(),
+            tree.0.join("nested/runtime")
+        );
+    }
+
+    #[test]
+    fn find_bundle_root_rejects_missing_or_ambiguous_bundle() {
+        let tree = TempTree::new("missing");
+        tree.write("empty/readme.txt", "");
+        assert!(find_bundle_root(&tree.0.join("empty")).is_err());
+
+        tree.write("two/a/bundle.json", "{}");
+        tree.write("two/b/bundle.json", "{}");
+        assert!(find_bundle_root(&tree.0.join("two")).is_err());
+    }
+
+    #[tokio::test]
+    async fn install_rejects_mismatched_bundle_without_touching_env() {
+        let tree = TempTree::new("install");
+        tree.write("python_env/python.exe", "current");
+        let python_env_dir = tree.0.join("python_env");
+
+        // 別のバージョン固定で作られたバンドル
+        let other = "six==1.16.0\n";
+        tree.write("bundle/bundle.json", &bundle_info(other));
+        tree.write("bundle/requirements.lock", other);
+        let error = install_from_dir(&tree.0.join("bundle"), &python_env_dir)
+            .await
+            .unwrap_err();
+        assert!(error.contains("different pinned versions"), "{}", error);
+
+        // bundle.jsonと一致しないrequirements.lock
+        tree.write("tampered/bundle.json", &bundle_info(REQUIREMENTS_LOCK));
+        tree.write("tampered/requirements.lock", other);
+        let error = install_from_dir(&tree.0.join("tampered"), &python_env_dir)
+            .await
+            .unwrap_err();
+        assert!(error.contains("does not match"), "{}", error);
+
+        assert_eq!(
+            std::fs::read_to_string(python_env_dir.join("python.exe")).unwrap(),
+            "current"
+        );
+        assert!(!tree.0.join(STAGING_DIR_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn swap_restores_previous_env_when_launchers_fail() {
+        let tree = TempTree::new("swap");
+        tree.write("python_env/python.exe", "current");
+        tree.write("python_env.staging/python.exe", "new");
+        let python_env_dir = tree.0.join("python_env");
+
+        // 入れ替えた環境のpython.exeは実行できないのでdemucs.exeの再生成で失敗する
+        let error = swap_env(
+            &tree.0.join("bundle"),
+            &tree.0.join(STAGING_DIR_NAME),
+            &python_env_dir,
+            "demucs==4.0.1\n",
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            error.starts_with("Failed to regenerate demucs.exe"),
+            "{}",
+            error
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(python_env_dir.join("python.exe")).unwrap(),
+            "current"
+        );
+        assert!(!tree.0.join(BACKUP_DIR_NAME).exists());
+    }
+}
@@ -0,0 +1,617 @@
+use crate::ffmpeg::find_ffmpeg;
+use crate::python_env::{self, PTH_CONTENT};
+use crate::runtime_bundle::TORCH_HOME_DIR;
+use crate::runtime_manifest::{self, REQUIREMENTS_LOCK};
+use crate::runtime_settings::RuntimeSettings;
+use serde::{Deserialize, Serialize};
+use souon_core::stem::StemJobQueue;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::Manager;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(120); // torchのimportは時間がかかる
+const DEFAULT_MODEL: &str = "htdemucs";
+const DEFAULT_MODEL_SIGNATURE: &str = "955717e8"; // htdemucsのチェックポイント名の先頭
+const MIN_FREE_BYTES_INSTALL: u64 = 6 * 1024 * 1024 * 1024; // torchを含むインストールに必要な空き容量
+const MIN_FREE_BYTES_RUN: u64 = 1024 * 1024 * 1024; // ステム分離の作業用
+
+// python_envの外に残る一時ファイル
+const LEFTOVER_ENTRIES: [&str; 7] = [
+    "python_temp.zip",
+    "ffmpeg_temp.zip",
+    "runtime_bundle",
+    "runtime_bundle_export",
+    "torch_backup",
+    "python_env.staging",
+    "python_env.old",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// 失敗した項目の修復方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FixAction {
+    /// python_envを作り直す
+    ReinstallPython,
+    /// python313._pthを書き直す
+    RepairPth,
+    InstallPip,
+    /// requirements.lockに従ってDemucsを入れ直す
+    InstallDemucs,
+    InstallFfmpeg,
+    DownloadModel,
+    /// 自動では直せない（ユーザーが空き容量を確保する）
+    FreeDiskSpace,
+}
+
+/// 1項目の診断結果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentCheck {
+    pub id: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<FixAction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentReport {
+    /// エラーの項目がなければtrue（警告は含めない）
+    pub healthy: bool,
+    pub checks: Vec<EnvironmentCheck>,
+}
+
+/// ランタイムの各構成要素を診断する
+#[tauri::command]
+pub async fn diagnose_environment(
+    app_handle: tauri::AppHandle,
+) -> Result<EnvironmentReport, String> {
+    let python_env_dir = python_env_dir(&app_handle)?;
+    let settings = RuntimeSettings::load(&app_handle)?;
+    Ok(diagnose(&python_env_dir, &settings).await)
+}
+
+/// 診断で見つかった問題を修復し、修復後の診断結果を返す
+///
+/// actionsを省略した場合は診断結果の修復方法をすべて実行する。
+#[tauri::command]
+pub async fn repair_environment(
+    app_handle: tauri::AppHandle,
+    queue: tauri::State<'_, StemJobQueue>,
+    actions: Option<Vec<FixAction>>,
+) -> Result<EnvironmentReport, String> {
+    ensure_no_running_jobs(&queue)?;
+    let python_env_dir = python_env_dir(&app_handle)?;
+    let settings = RuntimeSettings::load(&app_handle)?;
+
+    let actions = match actions {
+        Some(actions) => actions,
+        None => diagnose(&python_env_dir, &settings)
+            .await
+            .checks
+            .iter()
+            .filter_map(|check| check.fix)
+            .collect(),
+    };
+
+    for action in plan_fixes(actions) {
+        log::info!("Repairing environment: {:?}", action);
+        apply_fix(&app_handle, &python_env_dir, action).await?;
+    }
+
+    Ok(diagnose(&python_env_dir, &settings).await)
+}
+
+/// python_envと一時ファイルを削除する
+#[tauri::command]
+pub async fn uninstall_environment(
+    app_handle: tauri::AppHandle,
+    queue: tauri::State<'_, StemJobQueue>,
+) -> Result<(), String> {
+    ensure_no_running_jobs(&queue)?;
+    let python_env_dir = python_env_dir(&app_handle)?;
+    log::info!("Removing runtime environment: {}", python_env_dir.display());
+
+    remove_path(&python_env_dir).await?;
+    if let Some(local_data_dir) = python_env_dir.parent() {
+        for entry in LEFTOVER_ENTRIES {
+            remove_path(&local_data_dir.join(entry)).await?;
+        }
+    }
+
+    log::info!("Runtime environment removed");
+    Ok(())
+}
+
+async fn diagnose(python_env_dir: &Path, settings: &RuntimeSettings) -> EnvironmentReport {
+    let python_path = python_env_dir.join("python.exe");
+    let mut checks = Vec::new();
+
+    // Python本体
+    let python_ok = match probe(&python_path, &["--version"]).await {
+        Ok(version) => {
+            checks.push(ok("python", version));
+            true
+        }
+        Err(e) => {
+            checks.push(error("python", e, FixAction::ReinstallPython));
+            false
+        }
+    };
+
+    // python313._pth（import siteがないとpipやsite-packagesが使えない）
+    let pth_path = python_env_dir.join("python313._pth");
+    checks.push(match tokio::fs::read_to_string(&pth_path).await {
+        Ok(content) if content.replace("\r\n", "\n").trim() == PTH_CONTENT => {
+            ok("pth", "python313._pth is correct".to_string())
+        }
+        Ok(_) => error(
+            "pth",
+            "python313._pth does not enable site-packages".to_string(),
+            FixAction::RepairPth,
+        ),
+        Err(e) => error(
+            "pth",
+            format!("Failed to read python313._pth: {}", e),
+            if python_ok {
+                FixAction::RepairPth
+            } else {
+                FixAction::ReinstallPython
+            },
+        ),
+    });
+
+    if python_ok {
+        checks.push(
+            match probe(&python_path, &["-m", "pip", "--version"]).await {
+                Ok(version) => ok("pip", version),
+                Err(e) => error("pip", e, FixAction::InstallPip),
+            },
+        );
+
+        checks.push(
+            match probe(
+                &python_path,
+                &["-c", "import demucs; print(demucs.__version__)"],
+            )
+            .await
+            {
+                Ok(version) => {
+                    let installed_lock =
+                        tokio::fs::read_to_string(python_env_dir.join("requirements.lock"))
+                            .await
+                            .unwrap_or_default();
+                    if installed_lock == REQUIREMENTS_LOCK {
+                        ok("demucs", format!("demucs {}", version))
+                    } else {
+                        warning(
+                            "demucs",
+                            format!(
+                                "demucs {} is installed but does not match requirements.lock",
+                                version
+                            ),
+                            Some(FixAction::InstallDemucs),
+                        )
+                    }
+                }
+                Err(e) => error("demucs", e, FixAction::InstallDemucs),
+            },
+        );
+
+        checks.push(
+            match probe(
+                &python_path,
+                &[
+                    "-c",
+                    "import torch; print(torch.__version__, 'cuda' if torch.cuda.is_available() else 'cpu')",
+                ],
+            )
+            .await
+            {
+                Ok(output) => match output.split_once(' ') {
+                    Some((version, "cuda")) => ok("torch", format!("torch {} (CUDA)", version)),
+                    Some((version, _)) => warning(
+                        "torch",
+                        format!("torch {} (CPU only, separation will be slow)", version),
+                        None,
+                    ),
+                    None => ok("torch", output),
+                },
+                Err(e) => error("torch", e, FixAction::InstallDemucs),
+            },
+        );
+    }
+
+    // FFmpeg（なければSymphoniaでデコードするので必須ではない）
+    checks.push(match find_ffmpeg(python_env_dir, settings) {
+        Some((path, source)) => match probe(&path, &["-version"]).await {
+            Ok(output) => ok(
+                "ffmpeg",
+                format!(
+                    "{} ({:?})",
+                    output.lines().next().unwrap_or_default(),
+                    source
+                ),
+            ),
+            Err(e) => error("ffmpeg", e, FixAction::InstallFfmpeg),
+        },
+        None => warning(
+            "ffmpeg",
+            "FFmpeg not found, audio is decoded with Symphonia".to_string(),
+            None,
+        ),
+    });
+
+    // モデル（なくても初回実行時にダウンロードされる）
+    let checkpoints_dir = python_env_dir.join(TORCH_HOME_DIR).join("hub/checkpoints");
+    let model_cached = std::fs::read_dir(&checkpoints_dir).is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(DEFAULT_MODEL_SIGNATURE)
+        })
+    });
+    checks.push(if model_cached {
+        ok("model", format!("{} weights are cached", DEFAULT_MODEL))
+    } else {
+        warning(
+            "model",
+            format!(
+                "{} weights are not cached and will be downloaded on first use",
+                DEFAULT_MODEL
+            ),
+            python_ok.then_some(FixAction::DownloadModel),
+        )
+    });
+
+    // 空き容量（未インストールならtorchの分も必要）
+    let required = if python_ok {
+        MIN_FREE_BYTES_RUN
+    } else {
+        MIN_FREE_BYTES_INSTALL
+    };
+    let existing_dir = python_env_dir
+        .ancestors()
+        .find(|dir| dir.exists())
+        .unwrap_or(python_env_dir);
+    checks.push(match fs2::available_space(existing_dir) {
+        Ok(free) if free >= required => ok("disk", format!("{} free", format_bytes(free))),
+        Ok(free) => error(
+            "disk",
+            format!(
+                "{} free, at least {} required",
+                format_bytes(free),
+                format_bytes(required)
+            ),
+            FixAction::FreeDiskSpace,
+        ),
+        Err(e) => warning(
+            "disk",
+            format!("Failed to get free disk space: {}", e),
+            None,
+        ),
+    });
+
+    EnvironmentReport {
+        healthy: checks.iter().all(|c| c.status != CheckStatus::Error),
+        checks,
+    }
+}
+
+/// 修復方法を実行する順に並べる
+///
+/// 依存関係の順（Python→pip→Demucs→…）にし、Pythonを作り直す場合は他の修復も済むのでそれだけにする。
+fn plan_fixes(mut actions: Vec<FixAction>) -> Vec<FixAction> {
+    actions.sort();
+    actions.dedup();
+    if let Some(index) = actions
+        .iter()
+        .position(|&action| action == FixAction::ReinstallPython)
+    {
+        actions.truncate(index + 1);
+    }
+    actions
+}
+
+/// 修復に必要なハッシュが固定されているか確認する
+///
+/// 固定されていなければ入れ直せないため、今の環境を消す前に止める。
+fn check_fix_pins(action: FixAction) -> Result<(), String> {
+    let manifest = runtime_manifest::manifest();
+    match action {
+        FixAction::ReinstallPython => runtime_manifest::check_pins(),
+        FixAction::InstallPip => runtime_manifest::check_artifact_pin("getPip", &manifest.get_pip),
+        FixAction::InstallDemucs => runtime_manifest::check_lock_hashes(REQUIREMENTS_LOCK),
+        FixAction::InstallFfmpeg => {
+            runtime_manifest::check_artifact_pin("ffmpeg", &manifest.ffmpeg)
+        }
+        FixAction::RepairPth | FixAction::DownloadModel | FixAction::FreeDiskSpace => Ok(()),
+    }
+}
+
+async fn apply_fix(
+    app_handle: &tauri::AppHandle,
+    python_env_dir: &Path,
+    action: FixAction,
+) -> Result<(), String> {
+    check_fix_pins(action)?;
+    match action {
+        FixAction::ReinstallPython => {
+            // 一から作り直す（既存のモデルは残す）
+            let torch_home = python_env_dir.join(TORCH_HOME_DIR);
+            let saved_models = python_env_dir.with_file_name("torch_backup");
+            if torch_home.exists() {
+                remove_path(&saved_models).await?;
+                tokio::fs::rename(&torch_home, &saved_models)
+                    .await
+                    .map_err(|e| format!("Failed to keep model cache: {}", e))?;
+            }
+            remove_path(python_env_dir).await?;
+            let result = reinstall_all(app_handle).await;
+            // 戻せなかった場合は退避先を消さずに場所を伝える
+            let restored = if saved_models.exists() {
+                restore_model_cache(&saved_models, &torch_home).await
+            } else {
+                Ok(())
+            };
+            match (result, restored) {
+                (Err(e), Err(restore_error)) => Err(format!("{}; {}", e, restore_error)),
+                (Err(e), Ok(())) => Err(e),
+                (Ok(()), restored) => restored,
+            }
+        }
+        FixAction::RepairPth => {
+            tokio::fs::write(python_env_dir.join("python313._pth"), PTH_CONTENT)
+                .await
+                .map_err(|e| format!("Failed to update python313._pth: {}", e))
+        }
+        FixAction::InstallPip => {
+            // pip.exeがなければcheck_pythonがget-pip.pyを実行する
+            remove_path(&python_env_dir.join("Scripts/pip.exe")).await?;
+            python_env::check_python(app_handle.clone())
+                .await
+                .map(|_| ())
+        }
+        FixAction::InstallDemucs => {
+            // インストール済みの固定ファイルを消すとcheck_demucsが入れ直す
+            remove_path(&python_env_dir.join("requirements.lock")).await?;
+            python_env::check_demucs(app_handle.clone())
+                .await
+                .map(|_| ())
+        }
+        FixAction::InstallFfmpeg => {
+            remove_path(&python_env_dir.join("Scripts/ffmpeg.exe")).await?;
+            python_env::check_ffmpeg(app_handle.clone(), Some(true))
+                .await
+                .map(|_| ())
+        }
+        FixAction::DownloadModel => {
+            let mut command = python_command(&python_env_dir.join("python.exe"));
+            command
+                .arg("-c")
+                .arg(format!(
+                    "from demucs.pretrained import get_model; get_model('{}')",
+                    DEFAULT_MODEL
+                ))
+                .env("TORCH_HOME", python_env_dir.join(TORCH_HOME_DIR));
+            let output = command
+                .output()
+                .await
+                .map_err(|e| format!("Failed to execute Python: {}", e))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "Failed to download {} weights: {}",
+                    DEFAULT_MODEL,
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            Ok(())
+        }
+        FixAction::FreeDiskSpace => {
+            log::warn!("Free disk space cannot be repaired automatically");
+            Ok(())
+        }
+    }
+}
+
+async fn restore_model_cache(saved_models: &Path, torch_home: &Path) -> Result<(), String> {
+    let restore = async {
+        if let Some(parent) = torch_home.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(saved_models, torch_home).await
+    };
+    restore.await.map_err(|e| {
+        format!(
+            "Failed to restore model cache: {} (kept at {})",
+            e,
+            saved_models.display()
+        )
+    })
+}
+
+async fn reinstall_all(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    python_env::check_python(app_handle.clone()).await?;
+    python_env::check_demucs(app_handle.clone()).await?;
+    python_env::check_ffmpeg(app_handle.clone(), None).await?;
+    Ok(())
+}
+
+/// コマンドを実行し、標準出力（空ならstderr）を返す
+async fn probe(program: &Path, args: &[&str]) -> Result<String, String> {
+    if !program.exists() {
+        return Err(format!("{} not found", program.display()));
+    }
+    let mut command = python_command(program);
+    command.args(args).kill_on_drop(true);
+
+    let output = tokio::time::timeout(PROBE_TIMEOUT, command.output())
+        .await
+        .map_err(|_| format!("{} timed out", program.display()))?
+        .map_err(|e| format!("Failed to execute {}: {}", program.display(), e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if !output.status.success() {
+        // Pythonの例外は最後の行が要点
+        let message = stderr.lines().last().unwrap_or_default();
+        return Err(format!(
+            "exit code {}: {}",
+            output.status.code().unwrap_or(-1),
+            message
+        ));
+    }
+    Ok(if stdout.is_empty() { stderr } else { stdout })
+}
+
+fn python_command(program: &Path) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new(program);
+    command
+        .env("PYTHONUSERBASE", "") // ユーザーサイトパッケージを無効化
+        .env("PYTHONPATH", ""); // PYTHONPATH をクリア
+
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
+
+fn python_env_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path()
+        .resolve("python_env", tauri::path::BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve python_env path: {}", e))
+}
+
+fn ensure_no_running_jobs(queue: &StemJobQueue) -> Result<(), String> {
+    match queue.active_jobs() {
+        0 => Ok(()),
+        jobs => Err(format!(
+            "{} stem separation job(s) are running; cancel them first",
+            jobs
+        )),
+    }
+}
+
+async fn remove_path(path: &Path) -> Result<(), String> {
+    let result = if path.is_dir() {
+        tokio::fs::remove_dir_all(path).await
+    } else if path.exists() {
+        tokio::fs::remove_file(path).await
+    } else {
+        return Ok(());
+    };
+    result.map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+}
+
+fn ok(id: &'static str, detail: String) -> EnvironmentCheck {
+    EnvironmentCheck {
+        id,
+        status: CheckStatus::Ok,
+        detail,
+        fix: None,
+    }
+}
+
+fn warning(id: &'static str, detail: String, fix: Option<FixAction>) -> EnvironmentCheck {
+    EnvironmentCheck {
+        id,
+        status: CheckStatus::Warning,
+        detail,
+        fix,
+    }
+}
+
+fn error(id: &'static str, detail: String, fix: FixAction) -> EnvironmentCheck {
+    EnvironmentCheck {
+        id,
+        status: CheckStatus::Error,
+        detail,
+        fix: Some(fix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_fixes_sorts_and_dedups_in_dependency_order() {
+        let actions = vec![
+            FixAction::DownloadModel,
+            FixAction::InstallDemucs,
+            FixAction::RepairPth,
+            FixAction::InstallDemucs,
+            FixAction::InstallPip,
+        ];
+        assert_eq!(
+            plan_fixes(actions),
+            [
+                FixAction::RepairPth,
+                FixAction::InstallPip,
+                FixAction::InstallDemucs,
+                FixAction::DownloadModel,
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_fixes_stops_after_reinstalling_python() {
+        let actions = vec![
+            FixAction::InstallFfmpeg,
+            FixAction::ReinstallPython,
+            FixAction::InstallDemucs,
+        ];
+        assert_eq!(plan_fixes(actions), [FixAction::ReinstallPython]);
+    }
+
+    #[tokio::test]
+    async fn diagnose_missing_env_plans_python_reinstall_first() {
+        let python_env_dir = std::env::temp_dir().join(format!(
+            "souon-runtime-doctor-{}/python_env",
+            std::process::id()
+        ));
+        let report = diagnose(&python_env_dir, &RuntimeSettings::default()).await;
+
+        assert!(!report.healthy);
+        let python = report.checks.iter().find(|c| c.id == "python").unwrap();
+        assert_eq!(python.fix, Some(FixAction::ReinstallPython));
+        // pipやDemucsはPythonがなければ診断しない
+        assert!(report
+            .checks
+            .iter()
+            .all(|c| c.id != "pip" && c.id != "demucs"));
+
+        let actions = report.checks.iter().filter_map(|c| c.fix).collect();
+        assert_eq!(plan_fixes(actions)[0], FixAction::ReinstallPython);
+    }
+
+    #[test]
+    fn destructive_fixes_require_pins() {
+        // 環境を消さない修復はハッシュがなくても実行できる
+        assert!(check_fix_pins(FixAction::RepairPth).is_ok());
+        assert!(check_fix_pins(FixAction::DownloadModel).is_ok());
+        assert_eq!(
+            check_fix_pins(FixAction::ReinstallPython).is_ok(),
+            runtime_manifest::check_pins().is_ok()
+        );
+        assert_eq!(
+            check_fix_pins(FixAction::InstallDemucs).is_ok(),
+            runtime_manifest::check_lock_hashes(REQUIREMENTS_LOCK).is_ok()
+        );
+    }
+}
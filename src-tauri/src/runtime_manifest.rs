@@ -0,0 +1,287 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Demucs・PyTorchと推移的な依存関係の固定バージョンとハッシュ（pip install --require-hashes -rで使う）
+pub const REQUIREMENTS_LOCK: &str = include_str!("../runtime/requirements.lock");
+
+/// ダウンロードするファイルとそのSHA-256
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuntimeArtifact {
+    pub url: String,
+    /// 16進数の小文字（runtime/update_pins.pyで更新する）
+    pub sha256: String,
+}
+
+/// ランタイムのダウンロード元の一覧（runtime/manifest.json）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeManifest {
+    pub python: RuntimeArtifact,
+    pub get_pip: RuntimeArtifact,
+    pub ffmpeg: RuntimeArtifact,
+}
+
+pub fn manifest() -> &'static RuntimeManifest {
+    static MANIFEST: OnceLock<RuntimeManifest> = OnceLock::new();
+    MANIFEST.get_or_init(|| {
+        serde_json::from_str(include_str!("../runtime/manifest.json"))
+            .expect("Failed to parse runtime/manifest.json")
+    })
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 16進数64文字のSHA-256か
+pub fn is_valid_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// requirements.lockのすべてのパッケージに--hashが付いているか確認する
+///
+/// pipは--require-hashesでハッシュのない行に当たると中断するため、インストールの前に確かめる。
+pub fn check_lock_hashes(requirements: &str) -> Result<(), String> {
+    let mut entries: Vec<String> = Vec::new();
+    let mut continued = false;
+    for line in requirements.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continued = false;
+            continue;
+        }
+        match entries.last_mut() {
+            Some(entry) if continued => {
+                entry.push(' ');
+                entry.push_str(line);
+            }
+            _ => entries.push(line.to_string()),
+        }
+        continued = line.ends_with('\\');
+    }
+    if entries.is_empty() {
+        return Err("requirements.lock has no requirements".to_string());
+    }
+
+    let unhashed: Vec<&str> = entries
+        .iter()
+        .filter(|entry| {
+            !entry
+                .split_whitespace()
+                .filter_map(|token| token.strip_prefix("--hash=sha256:"))
+                .any(is_valid_sha256)
+        })
+        .map(|entry| entry.split_whitespace().next().unwrap_or_default())
+        .collect();
+    if !unhashed.is_empty() {
+        return Err(format!(
+            "requirements.lock has no --hash for {} (run runtime/update_pins.py)",
+            unhashed.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// マニフェストの1項目のハッシュが固定されているか確認する
+pub fn check_artifact_pin(name: &str, artifact: &RuntimeArtifact) -> Result<(), String> {
+    if is_valid_sha256(&artifact.sha256) {
+        Ok(())
+    } else {
+        Err(format!(
+            "No valid SHA-256 pinned for {} in runtime/manifest.json (run runtime/update_pins.py)",
+            name
+        ))
+    }
+}
+
+/// マニフェストとrequirements.lockのハッシュがすべて固定されているか確認する
+pub fn check_pins() -> Result<(), String> {
+    let manifest = manifest();
+    check_artifact_pin("python", &manifest.python)?;
+    check_artifact_pin("getPip", &manifest.get_pip)?;
+    check_artifact_pin("ffmpeg", &manifest.ffmpeg)?;
+    check_lock_hashes(REQUIREMENTS_LOCK)
+}
+
+/// ダウンロードした内容がマニフェストのハッシュと一致するか確認する
+pub fn verify_artifact(name: &str, artifact: &RuntimeArtifact, data: &[u8]) -> Result<(), String> {
+    let actual = sha256_hex(data);
+    // ハッシュが固定されていないものは検証できないため使わない
+    if !is_valid_sha256(&artifact.sha256) {
+        return Err(format!(
+            "No valid SHA-256 pinned for {} in runtime/manifest.json (actual: {})",
+            name, actual
+        ));
+    }
+    if !actual.eq_ignore_ascii_case(&artifact.sha256) {
+        return Err(format!(
+            "SHA-256 mismatch for {}: expected {}, got {}",
+            name, artifact.sha256, actual
+        ));
+    }
+    log::info!("Verified SHA-256 of {}: {}", name, actual);
+    Ok(())
+}
+
+/// ファイルをダウンロードし、ハッシュを検証してから返す
+pub async fn download_artifact(
+    client: &reqwest::Client,
+    name: &str,
+    artifact: &RuntimeArtifact,
+) -> Result<Vec<u8>, String> {
+    log::info!("Downloading {} from: {}", name, artifact.url);
+    let response = client
+        .get(&artifact.url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", name, e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to download {}: HTTP {}",
+            name,
+            response.status()
+        ));
+    }
+
+    let content = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read download content: {}", e))?;
+
+    verify_artifact(name, artifact, &content)?;
+    Ok(content.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// 1回だけ応答するHTTPサーバーを立て、そのURLを返す
+    fn serve_once(status: &'static str, body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buffer).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..n]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        });
+        format!("http://{}/artifact.zip", address)
+    }
+
+    /// 環境変数のプロキシを経由しないクライアント
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder().no_proxy().build().unwrap()
+    }
+
+    fn artifact(url: String, sha256: &str) -> RuntimeArtifact {
+        RuntimeArtifact {
+            url,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn download_returns_content_when_hash_matches() {
+        let body = b"runtime artifact";
+        let url = serve_once("200 OK", body);
+        let artifact = artifact(url, &sha256_hex(body).to_uppercase());
+
+        let content = download_artifact(&client(), "test", &artifact)
+            .await
+            .unwrap();
+        assert_eq!(content, body);
+    }
+
+    #[tokio::test]
+    async fn download_rejects_hash_mismatch() {
+        let url = serve_once("200 OK", b"tampered artifact");
+        let artifact = artifact(url, &sha256_hex(b"runtime artifact"));
+
+        let error = download_artifact(&client(), "test", &artifact)
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("SHA-256 mismatch for test"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn download_rejects_unpinned_hash() {
+        let url = serve_once("200 OK", b"runtime artifact");
+        let artifact = artifact(url, "");
+
+        let error = download_artifact(&client(), "test", &artifact)
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("No valid SHA-256 pinned"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn download_reports_http_error() {
+        let url = serve_once("404 Not Found", b"");
+        let artifact = artifact(url, &sha256_hex(b""));
+
+        let error = download_artifact(&client(), "test", &artifact)
+            .await
+            .unwrap_err();
+        assert!(error.contains("HTTP 404"), "{}", error);
+    }
+
+    #[test]
+    fn manifest_parses() {
+        let manifest = manifest();
+        assert!(manifest.python.url.starts_with("https://"));
+        assert!(manifest.get_pip.url.starts_with("https://"));
+        assert!(manifest.ffmpeg.url.starts_with("https://"));
+    }
+
+    /// 同梱のマニフェストとrequirements.lockのハッシュが固定されていること
+    #[test]
+    fn pins_are_valid() {
+        let manifest = manifest();
+        for artifact in [&manifest.python, &manifest.get_pip, &manifest.ffmpeg] {
+            assert!(is_valid_sha256(&artifact.sha256), "{}", artifact.url);
+        }
+        check_lock_hashes(REQUIREMENTS_LOCK).unwrap();
+    }
+
+    #[test]
+    fn lock_hashes_follow_continuation_lines() {
+        let hash = "ab".repeat(32);
+        let requirements = format!(
+            "# comment\nsix==1.17.0 \\\n    --hash=sha256:{hash} \\\n    --hash=sha256:{hash}\ntqdm==4.67.1 --hash=sha256:{hash}\n"
+        );
+        check_lock_hashes(&requirements).unwrap();
+    }
+
+    #[test]
+    fn lock_hashes_reject_bare_and_short_hashes() {
+        let hash = "ab".repeat(32);
+        let requirements =
+            format!("six==1.17.0 \\\n    --hash=sha256:{hash}\ntqdm==4.67.1\nwheel==0.45.1 --hash=sha256:abc\n");
+        let error = check_lock_hashes(&requirements).unwrap_err();
+        assert!(error.contains("tqdm==4.67.1, wheel==0.45.1"), "{}", error);
+        assert!(!error.contains("six"), "{}", error);
+        assert!(check_lock_hashes("# only comments\n").is_err());
+    }
+}
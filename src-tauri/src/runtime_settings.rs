@@ -0,0 +1,133 @@
+use crate::runtime_manifest::{self, RuntimeArtifact};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+const SETTINGS_FILE: &str = "runtime_settings.json";
+
+/// ランタイムのダウンロード設定（AppLocalData/runtime_settings.json）
+///
+/// URLを差し替えてもハッシュはマニフェストのものを使うため、ミラーは同じファイルを配信する必要がある。
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RuntimeSettings {
+    pub python_url: Option<String>,
+    pub get_pip_url: Option<String>,
+    pub ffmpeg_url: Option<String>,
+    /// HTTPプロキシ（例: "http://proxy.example.com:8080"）
+    pub proxy: Option<String>,
+    /// pipのインデックス（例: "https://pypi.example.com/simple"）
+    pub pypi_index_url: Option<String>,
+    /// 使用するffmpegの実行ファイル（未設定ならPATHから探す）
+    pub ffmpeg_path: Option<String>,
+}
+
+impl RuntimeSettings {
+    /// 設定ファイルを読み込む（存在しない場合は既定値）
+    pub fn load(app_handle: &tauri::AppHandle) -> Result<Self, String> {
+        let path = settings_path(app_handle)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", SETTINGS_FILE, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", SETTINGS_FILE, e))
+    }
+
+    pub fn python(&self) -> RuntimeArtifact {
+        with_url(&runtime_manifest::manifest().python, &self.python_url)
+    }
+
+    pub fn get_pip(&self) -> RuntimeArtifact {
+        with_url(&runtime_manifest::manifest().get_pip, &self.get_pip_url)
+    }
+
+    pub fn ffmpeg(&self) -> RuntimeArtifact {
+        with_url(&runtime_manifest::manifest().ffmpeg, &self.ffmpeg_url)
+    }
+
+    pub fn ffmpeg_path(&self) -> Option<&str> {
+        non_empty(&self.ffmpeg_path)
+    }
+
+    /// プロキシ設定を反映したHTTPクライアント
+    pub fn http_client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = non_empty(&self.proxy) {
+            let proxy =
+                reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+            builder = builder.proxy(proxy);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+
+    /// pipに渡す引数（--isolatedでは環境変数が無視されるため引数で指定する）
+    pub fn pip_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(index_url) = non_empty(&self.pypi_index_url) {
+            args.push("--index-url".to_string());
+            args.push(index_url.to_string());
+        }
+        if let Some(proxy) = non_empty(&self.proxy) {
+            args.push("--proxy".to_string());
+            args.push(proxy.to_string());
+        }
+        args
+    }
+}
+
+#[tauri::command]
+pub fn get_runtime_settings(app_handle: tauri::AppHandle) -> Result<RuntimeSettings, String> {
+    RuntimeSettings::load(&app_handle)
+}
+
+#[tauri::command]
+pub fn set_runtime_settings(
+    app_handle: tauri::AppHandle,
+    settings: RuntimeSettings,
+) -> Result<(), String> {
+    // 保存前に値を検証
+    settings.http_client()?;
+    for url in [
+        &settings.python_url,
+        &settings.get_pip_url,
+        &settings.ffmpeg_url,
+        &settings.pypi_index_url,
+    ] {
+        if let Some(url) = non_empty(url) {
+            reqwest::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        }
+    }
+
+    let path = settings_path(&app_handle)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create AppLocalData directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(&settings)
+        .map_err(|e| format!("Failed to serialize {}: {}", SETTINGS_FILE, e))?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", SETTINGS_FILE, e))
+}
+
+fn settings_path(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    app_handle
+        .path()
+        .resolve(SETTINGS_FILE, tauri::path::BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve {} path: {}", SETTINGS_FILE, e))
+}
+
+fn with_url(artifact: &RuntimeArtifact, url: &Option<String>) -> RuntimeArtifact {
+    match non_empty(url) {
+        Some(url) => RuntimeArtifact {
+            url: url.to_string(),
+            sha256: artifact.sha256.clone(),
+        },
+        None => artifact.clone(),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
@@ -0,0 +1,17 @@
+use souon_core::stem::StemSet;
+use souon_core::stem_activity::{self, StemActivity};
+use souon_core::tempo::TempoEvent;
+
+/// 各ステムの小節ごとの活動状況を計算する
+///
+/// 空のステムはnullを返す。
+#[tauri::command]
+pub async fn stem_activity(
+    music_tempo_list: Vec<TempoEvent>,
+    stems: StemSet<String>,
+) -> Result<StemSet<Option<StemActivity>>, String> {
+    // 重い処理を別スレッドで実行
+    tokio::task::spawn_blocking(move || stem_activity::stem_activity(music_tempo_list, stems))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
@@ -9,6 +9,7 @@ import Chart from "../store/chart";
 import { toaster } from "../components/ui/toaster";
 import { convertFileSrc } from "@tauri-apps/api/core";
 import TempoEvent from "../store/tempoEvent";
+import { StemNote } from "../store/project";
 import { invoke } from "@tauri-apps/api/core";
 import { useRef, useState } from "react";
 import { DialogRoot, DialogContent, DialogHeader, DialogFooter, DialogBody, DialogTitle, DialogDescription, DialogCloseTrigger } from "../components/ui/dialog";
@@ -110,12 +111,27 @@ export default function PlusMenu() {
     try {
       const [base64, mimeType] = await store.project.getMusicBase64();
 
-      const result: string = await invoke("demucs", {
-        inputBase64: base64,
-        mimeType: mimeType,
-      });
+      // Demucsのランタイムがない環境では内蔵のHPSSで代替する
+      // （Demucsの実行エラーやキャンセルでは代替しない）
+      let result: string;
+      if (await invoke<boolean>("demucs_available")) {
+        result = await invoke("demucs", {
+          inputBase64: base64,
+          mimeType: mimeType,
+        });
+      } else {
+        toaster.create({
+          title: "簡易ステム分離を使用します",
+          description: "Demucsがインストールされていないため、内蔵の分離処理を使用します。",
+          type: "info"
+        });
+        result = await invoke("hpss", {
+          inputBase64: base64,
+          mimeType: mimeType,
+        });
+      }
 
-      const stems = result.split("\n").map((s: string) => "data:audio/ogg;base64," + s);
+      const stems = result.split("\n");
       store.project.stems.bass = stems[0];
       store.project.stems.drums = stems[1];
       store.project.stems.other = stems[2];
@@ -149,7 +165,7 @@ export default function PlusMenu() {
     try {
       const stemTypes = ['bass', 'drums', 'other', 'vocals'] as const;
 
-      const stemNotes: { [key in typeof stemTypes[number]]: { pitch: number; velocity: number; time: number }[] } = {
+      const stemNotes: { [key in typeof stemTypes[number]]: StemNote[] } = {
         bass: [],
         drums: [],
         other: [],
@@ -157,9 +173,30 @@ export default function PlusMenu() {
       };
 
       for (const stemType of stemTypes) {
+        // 空のステム（HPSSでモノラル入力のvocalsなど）は検出しない
+        if (!store.project.stems[stemType]) continue;
+
+        // ドラムは種類（キック・スネアなど）も分類する
+        if (stemType === "drums") {
+          const hits: StemNote[] = await invoke("drum_hits", {
+            inputBase64Audio: store.project.stems.drums
+          });
+          stemNotes.drums.push(...hits);
+          continue;
+        }
+
+        // ベースとボーカルは単音の音高追跡で音高区間を求める
+        if (stemType === "bass" || stemType === "vocals") {
+          const segments: StemNote[] = await invoke("track_pitch", {
+            inputBase64Audio: store.project.stems[stemType],
+            instrument: stemType
+          });
+          stemNotes[stemType].push(...segments);
+          continue;
+        }
 
         const result: [number, number, number][] = await invoke("onset", {
-          inputBase64OggVorbis: store.project.stems[stemType]
+          inputBase64Audio: store.project.stems[stemType]
         });
 
         for (const [pitch, velocity, time] of result) {
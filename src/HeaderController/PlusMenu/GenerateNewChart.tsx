@@ -1,16 +1,119 @@
 import { Button, Box, Spinner, Text, Input, HStack, Textarea } from "@chakra-ui/react";
 import { DialogRoot, DialogContent, DialogHeader, DialogFooter, DialogBody, DialogTitle, DialogDescription, DialogCloseTrigger } from "../../components/ui/dialog";
 import { Checkbox } from "../../components/ui/checkbox";
-import { useState, forwardRef, useImperativeHandle, useEffect } from "react";
+import { useState, forwardRef, useImperativeHandle, useEffect, useRef } from "react";
 import { toaster } from "../../components/ui/toaster";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useSnapshot } from "valtio";
 import store from "../../store/store";
 import Chart from "../../store/chart";
-import ChartEvent from "../../store/chartEvent";
 import { SingleNoteEvent } from "../../store/noteEvent";
 import Lane from "../../store/lane";
 import TemporalPosition from "../../store/temporalPosition";
+import { DrumKind } from "../../store/project";
+
+// generate_chartが返すイベント（positionはナノ秒の文字列）
+interface GeneratedEvent {
+  type: "SingleNote";
+  uuid: string;
+  position: string;
+  lane: number;
+}
+
+// generate_chartの戻り値
+interface GeneratedChart {
+  uuid: string;
+  events: GeneratedEvent[];
+  laneNumber: number;
+  label: string;
+  level: number;
+  usage: LlmUsage;
+  // 有効な譜面を生成できなかった小節番号（1始まり）
+  failedBars: number[];
+}
+
+// LLMの使用量（トークン数はプロバイダーが報告しない場合はnull）
+interface LlmUsage {
+  promptTokens: number | null;
+  completionTokens: number | null;
+  latencyMs: number;
+}
+
+const formatUsage = (usage: LlmUsage) => {
+  const tokens = usage.promptTokens !== null || usage.completionTokens !== null ?
+    `入力${usage.promptTokens ?? "?"}・出力${usage.completionTokens ?? "?"}トークン、` :
+    "";
+  return `${tokens}${(usage.latencyMs / 1000).toFixed(1)}秒`;
+};
+
+// generate_chart_progressイベントの内容
+interface GenerateChartProgress {
+  startBar: number;
+  endBar: number;
+  processedBars: number;
+  totalBars: number;
+}
+
+// generate_chart_barイベントの内容（検証を通った1小節分）
+interface GenerateChartBar {
+  bar: number;
+  events: GeneratedEvent[];
+}
+
+// ollama_pull_progressイベントの内容
+interface OllamaPullProgress {
+  model: string;
+  status: string;
+  total?: number;
+  completed?: number;
+}
+
+// Ollamaにモデルがない場合のエラーの先頭（Rust側のollama::MODEL_NOT_FOUND）
+const OLLAMA_MODEL_NOT_FOUND = "Ollama model not found";
+
+// プレビューに表示するLLMの応答の末尾の文字数
+const STREAM_PREVIEW_LENGTH = 200;
+
+// LLMの応答キャッシュの使い方（Rust側のCacheMode）
+type CacheMode = "off" | "record" | "cached" | "replay";
+
+const cacheModes: { value: CacheMode; label: string; description: string }[] = [
+  { value: "off", label: "使わない", description: "応答を保存しません" },
+  { value: "record", label: "記録", description: "毎回AIに問い合わせ、応答を保存します（設定画面で削除できます）" },
+  { value: "cached", label: "再利用", description: "同じ条件の応答が保存されていれば、AIに問い合わせずに使います" },
+  { value: "replay", label: "リプレイ", description: "保存された応答のみを使います（ない小節はエラーになります）" },
+];
+
+const toSingleNoteEvent = (e: GeneratedEvent) =>
+  new SingleNoteEvent(e.uuid, TemporalPosition.fromJSON(e.position), e.lane as Lane);
+
+// recommend_modelsの戻り値
+interface ModelRecommendations {
+  hardware: {
+    gpus: { vendor: string; name: string; vramGb?: number | null }[];
+    ramGb: number;
+    cpuCores: number;
+    unifiedMemory: boolean;
+  };
+  models: {
+    name: string;
+    label: string;
+    description: string;
+    sizeGb: number;
+    device: "gpu" | "partial" | "cpu";
+    tokensPerSecond: number;
+    speed: "fast" | "moderate" | "slow";
+  }[];
+}
+
+const speedLabels = {
+  fast: { text: "高速", color: "green" },
+  moderate: { text: "普通", color: "orange" },
+  slow: { text: "低速", color: "red" },
+};
+
+const deviceLabels = { gpu: "GPU", partial: "GPU+CPU", cpu: "CPU" };
 
 export interface GenerateNewChartDialogRef {
   generateNewChart: () => void;
@@ -20,9 +123,9 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
   const snap = useSnapshot(store);
   const [showNewChartConfirmDialog, setShowNewChartConfirmDialog] = useState(false);
   const [isNewChartGenerating, setIsNewChartGenerating] = useState(false);
-  const [vramGb, setVramGb] = useState<number | null>(null);
-  const [vramError, setVramError] = useState<string | null>(null);
-  const [isVramLoading, setIsVramLoading] = useState(false);
+  const [recommendations, setRecommendations] = useState<ModelRecommendations | null>(null);
+  const [hardwareError, setHardwareError] = useState<string | null>(null);
+  const [isHardwareLoading, setIsHardwareLoading] = useState(false);
   const [isOllamaInstalled, setIsOllamaInstalled] = useState<boolean | null>(null);
   const [isOllamaLoading, setIsOllamaLoading] = useState(false);
   const [keyCount, setKeyCount] = useState(12);
@@ -34,10 +137,13 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
   const [customModel, setCustomModel] = useState("");
   const [useCustomModel, setUseCustomModel] = useState(false);
   const [barsPerBatch, setBarsPerBatch] = useState(1);
+  const [seed, setSeed] = useState("");
+  const [cacheMode, setCacheMode] = useState<CacheMode>("off");
   const [googleAiApiKey, setGoogleAiApiKey] = useState("");
+  const [hasGoogleAiApiKey, setHasGoogleAiApiKey] = useState(false);
   
-  // 利用可能なOllamaモデルのリスト
-  const availableOllamaModels = [
+  // ハードウェアを調べられなかった場合のOllamaモデルのリスト
+  const defaultOllamaModels = [
     { value: "phi4:14b", label: "Phi-4 14B (推奨)", description: "バランスの良い高性能モデル" },
     { value: "llama3.2:3b", label: "Llama 3.2 3B", description: "軽量で高速" },
     { value: "llama3.2:1b", label: "Llama 3.2 1B", description: "最軽量" },
@@ -62,10 +168,39 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
     }
   };
   
+  // 設定中のプロバイダーをchatコマンド用の形式にする
+  const getChatProvider = () => {
+    const provider = snap.userSettings.aiProvider || 'ollama';
+    // APIキーはバックエンドが保存済みのものを使う
+    if (provider === 'google-ai-studio') {
+      if (!hasGoogleAiApiKey) {
+        throw new Error("Google AI Studio APIキーが設定されていません。設定画面で設定してください。");
+      }
+      return { type: "google", model: getActualModelName() };
+    } else if (provider === 'openai-compatible') {
+      const baseUrl = snap.userSettings.openAiBaseUrl.trim();
+      const model = snap.userSettings.openAiModel.trim();
+      if (!baseUrl || !model) {
+        throw new Error("OpenAI互換APIのベースURLとモデル名が設定されていません。設定画面で設定してください。");
+      }
+      return {
+        type: "openAiCompatible",
+        baseUrl,
+        model
+      };
+    } else {
+      return { type: "ollama", model: getActualModelName() };
+    }
+  };
+
   // プログレス関連のstate
   const [currentBar, setCurrentBar] = useState(0);
   const [totalBars, setTotalBars] = useState(0);
   const [progressMessage, setProgressMessage] = useState("");
+  const [streamText, setStreamText] = useState("");
+  const [isCancelling, setIsCancelling] = useState(false);
+  // 生成前に取得中のOllamaモデル（中止用）
+  const pullingModelRef = useRef<string | null>(null);
 
   // ピアノの白鍵・黒鍵配置を生成する関数
   const generatePianoLayout = (count: number): ('white' | 'black')[] => {
@@ -80,17 +215,21 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
     return layout;
   };
 
-  // VRAMを取得する関数
-  const checkVram = async () => {
-    setIsVramLoading(true);
-    setVramError(null);
+  // ハードウェアを調べ、実行できるモデルを取得する関数
+  const checkHardware = async () => {
+    setIsHardwareLoading(true);
+    setHardwareError(null);
     try {
-      const vram = await invoke<number>("get_vram");
-      setVramGb(vram);
+      const result = await invoke<ModelRecommendations>("recommend_models");
+      setRecommendations(result);
+      // 選択中のモデルが実行できない場合は一番のおすすめに切り替える
+      if (result.models.length > 0 && !result.models.some((m) => m.name === selectedModel)) {
+        setSelectedModel(result.models[0].name);
+      }
     } catch (error) {
-      setVramError(error as string);
+      setHardwareError(error as string);
     } finally {
-      setIsVramLoading(false);
+      setIsHardwareLoading(false);
     }
   };
 
@@ -132,18 +271,36 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
     setKeyTypes(newKeyTypes);
   };
 
+  // 保存済みのGoogle AI Studio APIキーがあるか確認する
+  const checkGoogleAiApiKey = async () => {
+    try {
+      setHasGoogleAiApiKey(await invoke<boolean>("has_api_key", { providerId: "google" }));
+    } catch (error) {
+      console.error("Failed to check API key:", error);
+    }
+  };
+
+  // APIキーをバックエンドに保存する
+  const saveGoogleAiApiKey = async () => {
+    try {
+      await invoke("set_api_key", { providerId: "google", apiKey: googleAiApiKey });
+      setGoogleAiApiKey("");
+      await checkGoogleAiApiKey();
+      toaster.create({ title: "APIキーを保存しました", type: "success" });
+    } catch (error) {
+      toaster.create({ title: "APIキーの保存に失敗しました", description: String(error), type: "error" });
+    }
+  };
+
   // 初期化
   useEffect(() => {
-    // ユーザー設定からAPIキーを読み込む
-    setGoogleAiApiKey(snap.userSettings.googleAiApiKey || "");
-    
     // 初期モデル選択を設定
     if (snap.userSettings.aiProvider === 'google-ai-studio') {
       setSelectedModel("gemini-2.0-flash-exp");
     } else {
       setSelectedModel("phi4:14b");
     }
-  }, [snap.userSettings.aiProvider, snap.userSettings.googleAiApiKey]);
+  }, [snap.userSettings.aiProvider]);
 
   // 鍵盤タイプの初期化
   useEffect(() => {
@@ -167,30 +324,39 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
     setEnabledKeys(newEnabledKeys);
   };
 
-  // ダイアログが開かれるたびにVRAMとOllamaをチェック（Ollamaタブのみ）
+  // ダイアログが開かれるたびにハードウェアとOllamaをチェック（Ollamaタブのみ）
   useEffect(() => {
     if (showNewChartConfirmDialog && (snap.userSettings.aiProvider || 'ollama') === 'ollama') {
-      checkVram();
+      checkHardware();
       checkOllama();
     }
+    if (showNewChartConfirmDialog && snap.userSettings.aiProvider === 'google-ai-studio') {
+      checkGoogleAiApiKey();
+    }
   }, [showNewChartConfirmDialog, snap.userSettings.aiProvider]);
 
-  // VRAMステータスを判定する関数
-  const getVramStatus = () => {
-    if (vramGb === null) return { canExecute: true, color: "gray", message: "VRAM情報を取得中..." };
-    
-    if (vramGb <= 7) {
-      return { canExecute: false, color: "red", message: `使用不可 (${vramGb.toFixed(1)}GB)` };
-    } else if (vramGb < 15) {
-      return { canExecute: true, color: "orange", message: `使用可能だが推奨環境未満 (${vramGb.toFixed(1)}GB)` };
-    } else {
-      return { canExecute: true, color: "green", message: `推奨環境 (${vramGb.toFixed(1)}GB)` };
-    }
+  // ハードウェアの概要
+  const getHardwareSummary = () => {
+    if (!recommendations) return "";
+    const { gpus, ramGb, cpuCores, unifiedMemory } = recommendations.hardware;
+    const gpuText = gpus.length > 0 ?
+      gpus.map((gpu) => gpu.vramGb ? `${gpu.name} (${gpu.vramGb.toFixed(1)}GB)` : gpu.name).join(", ") :
+      "GPUなし";
+    const memoryText = `${unifiedMemory ? "ユニファイドメモリ" : "メモリ"} ${ramGb.toFixed(1)}GB`;
+    return `${gpuText} / ${memoryText} / ${cpuCores}コア`;
   };
 
-  const vramStatus = getVramStatus();
+  // 表示するOllamaモデル（ハードウェアを調べられた場合はおすすめ順）
+  const ollamaModels = recommendations ?
+    recommendations.models.map((m, i) => ({
+      value: m.name,
+      label: i === 0 ? `${m.label} (推奨)` : m.label,
+      description: `${m.description}・${deviceLabels[m.device]}で約${Math.round(m.tokensPerSecond)}トークン/秒`,
+      speed: m.speed,
+    })) :
+    defaultOllamaModels.map((m) => ({ ...m, speed: undefined }));
 
-  // 譜面生成を実行する関数
+  // 譜面生成を実行する関数（プロンプト作成・応答の検証・時間への変換はRust側で行う）
   const generate = async (
     keyCount: number,
     enabledKeys: boolean[],
@@ -198,10 +364,10 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
     keyTypes: ('white' | 'black')[],
     customInstructions: string,
     stemNotes: {
-      readonly bass: readonly { readonly pitch: number; readonly velocity: number; readonly time: number }[],
-      readonly drums: readonly { readonly pitch: number; readonly velocity: number; readonly time: number }[],
+      readonly bass: readonly { readonly pitch: number; readonly velocity: number; readonly time: number; readonly end?: number }[],
+      readonly drums: readonly { readonly pitch: number; readonly velocity: number; readonly time: number; readonly kind?: DrumKind }[],
       readonly other: readonly { readonly pitch: number; readonly velocity: number; readonly time: number }[],
-      readonly vocals: readonly { readonly pitch: number; readonly velocity: number; readonly time: number }[],
+      readonly vocals: readonly { readonly pitch: number; readonly velocity: number; readonly time: number; readonly end?: number }[],
     }
   ) => {
     console.log("譜面生成開始:", {
@@ -213,738 +379,144 @@ const GenerateNewChartDialog = forwardRef<GenerateNewChartDialogRef>((_props, re
       stemNotes
     });
 
+    // プログレス初期化
+    setTotalBars(0);
+    setCurrentBar(0);
+    setProgressMessage("譜面生成を開始しています...");
+    setStreamText("");
+
+    // 生成された小節から順に表示するプレビュー用の譜面
+    const label = `自動生成${new Date().toLocaleString('ja-JP')}`;
+    const previewUuid = crypto.randomUUID();
+    store.project.charts.push(new Chart(previewUuid, [], keyCount, `${label}（生成中）`));
+    const previewIndex = () => store.project.charts.findIndex((c) => c.uuid === previewUuid);
+
+    let generatedTotalBars = 0;
+    const unlistenBar = await listen<GenerateChartBar>("generate_chart_bar", (event) => {
+      const preview = store.project.charts[previewIndex()];
+      preview?.events.push(...event.payload.events.map(toSingleNoteEvent));
+    });
+    const unlistenToken = await listen<{ text: string }>("generate_chart_token", (event) => {
+      setStreamText((text) => (text + event.payload.text).slice(-STREAM_PREVIEW_LENGTH));
+    });
+    const unlisten = await listen<GenerateChartProgress>("generate_chart_progress", (event) => {
+      const { startBar, endBar, processedBars, totalBars } = event.payload;
+      generatedTotalBars = totalBars;
+      setTotalBars(totalBars);
+      setCurrentBar(processedBars);
+      setProgressMessage(startBar === endBar ?
+        `小節 ${startBar} を処理中...` :
+        `小節 ${startBar}-${endBar} を処理中...`);
+    });
+
     try {
-      
-      // stemNotesを[(base|drums|other|vocals), pitch, velocity, time]の形式に変換
-      const formattedStemNotes = [
-        ...stemNotes.bass.map(note => ['bass', note.pitch, note.velocity, note.time]),
-        ...stemNotes.drums.map(note => ['drums', note.pitch, note.velocity, note.time]),
-        ...stemNotes.other.map(note => ['other', note.pitch, note.velocity, note.time]),
-        ...stemNotes.vocals.map(note => ['vocals', note.pitch, note.velocity, note.time]),
-      ];
-
-      // プロジェクトのテンポ情報を取得
-      const tempoEvents = snap.project.musicTempoList;
-      
-      // 各小節の時間範囲を計算
-      interface BarInfo {
-        barIndex: number;
-        startTime: number; // 秒
-        endTime: number;   // 秒
-        tempo: number;
-        beat: number;
-        barDuration: number; // 1小節の長さ（秒）
-      }
-      
-      const bars: BarInfo[] = [];
-      let currentTime = 0;
-      let barIndex = 0;
-      
-      for (const tempoEvent of tempoEvents) {
-        const barDuration = (60 / tempoEvent.tempo) * tempoEvent.beat; // 1小節の秒数
-        
-        for (let i = 0; i < tempoEvent.length; i++) {
-          bars.push({
-            barIndex: barIndex,
-            startTime: currentTime,
-            endTime: currentTime + barDuration,
-            tempo: tempoEvent.tempo,
-            beat: tempoEvent.beat,
-            barDuration: barDuration
-          });
-          currentTime += barDuration;
-          barIndex++;
-        }
-      }
-      
-      // クオンタイズ関数（16分音符を最小単位とする）
-      const quantizeBeatPosition = (beatPosition: number): number => {
-        // 16分音符を最小単位とする（1拍を4分割）
-        const quantizeUnit = 0.25; // 16分音符
-        return Math.round(beatPosition / quantizeUnit) * quantizeUnit;
-      };
-      
-      // formattedStemNotesを小節ごとに分割し、timeを小節内の拍位置に変換
-      interface ProcessedNote {
-        instrument: string;
-        pitch: number;
-        velocity: number;
-        barIndex: number;
-        beatPosition: number; // 小節内の拍位置（クオンタイズ済み）
-        originalTime: number; // 元の時間（秒）
-      }
-      
-      const processedNotes: ProcessedNote[] = [];
-      
-      for (const note of formattedStemNotes) {
-        const [instrument, pitch, velocity, originalTime] = note as [string, number, number, number];
-        
-        // velocity が 0 のノートは除外
-        if (velocity === 0) {
-          continue;
-        }
-        
-        // このノートがどの小節に属するかを検索
-        const bar = bars.find(b => originalTime >= b.startTime && originalTime < b.endTime);
-        
-        if (bar) {
-          // 小節内での経過時間を計算
-          const timeInBar = originalTime - bar.startTime;
-          
-          // 小節内の拍位置を計算（1拍 = 60/tempo秒）
-          const beatDuration = 60 / bar.tempo; // 1拍の秒数
-          const rawBeatPosition = timeInBar / beatDuration;
-          
-          // クオンタイズを適用
-          const quantizedBeatPosition = quantizeBeatPosition(rawBeatPosition);
-          
-          // 小節の範囲内に収める（1拍目～beat拍目）
-          if (quantizedBeatPosition >= 1 && quantizedBeatPosition <= bar.beat) {
-            processedNotes.push({
-              instrument,
-              pitch,
-              velocity,
-              barIndex: bar.barIndex,
-              beatPosition: quantizedBeatPosition,
-              originalTime
-            });
-          }
-        }
-      }
-      
-      // 新しいデータ構造に変換
-      interface QuantizedNote {
-        beat: number;
-        pitch: number;
-        velocity: number;
-      }
-      
-      interface BarData {
-        barIndex: number;
-        notes: {
-          drums: QuantizedNote[];
-          bass: QuantizedNote[];
-          vocals: QuantizedNote[];
-          other: QuantizedNote[];
-        };
-      }
-      
-      const quantizedBarData: BarData[] = [];
-      
-      // 小節ごとにグループ化
-      const notesByBar = new Map<number, ProcessedNote[]>();
-      for (const note of processedNotes) {
-        if (!notesByBar.has(note.barIndex)) {
-          notesByBar.set(note.barIndex, []);
-        }
-        notesByBar.get(note.barIndex)!.push(note);
-      }
-      
-      // 各小節のデータを新しい構造に変換
-      for (const [barIndex, notes] of notesByBar.entries()) {
-        const barData: BarData = {
-          barIndex,
-          notes: {
-            drums: [],
-            bass: [],
-            vocals: [],
-            other: []
-          }
-        };
-        
-        // 楽器別にノートを分類
-        for (const note of notes) {
-          const quantizedNote: QuantizedNote = {
-            beat: note.beatPosition,
-            pitch: note.pitch,
-            velocity: note.velocity
-          };
-          
-          switch (note.instrument) {
-            case 'drums':
-              barData.notes.drums.push(quantizedNote);
-              break;
-            case 'bass':
-              barData.notes.bass.push(quantizedNote);
-              break;
-            case 'vocals':
-              barData.notes.vocals.push(quantizedNote);
-              break;
-            case 'other':
-              barData.notes.other.push(quantizedNote);
-              break;
-          }
+      const generated = await invoke<GeneratedChart>("generate_chart", {
+        provider: getChatProvider(),
+        musicTempoList: snap.project.musicTempoList,
+        stemNotes,
+        options: {
+          keyCount,
+          enabledKeys,
+          allowSimultaneousWhiteBlack,
+          keyTypes,
+          customInstructions,
+          barsPerBatch,
+          label
+        },
+        chatOptions: {
+          seed: seed.trim() ? Number(seed) : null,
+          cache: cacheMode
         }
-        
-        // 各楽器のノートを拍位置でソート
-        barData.notes.drums.sort((a, b) => a.beat - b.beat);
-        barData.notes.bass.sort((a, b) => a.beat - b.beat);
-        barData.notes.vocals.sort((a, b) => a.beat - b.beat);
-        barData.notes.other.sort((a, b) => a.beat - b.beat);
-        
-        quantizedBarData.push(barData);
-      }
-      
-      // 小節番号でソート
-      quantizedBarData.sort((a, b) => a.barIndex - b.barIndex);
-      
-      // デバッグ用ログ
-      console.log("テンポ情報:", tempoEvents);
-      console.log("小節情報:", bars);
-      console.log("処理済みノート数（velocity > 0）:", processedNotes.length);
-      console.log("クオンタイズ済み小節データ:", quantizedBarData);
-      
-      // サンプル表示（最初の3小節）
-      console.log("サンプル小節データ（最初の3小節）:", 
-        quantizedBarData.slice(0, 3).map(bar => ({
-          barIndex: bar.barIndex,
-          totalNotes: Object.values(bar.notes).reduce((sum, notes) => sum + notes.length, 0),
-          notesByInstrument: {
-            drums: bar.notes.drums.length,
-            bass: bar.notes.bass.length,
-            vocals: bar.notes.vocals.length,
-            other: bar.notes.other.length
-          }
-        }))
-      );
-      
-      // LLMプロンプトを作成する関数（単一小節）
-      const createPromptForBar = (
-        barData: BarData, 
-        barInfo: BarInfo,
-        keyCount: number,
-        enabledKeys: boolean[],
-        allowSimultaneousWhiteBlack: boolean,
-        keyTypes: ('white' | 'black')[],
-        customInstructions: string
-      ): string => {
-        // 有効な鍵盤の情報を作成
-        const enabledKeyInfo = enabledKeys
-          .map((enabled, index) => enabled ? `Key${index + 1}(${keyTypes[index]})` : null)
-          .filter(Boolean)
-          .join(', ');
-        
-        const stemNotesText = JSON.stringify(barData.notes, null, 2);
-        
-        return `You are a professional rhythm game chart designer. Create a chart for bar ${barData.barIndex + 1}.
-
-CONFIGURATION:
-- Total keys: ${keyCount}
-- Available keys: ${enabledKeyInfo}
-- White/Black key simultaneous press: ${allowSimultaneousWhiteBlack ? 'Allowed' : 'Not allowed'}
-- Time signature: ${barInfo.beat}/4
-- Tempo: ${barInfo.tempo} BPM
-
-STEM AUDIO DATA:
-${stemNotesText}
-
-RULES:
-1. Output ONLY a valid JSON array of notes in this exact format:
-   [{"key": 1, "beat": 1.0}, {"key": 3, "beat": 1.5}]
-2. Key numbers range from 1 to ${keyCount}
-3. Beat positions must be quantized to 16th notes (0.25 increments): 1.0, 1.25, 1.5, 1.75, 2.0, etc.
-4. Beat positions must be within 1.0 to ${barInfo.beat}.0
-5. Only use enabled keys: ${enabledKeys.map((enabled, i) => enabled ? i + 1 : null).filter(Boolean).join(', ')}
-${!allowSimultaneousWhiteBlack ? `6. Do not place white and black keys simultaneously at the same beat position` : ''}
-7. Multiple notes can have the same beat position if they use different keys
-8. Ensure all JSON syntax is correct (proper commas, brackets, quotes)
-
-DESIGN PHILOSOPHY:
-- Follow the rhythm and intensity of the stem audio data
-- Place notes where drums, bass, vocals, or other instruments have strong beats
-- Create engaging patterns that match the musical flow
-- Balance difficulty appropriately
-
-${customInstructions ? `CUSTOM INSTRUCTIONS:
-${customInstructions}` : ''}
-
-IMPORTANT: Output ONLY the JSON array, no markdown formatting, no code blocks, no other text:`;
-      };
+      });
 
-      // LLMプロンプトを作成する関数（複数小節バッチ）
-      const createPromptForBarBatch = (
-        barDataList: BarData[],
-        barInfoList: BarInfo[],
-        keyCount: number,
-        enabledKeys: boolean[],
-        allowSimultaneousWhiteBlack: boolean,
-        keyTypes: ('white' | 'black')[],
-        customInstructions: string
-      ): string => {
-        // 有効な鍵盤の情報を作成
-        const enabledKeyInfo = enabledKeys
-          .map((enabled, index) => enabled ? `Key${index + 1}(${keyTypes[index]})` : null)
-          .filter(Boolean)
-          .join(', ');
-        
-        const barsText = barDataList.map((barData, index) => {
-          const barInfo = barInfoList[index];
-          const stemNotesText = JSON.stringify(barData.notes, null, 2);
-          return `Bar ${barData.barIndex + 1} (${barInfo.beat}/4, ${barInfo.tempo} BPM):\n${stemNotesText}`;
-        }).join('\n\n');
-        
-        const barNumbers = barDataList.map(b => b.barIndex + 1).join(', ');
-        
-        return `You are a professional rhythm game chart designer. Create charts for bars ${barNumbers}.
-
-CONFIGURATION:
-- Total keys: ${keyCount}
-- Available keys: ${enabledKeyInfo}
-- White/Black key simultaneous press: ${allowSimultaneousWhiteBlack ? 'Allowed' : 'Not allowed'}
-
-STEM AUDIO DATA:
-${barsText}
-
-RULES:
-1. Output ONLY a valid JSON object with bar numbers as keys, each containing an array of notes.
-2. Format: {"1": [{"key": 1, "beat": 1.0}, {"key": 3, "beat": 1.5}], "2": [{"key": 2, "beat": 1.0}]}
-3. Key numbers range from 1 to ${keyCount}
-4. Beat positions must be quantized to 16th notes (0.25 increments): 1.0, 1.25, 1.5, 1.75, 2.0, etc.
-5. Beat positions must be within 1.0 to the respective bar's beat count
-6. Only use enabled keys: ${enabledKeys.map((enabled, i) => enabled ? i + 1 : null).filter(Boolean).join(', ')}
-${!allowSimultaneousWhiteBlack ? `7. Do not place white and black keys simultaneously at the same beat position` : ''}
-8. Multiple notes can have the same beat position if they use different keys
-9. Ensure all JSON syntax is correct (proper commas, brackets, quotes)
-
-DESIGN PHILOSOPHY:
-- Follow the rhythm and intensity of the stem audio data
-- Place notes where drums, bass, vocals, or other instruments have strong beats
-- Create engaging patterns that match the musical flow
-- Balance difficulty appropriately
-- Consider musical continuity between bars
-
-${customInstructions ? `CUSTOM INSTRUCTIONS:
-${customInstructions}` : ''}
-
-IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks, no other text:`;
-      };
-      
-      // LLMからの出力をパースする関数
-      const parseAIResponse = (response: string): { key: number; beat: number }[] => {
-        try {
-          // Markdownのコードブロックを除去
-          let cleanedResponse = response;
-          
-          // ```json と ``` を削除
-          cleanedResponse = cleanedResponse.replace(/```json\s*/g, '');
-          cleanedResponse = cleanedResponse.replace(/```\s*/g, '');
-          
-          // レスポンスからJSONを抽出
-          const jsonMatch = cleanedResponse.match(/\[[\s\S]*?\]/);
-          if (!jsonMatch) {
-            console.warn("No JSON array found in AI response:", response);
-            return [];
-          }
-          
-          console.log("Attempting to parse JSON:", jsonMatch[0]);
-          const parsed = JSON.parse(jsonMatch[0]);
-          if (!Array.isArray(parsed)) {
-            console.warn("AI response is not an array:", parsed);
-            return [];
-          }
-          
-          // バリデーション
-          const validNotes = parsed.filter(note => {
-            if (typeof note.key !== 'number' || typeof note.beat !== 'number') {
-              console.warn("Invalid note format:", note);
-              return false;
-            }
-            if (note.key < 1 || note.key > keyCount) {
-              console.warn("Invalid key number:", note.key);
-              return false;
-            }
-            if (!enabledKeys[note.key - 1]) {
-              console.warn("Key not enabled:", note.key);
-              return false;
-            }
-            return true;
-          });
-          
-          console.log(`Parsed ${validNotes.length} valid notes from ${parsed.length} total notes`);
-          return validNotes;
-        } catch (error) {
-          console.error("Failed to parse AI response:", error);
-          console.error("Original response:", response);
-          return [];
-        }
-      };
-      
-      // バッチ応答のパース関数
-      const parseAIBatchResponse = (response: string, barDataList: BarData[]): Map<number, { key: number; beat: number }[]> => {
-        const result = new Map<number, { key: number; beat: number }[]>();
-        
-        try {
-          // Markdownのコードブロックを除去
-          let cleanedResponse = response.trim();
-          
-          // ```json と ``` を削除
-          cleanedResponse = cleanedResponse.replace(/```json\s*/g, '');
-          cleanedResponse = cleanedResponse.replace(/```\s*/g, '');
-          cleanedResponse = cleanedResponse.trim();
-          
-          console.log("Cleaned response for parsing:", cleanedResponse);
-          
-          // レスポンスからJSONを抽出（ネストしたオブジェクト対応）
-          let jsonString = '';
-          
-          // まず、完全なJSONオブジェクトを見つけるために括弧をカウント
-          const startIndex = cleanedResponse.indexOf('{');
-          if (startIndex === -1) {
-            console.error("No JSON object found in AI response:", response);
-            return result;
-          }
-          
-          let braceCount = 0;
-          let endIndex = -1;
-          
-          for (let i = startIndex; i < cleanedResponse.length; i++) {
-            const char = cleanedResponse[i];
-            if (char === '{') {
-              braceCount++;
-            } else if (char === '}') {
-              braceCount--;
-              if (braceCount === 0) {
-                endIndex = i;
-                break;
-              }
-            }
-          }
-          
-          if (endIndex === -1) {
-            console.error("Incomplete JSON object in AI response:", response);
-            return result;
-          }
-          
-          jsonString = cleanedResponse.substring(startIndex, endIndex + 1);
-          console.log("Extracted JSON string:", jsonString);
-          
-          // JSON文字列を段階的にクリーンアップ
-          // 不正な文字を除去
-          jsonString = jsonString.replace(/[\u0000-\u001F\u007F-\u009F]/g, '');
-          
-          // 末尾のカンマを除去
-          jsonString = jsonString.replace(/,(\s*[}\]])/g, '$1');
-          
-          console.log("Attempting to parse cleaned JSON:", jsonString);
-          
-          const parsed = JSON.parse(jsonString);
-          if (typeof parsed !== 'object' || parsed === null) {
-            console.warn("AI response is not an object:", parsed);
-            return result;
-          }
-          
-          console.log("Successfully parsed JSON:", parsed);
-          console.log("Available keys in parsed JSON:", Object.keys(parsed));
-          
-          // 各小節のデータを処理
-          for (const barData of barDataList) {
-            const barKey = (barData.barIndex + 1).toString();
-            console.log(`Looking for bar key: "${barKey}" in parsed data`);
-            const barNotes = parsed[barKey];
-            
-            if (!Array.isArray(barNotes)) {
-              console.warn(`No notes found for bar ${barData.barIndex + 1}, found:`, barNotes);
-              console.warn(`Available keys:`, Object.keys(parsed));
-              result.set(barData.barIndex, []);
-              continue;
-            }
-            
-            console.log(`Processing ${barNotes.length} notes for bar ${barData.barIndex + 1}:`, barNotes);
-            
-            // バリデーション
-            const validNotes = barNotes.filter((note, index) => {
-              if (typeof note !== 'object' || note === null) {
-                console.warn(`Note ${index} is not an object:`, note);
-                return false;
-              }
-              if (typeof note.key !== 'number' || typeof note.beat !== 'number') {
-                console.warn(`Invalid note format at index ${index}:`, note);
-                return false;
-              }
-              if (note.key < 1 || note.key > keyCount) {
-                console.warn(`Invalid key number at index ${index}:`, note.key);
-                return false;
-              }
-              if (!enabledKeys[note.key - 1]) {
-                console.warn(`Key not enabled at index ${index}:`, note.key);
-                return false;
-              }
-              return true;
-            });
-            
-            result.set(barData.barIndex, validNotes);
-            console.log(`Parsed ${validNotes.length} valid notes for bar ${barData.barIndex + 1}`);
-          }
-          
-          return result;
-        } catch (error) {
-          console.error("Failed to parse AI batch response:", error);
-          console.error("Original response:", response);
-          
-          // フォールバック：各小節を個別に空配列として設定
-          for (const barData of barDataList) {
-            result.set(barData.barIndex, []);
-          }
-          
-          return result;
-        }
-      };
-      
-      // 譜面ノート（最終的な結果）
-      interface ChartNote {
-        key: number;
-        time: number; // 秒単位の時間
-        barIndex: number;
-        beat: number;
-      }
-      
-      const finalChartNotes: ChartNote[] = [];
-      
-      // 小節をバッチで処理
-      let processedBars = 0;
-      const totalBarsCount = quantizedBarData.length;
-      
-      // プログレス初期化
-      setTotalBars(totalBarsCount);
-      setCurrentBar(0);
-      setProgressMessage("譜面生成を開始しています...");
-      
-      // バッチサイズで小節を分割して処理
-      for (let i = 0; i < quantizedBarData.length; i += barsPerBatch) {
-        const batchBarData = quantizedBarData.slice(i, i + barsPerBatch);
-        const batchBarInfo = batchBarData.map(barData => {
-          const barInfo = bars.find(b => b.barIndex === barData.barIndex);
-          if (!barInfo) {
-            console.warn(`Bar info not found for bar ${barData.barIndex}`);
-          }
-          return barInfo;
-        }).filter(Boolean) as BarInfo[];
-        
-        if (batchBarInfo.length !== batchBarData.length) {
-          console.warn("Some bar info missing, skipping batch");
-          processedBars += batchBarData.length;
-          continue;
-        }
-        
-        try {
-          // プログレス更新
-          const batchStart = batchBarData[0].barIndex + 1;
-          const batchEnd = batchBarData[batchBarData.length - 1].barIndex + 1;
-          setCurrentBar(Math.min(processedBars + batchBarData.length, totalBarsCount));
-          setProgressMessage(batchBarData.length === 1 ? 
-            `小節 ${batchStart} を処理中...` : 
-            `小節 ${batchStart}-${batchEnd} を処理中...`);
-          
-          let aiResponse: string;
-          
-          if (batchBarData.length === 1) {
-            // 単一小節の場合は既存の関数を使用
-            const prompt = createPromptForBar(
-              batchBarData[0],
-              batchBarInfo[0],
-              keyCount,
-              enabledKeys,
-              allowSimultaneousWhiteBlack,
-              keyTypes,
-              customInstructions
-            );
-            
-            console.log(`Processing single bar ${batchStart}/${totalBarsCount}...`);
-            
-            // LLMを呼び出し
-            const actualModelName = getActualModelName();
-            const provider = snap.userSettings.aiProvider || 'ollama';
-            console.log(`Using provider: ${provider}, model: ${actualModelName}`);
-            
-            if (provider === 'google-ai-studio') {
-              const apiKey = snap.userSettings.googleAiApiKey;
-              if (!apiKey) {
-                throw new Error("Google AI Studio APIキーが設定されていません。設定画面で設定してください。");
-              }
-              aiResponse = await invoke<string>("call_google_ai", {
-                modelName: actualModelName,
-                query: prompt,
-                apiKey: apiKey
-              });
-            } else {
-              aiResponse = await invoke<string>("call_llm", {
-                modelName: actualModelName,
-                query: prompt
-              });
-            }
-            
-            console.log(`AI response for bar ${batchStart}:`, aiResponse);
-            
-            // AIの出力をパース
-            const barNotes = parseAIResponse(aiResponse);
-            
-            // 時間に変換して最終結果に追加
-            for (const note of barNotes) {
-              // 拍位置を時間に変換
-              const beatDuration = 60 / batchBarInfo[0].tempo; // 1拍の秒数
-              const timeInBar = (note.beat - 1) * beatDuration; // 小節内の時間（0基点）
-              const absoluteTime = batchBarInfo[0].startTime + timeInBar; // 絶対時間
-              
-              finalChartNotes.push({
-                key: note.key,
-                time: absoluteTime,
-                barIndex: batchBarData[0].barIndex,
-                beat: note.beat
-              });
-            }
-            
-            console.log(`Completed bar ${batchStart}, generated ${barNotes.length} notes`);
-            
-          } else {
-            // 複数小節の場合はバッチ関数を使用
-            const prompt = createPromptForBarBatch(
-              batchBarData,
-              batchBarInfo,
-              keyCount,
-              enabledKeys,
-              allowSimultaneousWhiteBlack,
-              keyTypes,
-              customInstructions
-            );
-            
-            console.log(`Processing batch bars ${batchStart}-${batchEnd}/${totalBarsCount}...`);
-            
-            // LLMを呼び出し
-            const actualModelName = getActualModelName();
-            const provider = snap.userSettings.aiProvider || 'ollama';
-            console.log(`Using provider: ${provider}, model: ${actualModelName}`);
-            
-            if (provider === 'google-ai-studio') {
-              const apiKey = snap.userSettings.googleAiApiKey;
-              if (!apiKey) {
-                throw new Error("Google AI Studio APIキーが設定されていません。設定画面で設定してください。");
-              }
-              aiResponse = await invoke<string>("call_google_ai", {
-                modelName: actualModelName,
-                query: prompt,
-                apiKey: apiKey
-              });
-            } else {
-              aiResponse = await invoke<string>("call_llm", {
-                modelName: actualModelName,
-                query: prompt
-              });
-            }
-            
-            console.log(`AI response for bars ${batchStart}-${batchEnd}:`, aiResponse);
-            
-            // AIの出力をパース
-            const batchNotes = parseAIBatchResponse(aiResponse, batchBarData);
-            
-            // 時間に変換して最終結果に追加
-            for (const [barIndex, barNotes] of batchNotes.entries()) {
-              const barInfo = batchBarInfo.find(b => b.barIndex === barIndex);
-              if (!barInfo) continue;
-              
-              for (const note of barNotes) {
-                // 拍位置を時間に変換
-                const beatDuration = 60 / barInfo.tempo; // 1拍の秒数
-                const timeInBar = (note.beat - 1) * beatDuration; // 小節内の時間（0基点）
-                const absoluteTime = barInfo.startTime + timeInBar; // 絶対時間
-                
-                finalChartNotes.push({
-                  key: note.key,
-                  time: absoluteTime,
-                  barIndex: barIndex,
-                  beat: note.beat
-                });
-              }
-            }
-            
-            const totalNotesGenerated = Array.from(batchNotes.values()).reduce((sum, notes) => sum + notes.length, 0);
-            console.log(`Completed batch bars ${batchStart}-${batchEnd}, generated ${totalNotesGenerated} notes`);
-          }
-          
-          processedBars += batchBarData.length;
-          
-        } catch (error) {
-          const batchStartForError = batchBarData[0].barIndex + 1;
-          console.error(`Error processing batch starting at bar ${batchStartForError}:`, error);
-          // エラーがあっても処理を続行
-          processedBars += batchBarData.length;
-        }
-      }
-      
-      // 最終処理のプログレス更新
-      setProgressMessage("譜面データを統合しています...");
-      
-      // 時間順でソート
-      finalChartNotes.sort((a, b) => a.time - b.time);
-      
-      console.log("=== 譜面生成完了 ===");
-      console.log(`処理した小節数: ${processedBars}/${totalBarsCount}`);
-      console.log(`生成されたノート数: ${finalChartNotes.length}`);
-      console.log("最終的な譜面データ:", finalChartNotes);
-      
-      // 統計情報
-      const notesByKey = finalChartNotes.reduce((acc, note) => {
-        acc[note.key] = (acc[note.key] || 0) + 1;
-        return acc;
-      }, {} as Record<number, number>);
-      
-      console.log("鍵盤別ノート数:", notesByKey);
-      
-      // 完了プログレス更新
-      setProgressMessage("譜面生成が完了しました！");
-      
-      // finalChartNotesをもとにChartEvent[]を作り、Chartを作成、storeにpushする処理
+      setCurrentBar(generatedTotalBars);
       setProgressMessage("譜面をプロジェクトに追加しています...");
-      
-      // ChartEventの配列を作成
-      const chartEvents: ChartEvent[] = [];
-      
-      for (const note of finalChartNotes) {
-        // 時間をTemporalPositionに変換
-        const position = TemporalPosition.createWithSeconds(note.time);
-        
-        // 鍵盤番号をLane（0ベース）に変換
-        const lane: Lane = note.key - 1;
-        
-        // SingleNoteEventを作成
-        const noteEvent = new SingleNoteEvent(
-          crypto.randomUUID(),
-          position,
-          lane
-        );
-        
-        chartEvents.push(noteEvent);
-      }
-      
-      // 時間順でソート（念のため）
-      chartEvents.sort((a, b) => Number(a.position.nanoseconds - b.position.nanoseconds));
-      
-      // 新しいChartを作成
+
+      // 受け取った譜面をクラスに戻す
       const newChart = new Chart(
-        crypto.randomUUID(),
-        chartEvents,
-        keyCount, // laneNumber として鍵盤数を設定
-        `自動生成${new Date().toLocaleString('ja-JP')}`,
-        1 // デフォルト難易度
+        generated.uuid,
+        generated.events.map(toSingleNoteEvent),
+        generated.laneNumber,
+        generated.label,
+        generated.level
       );
-      
-      // storeのprojectのchartsに追加
-      store.project.charts.push(newChart);
-      
+
+      // プレビューを完成した譜面で置き換える
+      const index = previewIndex();
+      if (index >= 0) {
+        store.project.charts[index] = newChart;
+      } else {
+        store.project.charts.push(newChart);
+      }
+
       console.log("新しい譜面をプロジェクトに追加しました:", {
         chartId: newChart.uuid,
-        eventCount: chartEvents.length,
+        eventCount: newChart.events.length,
         laneCount: keyCount,
         label: newChart.label
       });
-      
 
-      toaster.create({
-        title: "譜面生成完了",
-        description: "新しい譜面が生成されました。",
-        type: "success"
-      });
+      if (generated.failedBars.length > 0) {
+        // 生成できなかった小節は空のまま残る
+        toaster.create({
+          title: "一部の小節を生成できませんでした",
+          description: `小節 ${generated.failedBars.join(", ")} は空のままです。（${formatUsage(generated.usage)}）`,
+          type: "warning"
+        });
+      } else {
+        toaster.create({
+          title: "譜面生成完了",
+          description: `新しい譜面が生成されました。（${formatUsage(generated.usage)}）`,
+          type: "success"
+        });
+      }
     } catch (error) {
+      // 失敗・中止した場合はプレビューを取り除く
+      const index = previewIndex();
+      if (index >= 0) {
+        store.project.charts.splice(index, 1);
+      }
       throw error;
+    } finally {
+      unlisten();
+      unlistenBar();
+      unlistenToken();
+    }
+  };
+
+  // Ollamaのモデルを取得する（進行状況はollama_pull_progressイベントで受け取る）
+  const pullOllamaModel = async (model: string) => {
+    setProgressMessage(`${model} をダウンロードしています...`);
+    const unlisten = await listen<OllamaPullProgress>("ollama_pull_progress", (event) => {
+      const { status, total, completed } = event.payload;
+      if (event.payload.model !== model) {
+        return;
+      }
+      setProgressMessage(total && completed !== undefined ?
+        `${model} をダウンロードしています... ${Math.round((completed / total) * 100)}%` :
+        `${model}: ${status}`);
+    });
+    pullingModelRef.current = model;
+    try {
+      await invoke("pull_ollama_model", { baseUrl: null, model });
+    } finally {
+      pullingModelRef.current = null;
+      unlisten();
+    }
+  };
+
+  // 実行中の生成を中止する（generate_chartはエラーで終わる）
+  const cancelGeneration = async () => {
+    setIsCancelling(true);
+    try {
+      if (pullingModelRef.current) {
+        await invoke<boolean>("cancel_ollama_pull", { model: pullingModelRef.current });
+        return;
+      }
+      await invoke<boolean>("cancel_chart_generation");
+    } catch (error) {
+      console.error("Failed to cancel chart generation:", error);
+      setIsCancelling(false);
     }
   };
   
@@ -956,13 +528,14 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
   const handleConfirmNewChartGeneration = async () => {
     setShowNewChartConfirmDialog(false);
     setIsNewChartGenerating(true);
+    setIsCancelling(false);
 
     try {
       // snapからstemNotesを取得
       const stemNotes = snap.project.stemNotes;
       
       // generate関数を呼び出し
-      await generate(
+      const run = () => generate(
         keyCount,
         enabledKeys,
         allowSimultaneousWhiteBlack,
@@ -970,13 +543,30 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
         customInstructions,
         stemNotes
       );
+      try {
+        await run();
+      } catch (error) {
+        if (!String(error).startsWith(OLLAMA_MODEL_NOT_FOUND)) {
+          throw error;
+        }
+        // Ollamaにモデルがなければ取得してから生成し直す
+        await pullOllamaModel(getActualModelName());
+        await run();
+      }
     } catch (error) {
-      toaster.create({
-        title: "譜面生成エラー",
-        description: "譜面の生成中にエラーが発生しました。",
-        type: "error"
-      });
-      console.error("New chart generation error:", error);
+      if (String(error).includes("cancelled")) {
+        toaster.create({
+          title: "譜面生成を中止しました",
+          type: "info"
+        });
+      } else {
+        toaster.create({
+          title: "譜面生成エラー",
+          description: "譜面の生成中にエラーが発生しました。",
+          type: "error"
+        });
+        console.error("New chart generation error:", error);
+      }
     } finally {
       setIsNewChartGenerating(false);
     }
@@ -1008,44 +598,45 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
               <Text fontSize="sm" color="blue.600">
                 初回実行時には、AIモデルのダウンロードのため約5GBの空き容量が必要です。
               </Text>
-            ) : (
+            ) : (snap.userSettings.aiProvider || 'ollama') === 'google-ai-studio' ? (
               <Text fontSize="sm" color="blue.600">
                 Google AI Studio APIキーが必要です。設定画面でAPIキーを入力してください。
               </Text>
+            ) : (
+              <Text fontSize="sm" color="blue.600">
+                OpenAI互換APIのベースURLとモデル名が必要です。設定画面で入力してください。
+              </Text>
             )}
           </Box>
           
           {/* Ollamaタブ固有の情報表示 */}
           {(snap.userSettings.aiProvider || 'ollama') === 'ollama' && (
             <>
-              {/* VRAM情報表示 */}
+              {/* ハードウェア情報表示 */}
               <Box mt={4} p={3} border="1px solid" borderColor="gray.200" borderRadius="md">
-                <Text fontSize="sm" fontWeight="bold" mb={2}>VRAM使用量チェック</Text>
-                {isVramLoading ? (
+                <Text fontSize="sm" fontWeight="bold" mb={2}>ハードウェアチェック</Text>
+                {isHardwareLoading ? (
                   <Box display="flex" alignItems="center" gap={2}>
                     <Spinner size="sm" />
-                    <Text fontSize="sm">VRAM情報を取得中...</Text>
+                    <Text fontSize="sm">ハードウェア情報を取得中...</Text>
                   </Box>
-                ) : vramError ? (
+                ) : hardwareError ? (
                   <Text fontSize="sm" color="orange">
-                    VRAM情報の取得に失敗しました。目安として使用してください。
-                  </Text>
-                ) : (
-                  <Text fontSize="sm" color={vramStatus.color}>
-                    {vramStatus.message}
-                  </Text>
-                )}
-                
-                {vramGb !== null && vramGb < 15 && (
-                  <Text fontSize="xs" color="gray.500" mt={1}>
-                    ※ 15GB未満の場合でも実行可能ですが、処理に時間がかかる可能性があります。
-                  </Text>
-                )}
-                
-                {vramGb !== null && vramGb <= 7 && (
-                  <Text fontSize="xs" color="red.500" mt={1}>
-                    ※ 7GB以下の環境では実行が制限されています。
+                    ハードウェア情報の取得に失敗しました。モデルは目安として選んでください。
                   </Text>
+                ) : recommendations && (
+                  <>
+                    <Text fontSize="sm">{getHardwareSummary()}</Text>
+                    {recommendations.models.length === 0 ? (
+                      <Text fontSize="xs" color="red.500" mt={1}>
+                        ※ この環境のメモリで実行できるモデルがありません。Google AI StudioかOpenAI互換APIを使用してください。
+                      </Text>
+                    ) : recommendations.models[0].speed === "slow" && (
+                      <Text fontSize="xs" color="gray.500" mt={1}>
+                        ※ 実行可能ですが、処理に時間がかかる可能性があります。
+                      </Text>
+                    )}
+                  </>
                 )}
               </Box>
 
@@ -1135,6 +726,17 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
                 >
                   Google AI Studio
                 </Button>
+                <Button
+                  size="sm"
+                  variant={(snap.userSettings.aiProvider || 'ollama') === 'openai-compatible' ? "solid" : "outline"}
+                  colorScheme={(snap.userSettings.aiProvider || 'ollama') === 'openai-compatible' ? "blue" : "gray"}
+                  onClick={() => {
+                    store.userSettings.setAiProvider('openai-compatible');
+                    store.userSettings.save();
+                  }}
+                >
+                  OpenAI互換
+                </Button>
               </HStack>
             </Box>
 
@@ -1145,7 +747,7 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
               {/* Ollamaの場合 */}
               {(snap.userSettings.aiProvider || 'ollama') === 'ollama' && (
                 <Box>
-                  {availableOllamaModels.map((model) => (
+                  {ollamaModels.map((model) => (
                     <Box key={model.value} mb={2}>
                       <Button
                         size="sm"
@@ -1162,7 +764,14 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
                         p={3}
                       >
                         <Box>
-                          <Text fontSize="sm" fontWeight="bold">{model.label}</Text>
+                          <Text fontSize="sm" fontWeight="bold">
+                            {model.label}
+                            {model.speed && (
+                              <Text as="span" ml={2} fontSize="xs" color={speedLabels[model.speed].color}>
+                                {speedLabels[model.speed].text}
+                              </Text>
+                            )}
+                          </Text>
                           <Text fontSize="xs" color={selectedModel === model.value && !useCustomModel ? "blue.100" : "gray.500"}>
                             {model.description}
                           </Text>
@@ -1207,23 +816,36 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
                 </Box>
               )}
 
+              {/* OpenAI互換APIの場合 */}
+              {(snap.userSettings.aiProvider || 'ollama') === 'openai-compatible' && (
+                <Box p={3} border="1px solid" borderColor="gray.200" borderRadius="md">
+                  <Text fontSize="sm">
+                    {snap.userSettings.openAiModel || "(モデル未設定)"} @ {snap.userSettings.openAiBaseUrl || "(URL未設定)"}
+                  </Text>
+                  <Text fontSize="xs" color="gray.600" mt={1}>
+                    ベースURL・モデル名・APIキーは設定画面のAI設定で変更できます
+                  </Text>
+                </Box>
+              )}
+
               {/* Google AI Studioの場合 */}
               {(snap.userSettings.aiProvider || 'ollama') === 'google-ai-studio' && (
                 <Box>
                   {/* APIキー設定 */}
                   <Box mb={3} p={3} border="1px solid" borderColor="blue.200" borderRadius="md" bg="blue.50">
                     <Text fontSize="sm" fontWeight="bold" mb={2}>APIキー設定</Text>
-                    <Input
-                      type="password"
-                      placeholder="Google AI Studio APIキーを入力"
-                      value={googleAiApiKey}
-                      onChange={(e) => {
-                        setGoogleAiApiKey(e.target.value);
-                        store.userSettings.setGoogleAiApiKey(e.target.value);
-                        store.userSettings.save();
-                      }}
-                      size="sm"
-                    />
+                    <HStack>
+                      <Input
+                        type="password"
+                        placeholder={hasGoogleAiApiKey ? "保存済み（変更する場合のみ入力）" : "Google AI Studio APIキーを入力"}
+                        value={googleAiApiKey}
+                        onChange={(e) => setGoogleAiApiKey(e.target.value)}
+                        size="sm"
+                      />
+                      <Button size="sm" onClick={saveGoogleAiApiKey} disabled={!googleAiApiKey.trim()}>
+                        保存
+                      </Button>
+                    </HStack>
                     <Text fontSize="xs" color="gray.600" mt={1}>
                       Google AI StudioでAPIキーを取得してください
                     </Text>
@@ -1292,6 +914,34 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
                 現在の設定: {barsPerBatch}小節ずつ処理
               </Text>
             </Box>
+
+            {/* 再現性の設定 */}
+            <Box mb={4}>
+              <Text fontSize="sm" mb={2}>シードと応答のキャッシュ</Text>
+              <Input
+                size="sm"
+                mb={2}
+                placeholder="シード（空欄なら指定しない）"
+                value={seed}
+                onChange={(e) => setSeed(e.target.value.replace(/[^0-9]/g, ""))}
+              />
+              <HStack mb={2} gap={2}>
+                {cacheModes.map((mode) => (
+                  <Button
+                    key={mode.value}
+                    size="sm"
+                    variant={cacheMode === mode.value ? "solid" : "outline"}
+                    colorScheme={cacheMode === mode.value ? "blue" : "gray"}
+                    onClick={() => setCacheMode(mode.value)}
+                  >
+                    {mode.label}
+                  </Button>
+                ))}
+              </HStack>
+              <Text fontSize="xs" color="gray.500">
+                {cacheModes.find((mode) => mode.value === cacheMode)?.description}
+              </Text>
+            </Box>
             
             {/* 鍵盤数設定 */}
             <Box mb={4}>
@@ -1423,7 +1073,7 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
           </Button>
           <Button 
             onClick={handleConfirmNewChartGeneration}
-            disabled={(snap.userSettings.aiProvider || 'ollama') === 'ollama' && vramGb !== null && vramGb <= 7}
+            disabled={(snap.userSettings.aiProvider || 'ollama') === 'ollama' && recommendations !== null && recommendations.models.length === 0}
           >
             OK
           </Button>
@@ -1486,13 +1136,36 @@ IMPORTANT: Output ONLY the JSON object, no markdown formatting, no code blocks,
                 <Text>{progressMessage || "譜面を自動生成しています。緑茶でも飲んでてください..."}</Text>
                 {totalBars > 0 && (
                   <Text fontSize="sm" color="gray.600" mt={1}>
-                    小節の処理には時間がかかる場合があります
+                    生成された小節から順に譜面に追加されます
                   </Text>
                 )}
               </Box>
             </Box>
+
+            {/* LLMの応答（末尾のみ） */}
+            {streamText && (
+              <Box
+                p={2}
+                bg="gray.50"
+                borderRadius="md"
+                fontFamily="mono"
+                fontSize="xs"
+                color="gray.600"
+                whiteSpace="pre-wrap"
+                wordBreak="break-all"
+                maxH="120px"
+                overflow="hidden"
+              >
+                {streamText}
+              </Box>
+            )}
           </Box>
         </DialogBody>
+        <DialogFooter>
+          <Button variant="outline" onClick={cancelGeneration} disabled={isCancelling}>
+            {isCancelling ? "中止しています..." : "中止"}
+          </Button>
+        </DialogFooter>
       </DialogContent>
     </DialogRoot>
   </>)
@@ -27,6 +27,8 @@ import store from "../store/store";
 import { toaster } from "../components/ui/toaster";
 import { useSnapshot } from "valtio";
 import { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import OllamaModels from "./SettingsMenu/OllamaModels";
 
 enum PlusMenuSelection {
   SetBackground = "set_background",
@@ -41,6 +43,13 @@ export default function SettingsMenu() {
   const snap = useSnapshot(store);
   const [showAiConfigDialog, setShowAiConfigDialog] = useState(false);
   const [tempGoogleApiKey, setTempGoogleApiKey] = useState("");
+  const [tempOpenAiBaseUrl, setTempOpenAiBaseUrl] = useState("");
+  const [tempOpenAiModel, setTempOpenAiModel] = useState("");
+  const [tempOpenAiApiKey, setTempOpenAiApiKey] = useState("");
+  // 保存済みのAPIキーがあるか（キー自体はバックエンドから返されない）
+  const [storedApiKeys, setStoredApiKeys] = useState({ google: false, openAiCompatible: false });
+  // 保存されているAIの応答（llm_cache_sizeの戻り値）
+  const [llmCacheSize, setLlmCacheSize] = useState<{ entries: number; bytes: number } | null>(null);
 
   const onSelect = (d: MenuSelectionDetails) => {
     const value = d.value;
@@ -111,9 +120,13 @@ export default function SettingsMenu() {
       });
     };
 
-    const ConfigureAi = () => {
-      setTempGoogleApiKey(snap.userSettings.googleAiApiKey || "");
+    const ConfigureAi = async () => {
+      setTempGoogleApiKey("");
+      setTempOpenAiBaseUrl(snap.userSettings.openAiBaseUrl || "");
+      setTempOpenAiModel(snap.userSettings.openAiModel || "");
+      setTempOpenAiApiKey("");
       setShowAiConfigDialog(true);
+      await Promise.all([loadStoredApiKeys(), loadLlmCacheSize()]);
     };
 
     switch (value) {
@@ -143,8 +156,83 @@ export default function SettingsMenu() {
     }
   };
 
+  const loadStoredApiKeys = async () => {
+    try {
+      const [google, openAiCompatible] = await Promise.all([
+        invoke<boolean>("has_api_key", { providerId: "google" }),
+        invoke<boolean>("has_api_key", { providerId: "openAiCompatible" }),
+      ]);
+      setStoredApiKeys({ google, openAiCompatible });
+    } catch (error) {
+      console.error("Failed to check stored API keys:", error);
+    }
+  };
+
+  const deleteApiKey = async (providerId: "google" | "openAiCompatible") => {
+    try {
+      await invoke("delete_api_key", { providerId });
+      await loadStoredApiKeys();
+      toaster.create({ title: "APIキーを削除しました", type: "info" });
+    } catch (error) {
+      toaster.create({
+        title: "APIキーの削除に失敗しました",
+        description: String(error),
+        type: "error",
+      });
+    }
+  };
+
+  const loadLlmCacheSize = async () => {
+    try {
+      setLlmCacheSize(await invoke<{ entries: number; bytes: number }>("llm_cache_size"));
+    } catch (error) {
+      console.error("Failed to get LLM cache size:", error);
+      setLlmCacheSize(null);
+    }
+  };
+
+  const clearLlmCache = async () => {
+    try {
+      const count = await invoke<number>("clear_llm_cache");
+      toaster.create({
+        title: "AIの応答キャッシュを削除しました",
+        description: `${count}件`,
+        type: "info",
+      });
+    } catch (error) {
+      toaster.create({
+        title: "AIの応答キャッシュの削除に失敗しました",
+        description: String(error),
+        type: "error",
+      });
+    }
+    await loadLlmCacheSize();
+  };
+
   const saveAiSettings = async () => {
-    store.userSettings.setGoogleAiApiKey(tempGoogleApiKey);
+    try {
+      // 入力された場合のみ更新する（空欄なら保存済みのキーを使い続ける）
+      if (tempGoogleApiKey.trim()) {
+        await invoke("set_api_key", {
+          providerId: "google",
+          apiKey: tempGoogleApiKey,
+        });
+      }
+      if (tempOpenAiApiKey.trim()) {
+        await invoke("set_api_key", {
+          providerId: "openAiCompatible",
+          apiKey: tempOpenAiApiKey,
+        });
+      }
+    } catch (error) {
+      toaster.create({
+        title: "APIキーの保存に失敗しました",
+        description: String(error),
+        type: "error",
+      });
+      return;
+    }
+    store.userSettings.setOpenAiCompatible(tempOpenAiBaseUrl, tempOpenAiModel);
     await store.userSettings.save();
     setShowAiConfigDialog(false);
     toaster.create({
@@ -235,6 +323,27 @@ export default function SettingsMenu() {
                 >
                   Google AI Studio
                 </Button>
+                <Button
+                  size="sm"
+                  variant={
+                    (snap.userSettings.aiProvider || "ollama") ===
+                    "openai-compatible"
+                      ? "solid"
+                      : "outline"
+                  }
+                  colorScheme={
+                    (snap.userSettings.aiProvider || "ollama") ===
+                    "openai-compatible"
+                      ? "blue"
+                      : "gray"
+                  }
+                  onClick={() => {
+                    store.userSettings.setAiProvider("openai-compatible");
+                    store.userSettings.save();
+                  }}
+                >
+                  OpenAI互換
+                </Button>
               </HStack>
             </Box>
 
@@ -245,15 +354,84 @@ export default function SettingsMenu() {
                 <Text fontSize="sm" fontWeight="bold" mb={2}>
                   Google AI Studio APIキー
                 </Text>
+                <HStack>
+                  <Input
+                    type="password"
+                    placeholder={
+                      storedApiKeys.google
+                        ? "保存済み（変更する場合のみ入力）"
+                        : "Google AI Studio APIキーを入力"
+                    }
+                    value={tempGoogleApiKey}
+                    onChange={(e) => setTempGoogleApiKey(e.target.value)}
+                    size="sm"
+                  />
+                  {storedApiKeys.google && (
+                    <Button
+                      size="sm"
+                      variant="outline"
+                      onClick={() => deleteApiKey("google")}
+                    >
+                      削除
+                    </Button>
+                  )}
+                </HStack>
+                <Text fontSize="xs" color="gray.600" mt={1}>
+                  Google AI StudioでAPIキーを取得してください。キーはOSの資格情報ストアに保存されます
+                </Text>
+              </Box>
+            )}
+
+            {/* OpenAI互換API設定 */}
+            {(snap.userSettings.aiProvider || "ollama") ===
+              "openai-compatible" && (
+              <Box mb={4}>
+                <Text fontSize="sm" fontWeight="bold" mb={2}>
+                  ベースURL
+                </Text>
                 <Input
-                  type="password"
-                  placeholder="Google AI Studio APIキーを入力"
-                  value={tempGoogleApiKey}
-                  onChange={(e) => setTempGoogleApiKey(e.target.value)}
+                  placeholder="http://127.0.0.1:8080/v1"
+                  value={tempOpenAiBaseUrl}
+                  onChange={(e) => setTempOpenAiBaseUrl(e.target.value)}
                   size="sm"
                 />
+                <Text fontSize="sm" fontWeight="bold" mt={3} mb={2}>
+                  モデル名
+                </Text>
+                <Input
+                  placeholder="モデル名を入力"
+                  value={tempOpenAiModel}
+                  onChange={(e) => setTempOpenAiModel(e.target.value)}
+                  size="sm"
+                />
+                <Text fontSize="sm" fontWeight="bold" mt={3} mb={2}>
+                  APIキー（任意）
+                </Text>
+                <HStack>
+                  <Input
+                    type="password"
+                    placeholder={
+                      storedApiKeys.openAiCompatible
+                        ? "保存済み（変更する場合のみ入力）"
+                        : "APIキーを入力"
+                    }
+                    value={tempOpenAiApiKey}
+                    onChange={(e) => setTempOpenAiApiKey(e.target.value)}
+                    size="sm"
+                  />
+                  {storedApiKeys.openAiCompatible && (
+                    <Button
+                      size="sm"
+                      variant="outline"
+                      onClick={() => deleteApiKey("openAiCompatible")}
+                    >
+                      削除
+                    </Button>
+                  )}
+                </HStack>
                 <Text fontSize="xs" color="gray.600" mt={1}>
-                  Google AI StudioでAPIキーを取得してください
+                  llama.cpp server、vLLM、LM Studioなどのchat
+                  completions互換APIに対応しています
                 </Text>
               </Box>
             )}
@@ -276,8 +454,24 @@ export default function SettingsMenu() {
                   <br />
                   初回実行時には約5GBのモデルダウンロードが必要です。
                 </Text>
+                <OllamaModels />
               </Box>
             )}
+            <HStack mt={4} justify="space-between">
+              <Text fontSize="xs" color="gray.600">
+                譜面生成で保存したAIの応答
+                {llmCacheSize &&
+                  `（${llmCacheSize.entries}件・${(llmCacheSize.bytes / 1024 ** 2).toFixed(1)}MB）`}
+              </Text>
+              <Button
+                size="xs"
+                variant="outline"
+                onClick={clearLlmCache}
+                disabled={llmCacheSize?.entries === 0}
+              >
+                キャッシュを削除
+              </Button>
+            </HStack>
           </DialogBody>
           <DialogFooter>
             <Button
@@ -0,0 +1,166 @@
+import { Button, Box, Text, Input, HStack, Spinner } from "@chakra-ui/react";
+import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { toaster } from "../../components/ui/toaster";
+
+// list_ollama_modelsの戻り値の1要素
+interface OllamaModel {
+  name: string;
+  size: number;
+  modifiedAt: string;
+  family: string;
+  parameterSize: string;
+  quantizationLevel: string;
+}
+
+// ollama_pull_progressイベントの内容
+interface OllamaPullProgress {
+  model: string;
+  status: string;
+  digest?: string;
+  total?: number;
+  completed?: number;
+}
+
+const formatSize = (bytes: number) => `${(bytes / 1024 ** 3).toFixed(1)}GB`;
+
+// ローカルのOllamaモデルの一覧・取得・削除
+export default function OllamaModels() {
+  const [models, setModels] = useState<OllamaModel[]>([]);
+  const [isLoading, setIsLoading] = useState(false);
+  const [loadError, setLoadError] = useState<string | null>(null);
+  const [pullModel, setPullModel] = useState("");
+  const [pullingModel, setPullingModel] = useState<string | null>(null);
+  const [pullProgress, setPullProgress] = useState<OllamaPullProgress | null>(null);
+
+  const loadModels = async () => {
+    setIsLoading(true);
+    try {
+      setModels(await invoke<OllamaModel[]>("list_ollama_models", { baseUrl: null }));
+      setLoadError(null);
+    } catch (error) {
+      console.error("Failed to list Ollama models:", error);
+      setLoadError(String(error));
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  useEffect(() => {
+    loadModels();
+  }, []);
+
+  const pull = async () => {
+    const model = pullModel.trim();
+    if (!model) {
+      return;
+    }
+
+    setPullingModel(model);
+    setPullProgress(null);
+    const unlisten = await listen<OllamaPullProgress>("ollama_pull_progress", (event) => {
+      if (event.payload.model === model) {
+        setPullProgress(event.payload);
+      }
+    });
+
+    try {
+      await invoke("pull_ollama_model", { baseUrl: null, model });
+      toaster.create({ title: "モデルを取得しました", description: model, type: "success" });
+      setPullModel("");
+      await loadModels();
+    } catch (error) {
+      if (String(error).includes("cancelled")) {
+        toaster.create({ title: "モデルの取得を中止しました", description: model, type: "info" });
+      } else {
+        toaster.create({ title: "モデルの取得に失敗しました", description: String(error), type: "error" });
+      }
+    } finally {
+      unlisten();
+      setPullingModel(null);
+      setPullProgress(null);
+    }
+  };
+
+  const cancelPull = async () => {
+    if (pullingModel) {
+      await invoke<boolean>("cancel_ollama_pull", { model: pullingModel });
+    }
+  };
+
+  const deleteModel = async (model: string) => {
+    try {
+      await invoke("delete_ollama_model", { baseUrl: null, model });
+      await loadModels();
+    } catch (error) {
+      toaster.create({ title: "モデルの削除に失敗しました", description: String(error), type: "error" });
+    }
+  };
+
+  const percent =
+    pullProgress?.total && pullProgress.completed !== undefined
+      ? Math.round((pullProgress.completed / pullProgress.total) * 100)
+      : null;
+
+  return (
+    <Box mt={3}>
+      <Text fontSize="sm" fontWeight="bold" mb={2}>
+        インストール済みのモデル
+      </Text>
+      {isLoading ? (
+        <Spinner size="sm" />
+      ) : loadError ? (
+        <Text fontSize="xs" color="red.500">
+          Ollamaに接続できません: {loadError}
+        </Text>
+      ) : models.length === 0 ? (
+        <Text fontSize="xs" color="gray.600">
+          モデルがありません
+        </Text>
+      ) : (
+        models.map((model) => (
+          <HStack key={model.name} justify="space-between" mb={1}>
+            <Text fontSize="xs">
+              {model.name}（{[model.parameterSize, model.quantizationLevel, formatSize(model.size)].filter(Boolean).join(", ")}）
+            </Text>
+            <Button size="xs" variant="outline" disabled={pullingModel !== null} onClick={() => deleteModel(model.name)}>
+              削除
+            </Button>
+          </HStack>
+        ))
+      )}
+
+      <HStack mt={3}>
+        <Input
+          size="sm"
+          placeholder="例: phi4:14b"
+          value={pullModel}
+          disabled={pullingModel !== null}
+          onChange={(e) => setPullModel(e.target.value)}
+        />
+        {pullingModel ? (
+          <Button size="sm" variant="outline" onClick={cancelPull}>
+            中止
+          </Button>
+        ) : (
+          <Button size="sm" onClick={pull} disabled={!pullModel.trim()}>
+            取得
+          </Button>
+        )}
+      </HStack>
+
+      {pullingModel && (
+        <Box mt={2}>
+          <Text fontSize="xs" color="gray.600" mb={1}>
+            {pullProgress?.status || "取得を開始しています..."}
+            {percent !== null && ` ${percent}%`}
+          </Text>
+          <Box w="100%" h="8px" bg="gray.200" borderRadius="md" overflow="hidden">
+            <Box h="100%" bg="blue.500" transition="width 0.3s ease" width={`${percent ?? 0}%`} />
+          </Box>
+        </Box>
+      )}
+    </Box>
+  );
+}
@@ -12,12 +12,14 @@ import {
   DialogActionTrigger 
 } from "../../components/ui/dialog";
 import { HiCog6Tooth } from "react-icons/hi2";
-import { HiTrash, HiClipboard, HiClipboardDocument } from "react-icons/hi2";
+import { HiTrash, HiClipboard, HiClipboardDocument, HiSparkles, HiChatBubbleLeftRight } from "react-icons/hi2";
 import { SingleNoteEvent, LongNoteEvent } from "../../store/noteEvent";
 import { SpeedChangeEvent } from "../../store/speedChangeEvent";
 import TemporalPosition from "../../store/temporalPosition";
 import ChartEventType from "../../store/chartEventType";
 import { writeText, readText } from "@tauri-apps/plugin-clipboard-manager";
+import RefineChartDialog from "./RefineChartDialog";
+import ReviewChartDialog from "./ReviewChartDialog";
 
 interface ChartTrackMenuProps {
   chartUuid: string;
@@ -27,6 +29,8 @@ export default function ChartTrackMenu({ chartUuid }: ChartTrackMenuProps) {
   const snap = useSnapshot(store);
   const [showDeleteDialog, setShowDeleteDialog] = useState(false);
   const [showPasteDialog, setShowPasteDialog] = useState(false);
+  const [showRefineDialog, setShowRefineDialog] = useState(false);
+  const [showReviewDialog, setShowReviewDialog] = useState(false);
   const [clipboardData, setClipboardData] = useState<any>(null);
 
   const chart = snap.project.charts.find(c => c.uuid === chartUuid);
@@ -172,6 +176,14 @@ export default function ChartTrackMenu({ chartUuid }: ChartTrackMenuProps) {
             <HiClipboardDocument size={16} />
             ペースト
           </MenuItem>
+          <MenuItem value="refine" onClick={() => setShowRefineDialog(true)}>
+            <HiSparkles size={16} />
+            AIで調整
+          </MenuItem>
+          <MenuItem value="review" onClick={() => setShowReviewDialog(true)}>
+            <HiChatBubbleLeftRight size={16} />
+            AIでレビュー
+          </MenuItem>
           <MenuItem value="delete" onClick={() => setShowDeleteDialog(true)} color="red.500">
             <HiTrash size={16} />
             削除
@@ -225,6 +237,9 @@ export default function ChartTrackMenu({ chartUuid }: ChartTrackMenuProps) {
           </DialogFooter>
         </DialogContent>
       </DialogRoot>
+
+      <RefineChartDialog chartUuid={chartUuid} open={showRefineDialog} onOpenChange={setShowRefineDialog} />
+      <ReviewChartDialog chartUuid={chartUuid} open={showReviewDialog} onOpenChange={setShowReviewDialog} />
     </>
   );
 }
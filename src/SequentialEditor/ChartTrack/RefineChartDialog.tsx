@@ -0,0 +1,178 @@
+import { Box, Button, Input, Spinner, Text, Textarea, VStack } from "@chakra-ui/react";
+import {
+  DialogRoot,
+  DialogContent,
+  DialogHeader,
+  DialogTitle,
+  DialogBody,
+  DialogFooter,
+  DialogCloseTrigger
+} from "../../components/ui/dialog";
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useSnapshot } from "valtio";
+import store from "../../store/store";
+import { SingleNoteEvent, LongNoteEvent } from "../../store/noteEvent";
+import ChartEventType from "../../store/chartEventType";
+import Lane from "../../store/lane";
+import TemporalPosition from "../../store/temporalPosition";
+import { toaster } from "../../components/ui/toaster";
+import { chatProvider, defaultModel } from "../../utils/chatProvider";
+
+// refine_chartが返すイベント（positionはナノ秒の文字列）
+type RefinedEvent =
+  | { type: "SingleNote"; uuid: string; position: string; lane: number }
+  | { type: "LongNote"; uuid: string; position: string; lane: number; endPosition: string };
+
+// refine_chartの戻り値
+interface ChartRefinement {
+  changes: { bar: number; added: RefinedEvent[]; removed: string[] }[];
+  comment: string;
+  usage: { promptTokens: number | null; completionTokens: number | null; latencyMs: number };
+}
+
+// ピアノの1オクターブの白鍵・黒鍵の並び（C C# D D# E F F# G G# A A# B）
+const PIANO_PATTERN: ("white" | "black")[] = ["white", "black", "white", "black", "white", "white", "black", "white", "black", "white", "black", "white"];
+
+const toNoteEvent = (e: RefinedEvent) =>
+  e.type === "LongNote" ?
+    new LongNoteEvent(e.uuid, TemporalPosition.fromJSON(e.position), e.lane as Lane, TemporalPosition.fromJSON(e.endPosition)) :
+    new SingleNoteEvent(e.uuid, TemporalPosition.fromJSON(e.position), e.lane as Lane);
+
+interface RefineChartDialogProps {
+  chartUuid: string;
+  open: boolean;
+  onOpenChange: (open: boolean) => void;
+}
+
+// AIとの対話で譜面を調整するダイアログ
+export default function RefineChartDialog({ chartUuid, open, onOpenChange }: RefineChartDialogProps) {
+  const snap = useSnapshot(store);
+  const [model, setModel] = useState("");
+  const [sessionId, setSessionId] = useState<string | null>(null);
+  const [instruction, setInstruction] = useState("");
+  const [history, setHistory] = useState<{ instruction: string; comment: string }[]>([]);
+  const [isRefining, setIsRefining] = useState(false);
+
+  const chart = snap.project.charts.find(c => c.uuid === chartUuid);
+
+  useEffect(() => {
+    setModel(defaultModel(snap.userSettings));
+  }, [snap.userSettings.aiProvider, snap.userSettings.openAiModel]);
+
+  // 閉じたらセッションを終了する
+  useEffect(() => {
+    if (open || !sessionId) return;
+    invoke<boolean>("end_chart_session", { sessionId }).catch((error) => {
+      console.error("Failed to end chart session:", error);
+    });
+    setSessionId(null);
+    setHistory([]);
+  }, [open, sessionId]);
+
+  // ノートのイベントのみ送る（positionはtoJSONでナノ秒の文字列になる）
+  const noteEvents = () => {
+    const chartStore = store.project.charts.find(c => c.uuid === chartUuid);
+    return (chartStore?.events ?? []).filter(e => e.type === ChartEventType.SingleNote || e.type === ChartEventType.LongNote);
+  };
+
+  const startSession = async () => {
+    if (!chart) throw new Error("譜面が見つかりません");
+    const keyCount = chart.laneNumber;
+    return await invoke<string>("start_chart_session", {
+      provider: chatProvider(snap.userSettings, model),
+      musicTempoList: snap.project.musicTempoList,
+      musicLength: snap.project.musicLength,
+      stemNotes: snap.project.stemNotes,
+      events: noteEvents(),
+      options: {
+        keyCount,
+        enabledKeys: Array(keyCount).fill(true),
+        allowSimultaneousWhiteBlack: true,
+        keyTypes: Array.from({ length: keyCount }, (_, i) => PIANO_PATTERN[i % 12]),
+        barsPerBatch: 1,
+        label: chart.label
+      }
+    });
+  };
+
+  const handleSend = async () => {
+    const text = instruction.trim();
+    if (!text || isRefining) return;
+    setIsRefining(true);
+    try {
+      const id = sessionId ?? await startSession();
+      setSessionId(id);
+      // エディタで編集されている場合に備えて、毎回現在の譜面を送る
+      const refinement = await invoke<ChartRefinement>("refine_chart", {
+        sessionId: id,
+        instruction: text,
+        events: noteEvents()
+      });
+
+      const chartStore = store.project.charts.find(c => c.uuid === chartUuid);
+      if (chartStore) {
+        const removed = new Set(refinement.changes.flatMap(change => change.removed));
+        chartStore.events = chartStore.events.filter(e => !removed.has(e.uuid));
+        chartStore.events.push(...refinement.changes.flatMap(change => change.added.map(toNoteEvent)));
+      }
+
+      setHistory(history => [...history, { instruction: text, comment: refinement.comment }]);
+      setInstruction("");
+      toaster.create({
+        title: "譜面を調整しました",
+        description: `${refinement.changes.length}小節を変更しました（${(refinement.usage.latencyMs / 1000).toFixed(1)}秒）`,
+        type: "success"
+      });
+    } catch (error) {
+      console.error("Failed to refine chart:", error);
+      toaster.create({
+        title: "譜面の調整に失敗しました",
+        description: String(error),
+        type: "error"
+      });
+    } finally {
+      setIsRefining(false);
+    }
+  };
+
+  return (
+    <DialogRoot open={open} onOpenChange={(details) => onOpenChange(details.open)}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>AIで譜面を調整「{chart?.label}」</DialogTitle>
+        </DialogHeader>
+        <DialogBody>
+          <VStack align="stretch" gap={3}>
+            <Box>
+              <Text fontSize="sm" mb={1}>モデル</Text>
+              <Input size="sm" value={model} onChange={(e) => setModel(e.target.value)} disabled={sessionId !== null} />
+            </Box>
+            {history.map((entry, index) => (
+              <Box key={index} p={2} borderWidth="1px" borderRadius="md">
+                <Text fontSize="sm" fontWeight="bold">{entry.instruction}</Text>
+                <Text fontSize="sm" color="gray.500">{entry.comment}</Text>
+              </Box>
+            ))}
+            <Textarea
+              value={instruction}
+              onChange={(e) => setInstruction(e.target.value)}
+              placeholder="例: 9～16小節をもっと薄くして / ボーカルに合わせてロングノートを増やして"
+              rows={3}
+              disabled={isRefining}
+            />
+          </VStack>
+        </DialogBody>
+        <DialogFooter>
+          <Button variant="outline" onClick={() => onOpenChange(false)} disabled={isRefining}>
+            閉じる
+          </Button>
+          <Button colorScheme="blue" onClick={handleSend} disabled={isRefining || !instruction.trim() || !model.trim()}>
+            {isRefining ? <Spinner size="sm" /> : "送信"}
+          </Button>
+        </DialogFooter>
+        <DialogCloseTrigger />
+      </DialogContent>
+    </DialogRoot>
+  );
+}
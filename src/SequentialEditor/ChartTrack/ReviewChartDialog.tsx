@@ -0,0 +1,136 @@
+import { Badge, Box, Button, HStack, Input, Spinner, Text, VStack } from "@chakra-ui/react";
+import {
+  DialogRoot,
+  DialogContent,
+  DialogHeader,
+  DialogTitle,
+  DialogBody,
+  DialogFooter,
+  DialogCloseTrigger
+} from "../../components/ui/dialog";
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useSnapshot } from "valtio";
+import store from "../../store/store";
+import ChartEventType from "../../store/chartEventType";
+import { toaster } from "../../components/ui/toaster";
+import { chatProvider, defaultModel } from "../../utils/chatProvider";
+
+// review_chartが返すコメント（bar～endBar、両端を含む）
+interface ReviewComment {
+  bar: number;
+  endBar: number;
+  category: "problem" | "difficultySpike" | "suggestion";
+  severity: "low" | "medium" | "high";
+  message: string;
+  fix: string;
+}
+
+// review_chartの戻り値
+interface ChartReview {
+  summary: string;
+  comments: ReviewComment[];
+  usage: { promptTokens: number | null; completionTokens: number | null; latencyMs: number };
+}
+
+const categoryLabels = {
+  problem: "問題",
+  difficultySpike: "難易度の急変",
+  suggestion: "提案",
+};
+
+const severityColors = { low: "gray", medium: "orange", high: "red" };
+
+interface ReviewChartDialogProps {
+  chartUuid: string;
+  open: boolean;
+  onOpenChange: (open: boolean) => void;
+}
+
+// AIに譜面をレビューしてもらうダイアログ
+export default function ReviewChartDialog({ chartUuid, open, onOpenChange }: ReviewChartDialogProps) {
+  const snap = useSnapshot(store);
+  const [model, setModel] = useState("");
+  const [review, setReview] = useState<ChartReview | null>(null);
+  const [isReviewing, setIsReviewing] = useState(false);
+
+  const chart = snap.project.charts.find(c => c.uuid === chartUuid);
+
+  useEffect(() => {
+    setModel(defaultModel(snap.userSettings));
+  }, [snap.userSettings.aiProvider, snap.userSettings.openAiModel]);
+
+  const handleReview = async () => {
+    const chartStore = store.project.charts.find(c => c.uuid === chartUuid);
+    if (!chartStore || isReviewing) return;
+    setIsReviewing(true);
+    try {
+      // ノートのイベントのみ送る（positionはtoJSONでナノ秒の文字列になる）
+      const result = await invoke<ChartReview>("review_chart", {
+        provider: chatProvider(snap.userSettings, model),
+        musicTempoList: snap.project.musicTempoList,
+        musicLength: snap.project.musicLength,
+        stemNotes: snap.project.stemNotes,
+        events: chartStore.events.filter(e => e.type === ChartEventType.SingleNote || e.type === ChartEventType.LongNote),
+        laneNumber: chartStore.laneNumber
+      });
+      setReview(result);
+    } catch (error) {
+      console.error("Failed to review chart:", error);
+      toaster.create({
+        title: "譜面のレビューに失敗しました",
+        description: String(error),
+        type: "error"
+      });
+    } finally {
+      setIsReviewing(false);
+    }
+  };
+
+  return (
+    <DialogRoot open={open} onOpenChange={(details) => onOpenChange(details.open)}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>AIで譜面をレビュー「{chart?.label}」</DialogTitle>
+        </DialogHeader>
+        <DialogBody>
+          <VStack align="stretch" gap={3}>
+            <Box>
+              <Text fontSize="sm" mb={1}>モデル</Text>
+              <Input size="sm" value={model} onChange={(e) => setModel(e.target.value)} disabled={isReviewing} />
+            </Box>
+            {review && (
+              <>
+                <Text fontSize="sm">{review.summary}</Text>
+                {review.comments.length === 0 && (
+                  <Text fontSize="sm" color="gray.500">指摘はありませんでした</Text>
+                )}
+                {review.comments.map((comment, index) => (
+                  <Box key={index} p={2} borderWidth="1px" borderRadius="md">
+                    <HStack mb={1}>
+                      <Text fontSize="sm" fontWeight="bold">
+                        {comment.bar === comment.endBar ? `小節 ${comment.bar}` : `小節 ${comment.bar}-${comment.endBar}`}
+                      </Text>
+                      <Badge colorPalette={severityColors[comment.severity]}>{categoryLabels[comment.category]}</Badge>
+                    </HStack>
+                    <Text fontSize="sm">{comment.message}</Text>
+                    <Text fontSize="sm" color="gray.500">修正案: {comment.fix}</Text>
+                  </Box>
+                ))}
+              </>
+            )}
+          </VStack>
+        </DialogBody>
+        <DialogFooter>
+          <Button variant="outline" onClick={() => onOpenChange(false)}>
+            閉じる
+          </Button>
+          <Button colorScheme="blue" onClick={handleReview} disabled={isReviewing || !model.trim()}>
+            {isReviewing ? <Spinner size="sm" /> : review ? "もう一度レビュー" : "レビュー"}
+          </Button>
+        </DialogFooter>
+        <DialogCloseTrigger />
+      </DialogContent>
+    </DialogRoot>
+  );
+}
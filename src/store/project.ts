@@ -11,6 +11,13 @@ import { SpeedChangeEvent } from "./speedChangeEvent";
 import store from "./store";
 import { secondsToNanosecondsBigInt, safeBigInt } from '../utils/bigintHelpers';
 
+// ドラムの種類（drum_hitsで分類される）
+export type DrumKind = "kick" | "snare" | "hihat" | "tom" | "cymbal";
+
+// ステムから検出されたノート
+// （bass/vocalsはtrack_pitchによる音高区間で、end・confidenceを持つ）
+export type StemNote = { pitch: number; velocity: number; time: number; kind?: DrumKind; end?: number; confidence?: number };
+
 // プロジェクトごとのキャッシュを外部で管理
 const snappingPositionsCache = new WeakMap<Project, {
   positions: TemporalPosition[];
@@ -33,10 +40,10 @@ export default class Project {
     vocals: string
   };
   stemNotes: {
-    bass: { pitch: number; velocity: number; time: number }[],
-    drums: { pitch: number; velocity: number; time: number }[],
-    other: { pitch: number; velocity: number; time: number }[],
-    vocals: { pitch: number; velocity: number; time: number }[],
+    bass: StemNote[],
+    drums: StemNote[],
+    other: StemNote[],
+    vocals: StemNote[],
   };
 
   constructor(music: string, name: string, charts: Chart[], musicTempoList: TempoEvent[]) {
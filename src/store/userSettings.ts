@@ -1,19 +1,25 @@
 import * as path from "@tauri-apps/api/path";
 import { readTextFile, writeTextFile } from "@tauri-apps/plugin-fs";
+import { invoke } from "@tauri-apps/api/core";
+
+export type AiProvider = "ollama" | "google-ai-studio" | "openai-compatible";
 
 export default class UserSettings {
   background: string;
   backgroundBlur: boolean;
   headerBlur: boolean;
-  aiProvider: "ollama" | "google-ai-studio";
-  googleAiApiKey: string;
+  aiProvider: AiProvider;
+  // APIキーはバックエンド（OSのキーリング）に保存し、ここには持たない
+  openAiBaseUrl: string;
+  openAiModel: string;
 
   constructor() {
     this.background = "";
     this.backgroundBlur = false;
     this.headerBlur = false;
     this.aiProvider = "ollama";
-    this.googleAiApiKey = "";
+    this.openAiBaseUrl = "";
+    this.openAiModel = "";
   }
 
   setBackground(background: string): void {
@@ -32,20 +38,17 @@ export default class UserSettings {
     return this.backgroundBlur;
   }
 
-  setAiProvider(provider: "ollama" | "google-ai-studio"): void {
+  setAiProvider(provider: AiProvider): void {
     this.aiProvider = provider;
   }
 
-  getAiProvider(): "ollama" | "google-ai-studio" {
+  getAiProvider(): AiProvider {
     return this.aiProvider;
   }
 
-  setGoogleAiApiKey(apiKey: string): void {
-    this.googleAiApiKey = apiKey;
-  }
-
-  getGoogleAiApiKey(): string {
-    return this.googleAiApiKey;
+  setOpenAiCompatible(baseUrl: string, model: string): void {
+    this.openAiBaseUrl = baseUrl;
+    this.openAiModel = model;
   }
 
   static createDefault(): UserSettings {
@@ -58,7 +61,8 @@ export default class UserSettings {
       backgroundBlur: this.backgroundBlur,
       headerBlur: this.headerBlur,
       aiProvider: this.aiProvider,
-      googleAiApiKey: this.googleAiApiKey,
+      openAiBaseUrl: this.openAiBaseUrl,
+      openAiModel: this.openAiModel,
     });
   }
 
@@ -83,7 +87,20 @@ export default class UserSettings {
       settings.backgroundBlur = json.backgroundBlur || false;
       settings.headerBlur = json.headerBlur || false;
       settings.aiProvider = json.aiProvider || "ollama";
-      settings.googleAiApiKey = json.googleAiApiKey || "";
+      settings.openAiBaseUrl = json.openAiBaseUrl || "";
+      settings.openAiModel = json.openAiModel || "";
+
+      // 以前のバージョンで平文保存していたAPIキーをバックエンドに移し、ファイルから消す
+      const legacyApiKeys = [
+        { providerId: "google", apiKey: json.googleAiApiKey },
+        { providerId: "openAiCompatible", apiKey: json.openAiApiKey },
+      ].filter((k) => k.apiKey);
+      if (legacyApiKeys.length > 0) {
+        for (const { providerId, apiKey } of legacyApiKeys) {
+          await invoke("set_api_key", { providerId, apiKey });
+        }
+        await settings.save();
+      }
       return settings;
     } catch (error) {
       console.error("Failed to load user settings:", error);
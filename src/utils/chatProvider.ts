@@ -0,0 +1,44 @@
+import { AiProvider } from "../store/userSettings";
+
+/**
+ * 譜面の調整・レビューで使うプロバイダーの指定のためのユーティリティ関数
+ */
+
+interface ProviderSettings {
+  readonly aiProvider: AiProvider;
+  readonly openAiBaseUrl: string;
+  readonly openAiModel: string;
+}
+
+/**
+ * プロバイダーごとの既定のモデル名
+ * @param settings ユーザー設定
+ * @returns モデル名
+ */
+export function defaultModel(settings: ProviderSettings): string {
+  switch (settings.aiProvider || "ollama") {
+    case "google-ai-studio":
+      return "gemini-2.5-flash";
+    case "openai-compatible":
+      return settings.openAiModel;
+    default:
+      return "phi4:14b";
+  }
+}
+
+/**
+ * 設定中のプロバイダーをchatコマンド用の形式にする（APIキーはバックエンドが保存済みのものを使う）
+ * @param settings ユーザー設定
+ * @param model モデル名
+ * @returns Rust側のChatProvider
+ */
+export function chatProvider(settings: ProviderSettings, model: string) {
+  switch (settings.aiProvider || "ollama") {
+    case "google-ai-studio":
+      return { type: "google", model: model.trim() };
+    case "openai-compatible":
+      return { type: "openAiCompatible", baseUrl: settings.openAiBaseUrl.trim(), model: model.trim() };
+    default:
+      return { type: "ollama", model: model.trim() };
+  }
+}